@@ -0,0 +1,26 @@
+use std::{fs, path::Path, process::Command};
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=CSFM_GIT_COMMIT={}", commit);
+    }
+
+    let lock = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.lock");
+    let iced_version = fs::read_to_string(&lock).ok().and_then(|data| {
+        let start = data.find("name = \"iced\"\n")?;
+        let line = data[start..].lines().nth(1)?;
+        line.strip_prefix("version = \"").map(|v| v.trim_end_matches('"').to_string())
+    });
+
+    println!("cargo:rustc-env=CSFM_ICED_VERSION={}", iced_version.unwrap_or_else(|| "unknown".to_string()));
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+}
@@ -0,0 +1,16 @@
+//! The file operations behind csfm, without any GUI: listing and walking directories,
+//! copying, moving, renaming and deleting, the trash and the operation log.
+//!
+//! Nothing here asks the user anything. Conflicts come back as values for the caller to
+//! resolve, and long operations report each finished item through [`transfer::Progress`].
+
+pub mod listing;
+pub mod mounts;
+pub mod ops;
+pub mod oplog;
+pub mod paths;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod transfer;
+pub mod trash;
+pub mod uri;
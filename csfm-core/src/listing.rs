@@ -0,0 +1,453 @@
+//! Reading directories: the entries of one folder, sorted for display, and recursive walks
+//! below a folder.
+
+use std::{
+    ffi::OsStr,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/// What the file list is ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortMode {
+    #[default]
+    Name,
+    Size,
+    Modified,
+    Type,
+}
+
+impl SortMode {
+    pub const ALL: [SortMode; 4] = [SortMode::Name, SortMode::Size, SortMode::Modified, SortMode::Type];
+}
+
+impl std::fmt::Display for SortMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SortMode::Name => "Name",
+            SortMode::Size => "Size",
+            SortMode::Modified => "Modified",
+            SortMode::Type => "Type",
+        })
+    }
+}
+
+/// The entries of one directory
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    pub files: Vec<FileEntry>,
+    /// Entries left out because hidden files aren't shown
+    pub hidden: usize,
+    /// What `files` is sorted by, None while they are in the order they were read
+    pub order: Option<SortOrder>
+}
+
+/// An entry of a listing or of walk results.
+#[derive(Debug, Clone)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// Directory symlink whose target lies outside the listed directory
+    pub leaves_tree: bool,
+    pub is_symlink: bool,
+    /// None when the entry couldn't be stat'ed, e.g. in a directory without search permission
+    pub size: Option<u64>,
+    pub modified: Option<std::time::SystemTime>,
+    pub flags: InodeFlags,
+    /// The name in NFC and lower case, worked out once for filtering
+    pub folded_name: String,
+    pub sort_keys: SortKeys
+}
+
+/// What entries are sorted by, worked out once when an entry is listed rather than on every
+/// comparison.
+#[derive(Debug, Clone)]
+pub struct SortKeys {
+    /// Names that only differ in case, leading zeros or their Unicode normalization compare
+    /// equal in natural order, so the later parts keep their order stable.
+    name: (Vec<NameChunk>, Vec<usize>, String, Vec<u8>),
+    /// Lower case
+    extension: Option<String>
+}
+
+impl SortKeys {
+    pub fn of(path: &Path) -> Self {
+        let name = path.file_name().unwrap_or_default();
+        let normalized = normalized_name(name);
+        SortKeys {
+            name: (natural_key(&normalized), leading_zeros(&normalized), normalized, name.as_bytes().to_vec()),
+            extension: path.extension().map(|e| e.to_string_lossy().to_lowercase()),
+        }
+    }
+}
+
+/// The ext2-style inode attributes csfm cares about, see chattr(1).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct InodeFlags {
+    pub immutable: bool,
+    pub append_only: bool
+}
+
+impl InodeFlags {
+    /// Reads the attributes of `path` itself, not following symlinks. Filesystems that don't
+    /// report them through statx show none.
+    pub fn read(path: &Path) -> Self {
+        let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+            return InodeFlags::default();
+        };
+        let mut stx: libc::statx = unsafe { std::mem::zeroed() };
+        // SAFETY: the path is NUL-terminated and stx is a valid statx to fill in
+        if unsafe { libc::statx(libc::AT_FDCWD, c_path.as_ptr(), libc::AT_SYMLINK_NOFOLLOW, 0, &mut stx) } != 0 {
+            return InodeFlags::default();
+        }
+
+        let attributes = stx.stx_attributes & stx.stx_attributes_mask;
+        InodeFlags {
+            immutable: attributes & libc::STATX_ATTR_IMMUTABLE as u64 != 0,
+            append_only: attributes & libc::STATX_ATTR_APPEND as u64 != 0,
+        }
+    }
+
+    pub fn locked(&self) -> bool {
+        self.immutable || self.append_only
+    }
+
+    pub fn describe(&self) -> Option<&'static str> {
+        match (self.immutable, self.append_only) {
+            (true, _) => Some("marked immutable (chattr +i)"),
+            (false, true) => Some("marked append-only (chattr +a)"),
+            _ => None,
+        }
+    }
+}
+
+/// Limits shared by every recursive walk over the filesystem.
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    pub max_depth: usize,
+    /// Descend into symlinked directories. Cycles are still caught by the visited set.
+    pub follow_symlinks: bool,
+    pub show_hidden_files: bool
+}
+
+/// What a walk skipped, so callers can say so instead of silently showing partial results.
+#[derive(Debug, Clone, Default)]
+pub struct WalkSummary {
+    /// Directories that weren't entered because they are at the depth limit
+    pub depth_limited: Vec<PathBuf>,
+    /// Directories seen a second time through a symlink or bind mount
+    pub cycles: usize,
+    /// The visitor asked to stop before the walk was done
+    pub stopped: bool,
+    /// Hidden entries that were neither reported nor entered
+    pub hidden: usize
+}
+
+/// Walks `root` depth-first and calls `visit` with every entry below it and its
+/// metadata. Symlinks are reported as themselves unless following is enabled.
+/// Directories are identified by (st_dev, st_ino) so each is entered at most once.
+/// Returning false from `visit` stops the walk.
+pub fn walk(root: &Path, options: &WalkOptions, mut visit: impl FnMut(&Path, &fs::Metadata) -> bool) -> WalkSummary {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut summary = WalkSummary::default();
+    let mut visited = std::collections::HashSet::new();
+    if let Ok(metadata) = fs::metadata(root) {
+        visited.insert((metadata.dev(), metadata.ino()));
+    }
+    let mut pending = vec![(root.to_path_buf(), 0)];
+
+    while let Some((dir, depth)) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            if !options.show_hidden_files && entry.file_name().as_bytes().starts_with(b".") {
+                summary.hidden += 1;
+                continue;
+            }
+
+            let p = entry.path();
+            let Ok(mut metadata) = fs::symlink_metadata(&p) else {
+                continue;
+            };
+            if metadata.file_type().is_symlink()
+                && options.follow_symlinks
+                && let Ok(target) = fs::metadata(&p)
+            {
+                metadata = target;
+            }
+
+            if !visit(&p, &metadata) {
+                summary.stopped = true;
+                return summary;
+            }
+
+            if metadata.is_dir() {
+                if !visited.insert((metadata.dev(), metadata.ino())) {
+                    summary.cycles += 1;
+                } else if depth >= options.max_depth {
+                    summary.depth_limited.push(p);
+                } else {
+                    pending.push((p, depth + 1));
+                }
+            }
+        }
+    }
+
+    summary
+}
+
+/// Whether `link` is a symlink resolving to somewhere outside `tree`. Resolution is
+/// bounded by the kernel's symlink hop limit, so loops just count as not leaving.
+fn link_leaves_tree(link: &Path, tree: &Path) -> bool {
+    let is_link = fs::symlink_metadata(link).is_ok_and(|m| m.file_type().is_symlink());
+    if !is_link {
+        return false;
+    }
+
+    match fs::canonicalize(link) {
+        Ok(target) => !target.starts_with(tree),
+        Err(_) => false,
+    }
+}
+
+pub fn get_files(path: PathBuf, show_hidden_files: bool) -> io::Result<Listing> {
+    let mut files_and_dirs = vec![];
+    let mut hidden = 0;
+    let tree = fs::canonicalize(&path).unwrap_or(path.clone());
+
+    let entries = fs::read_dir(&path)?;
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        if !show_hidden_files && file_name.starts_with('.') {
+            hidden += 1;
+            continue;
+        }
+
+        let p = entry.path();
+        let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+        // Follows symlinks, falling back to the link itself when it is dangling
+        let Ok(metadata) = fs::metadata(&p).or_else(|_| fs::symlink_metadata(&p)) else {
+            // Still listed, the name is known even when nothing else is
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let (folded_name, sort_keys) = (folded_name(&p), SortKeys::of(&p));
+            files_and_dirs.push(FileEntry { path: p, is_dir, leaves_tree: false, is_symlink, size: None, modified: None, flags: InodeFlags::default(), folded_name, sort_keys });
+            continue;
+        };
+        let is_dir = metadata.is_dir();
+        let leaves_tree = is_dir && link_leaves_tree(&p, &tree);
+
+        let flags = InodeFlags::read(&p);
+        let (folded_name, sort_keys) = (folded_name(&p), SortKeys::of(&p));
+        files_and_dirs.push(FileEntry { path: p, is_dir, leaves_tree, is_symlink, size: Some(metadata.len()), modified: metadata.modified().ok(), flags, folded_name, sort_keys });
+    }
+
+    Ok(Listing { files: files_and_dirs, hidden, order: None })
+}
+
+pub fn sort_by_name(files: &mut [FileEntry]) {
+    files.sort_by(|a, b| a.sort_keys.name.cmp(&b.sort_keys.name));
+}
+
+/// Part of a name for natural ordering, a run of digits or of anything else.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum NameChunk {
+    /// Digits without leading zeros, compared by length first so `10` sorts after `2`
+    Number(usize, String),
+    /// Lowercase, so case doesn't matter
+    Text(String),
+}
+
+/// Sort key that orders `file2` before `file10` and ignores case.
+fn natural_key(name: &str) -> Vec<NameChunk> {
+    let mut chunks = vec![];
+    let mut rest = name;
+    while let Some(first) = rest.chars().next() {
+        let is_digit = first.is_ascii_digit();
+        let end = rest.find(|c: char| c.is_ascii_digit() != is_digit).unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(end);
+        chunks.push(if is_digit {
+            let digits = run.trim_start_matches('0');
+            NameChunk::Number(digits.len(), digits.to_string())
+        } else {
+            NameChunk::Text(run.to_lowercase())
+        });
+        rest = tail;
+    }
+
+    chunks
+}
+
+/// Leading zeros of each number in `name`, so `img7` sorts before `img007`.
+fn leading_zeros(name: &str) -> Vec<usize> {
+    name.split(|c: char| !c.is_ascii_digit())
+        .filter(|run| !run.is_empty())
+        .map(|run| run.len() - run.trim_start_matches('0').len())
+        .collect()
+}
+
+/// Orders `files` in `order`, directories first unless `mix_folders` is set.
+/// Ties keep alphabetic order.
+pub fn sort_files(files: &mut [FileEntry], order: SortOrder) {
+    sort_by_name(files);
+
+    let descending = order.descending;
+    match order.mode {
+        SortMode::Name if descending => files.reverse(),
+        SortMode::Name => {}
+        // The sorts are stable, so entries that tie stay in name order
+        SortMode::Size => files.sort_by(|a, b| directed(a.size.cmp(&b.size), descending)),
+        SortMode::Modified => files.sort_by(|a, b| directed(a.modified.cmp(&b.modified), descending)),
+        SortMode::Type => files.sort_by(|a, b| directed(a.sort_keys.extension.cmp(&b.sort_keys.extension), descending)),
+    }
+
+    if !order.mix_folders {
+        files.sort_by_key(|f| !f.is_dir);
+    }
+}
+
+/// The order a listing is shown in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortOrder {
+    pub mode: SortMode,
+    pub descending: bool,
+    pub mix_folders: bool
+}
+
+impl Listing {
+    /// Sorts the listing in `order`, for the background tasks that read directories.
+    pub fn sorted(mut self, order: SortOrder) -> Self {
+        sort_files(&mut self.files, order);
+        self.order = Some(order);
+        self
+    }
+
+    /// Sorts the listing unless it already is in `order`.
+    pub fn sort(&mut self, order: SortOrder) {
+        if self.order != Some(order) {
+            sort_files(&mut self.files, order);
+            self.order = Some(order);
+        }
+    }
+}
+
+fn directed(ordering: std::cmp::Ordering, descending: bool) -> std::cmp::Ordering {
+    if descending { ordering.reverse() } else { ordering }
+}
+
+/// A file name in NFC, for comparing names that may have been written in NFD (e.g. on macOS).
+/// Only for matching and sorting; filesystem calls always use the original bytes.
+pub fn normalized_name(name: &OsStr) -> String {
+    name.to_string_lossy().nfc().collect()
+}
+
+/// The normalized name of `path` in lower case, which the filter and search compare against.
+pub fn folded_name(path: &Path) -> String {
+    normalized_name(path.file_name().unwrap_or_default()).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScratchDir;
+
+    fn sorted_names(names: &[&str]) -> Vec<String> {
+        let mut files: Vec<FileEntry> = names
+            .iter()
+            .map(|name| {
+                let path = PathBuf::from(name);
+                FileEntry {
+                    folded_name: folded_name(&path),
+                    sort_keys: SortKeys::of(&path),
+                    path,
+                    is_dir: false,
+                    leaves_tree: false,
+                    is_symlink: false,
+                    size: Some(0),
+                    modified: None,
+                    flags: InodeFlags::default(),
+                }
+            })
+            .collect();
+        sort_by_name(&mut files);
+        files.iter().map(|f| f.path.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn natural_order_compares_digit_runs_as_numbers() {
+        assert_eq!(sorted_names(&["file10", "file2", "File1", "file1b", "file1a"]), ["File1", "file1a", "file1b", "file2", "file10"]);
+        assert_eq!(sorted_names(&["v1.10.0", "v1.9.2", "v1.9.10"]), ["v1.9.2", "v1.9.10", "v1.10.0"]);
+        assert_eq!(
+            natural_key("Track07 of 12"),
+            [NameChunk::Text("track".into()), NameChunk::Number(1, "7".into()), NameChunk::Text(" of ".into()), NameChunk::Number(2, "12".into())]
+        );
+    }
+
+    #[test]
+    fn natural_order_breaks_ties_between_leading_zeros() {
+        assert_eq!(natural_key("img007"), natural_key("img7"));
+        assert_eq!(sorted_names(&["img007", "img8", "img7", "img07"]), ["img7", "img07", "img007", "img8"]);
+        assert_eq!(sorted_names(&["a0", "a00", "a"]), ["a", "a0", "a00"]);
+    }
+
+    #[test]
+    fn natural_order_of_pure_numbers() {
+        assert_eq!(sorted_names(&["100", "20", "3", "0", "003"]), ["0", "3", "003", "20", "100"]);
+        // Longer than any integer type
+        assert_eq!(sorted_names(&["123456789012345678901234567890", "99999999999999999999"]), ["99999999999999999999", "123456789012345678901234567890"]);
+    }
+
+    #[test]
+    fn natural_order_of_accented_names() {
+        // The same name composed and decomposed, and in either case, sorts together
+        assert_eq!(natural_key(&normalized_name(OsStr::new("Cafe\u{301}"))), natural_key(&normalized_name(OsStr::new("café"))));
+        assert_eq!(natural_key("ÉTÉ2"), natural_key("été2"));
+        assert_eq!(sorted_names(&["été10", "Été2", "ete1"]), ["ete1", "Été2", "été10"]);
+        // Identical keys still order the same way every time
+        assert_eq!(sorted_names(&["Cafe\u{301}", "café"]), sorted_names(&["café", "Cafe\u{301}"]));
+    }
+
+    fn walk_options(max_depth: usize, follow_symlinks: bool) -> WalkOptions {
+        WalkOptions { max_depth, follow_symlinks, show_hidden_files: true }
+    }
+
+    #[test]
+    fn walks_end_on_symlink_loops() {
+        let dir = ScratchDir::new("walk-loops");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/b/file"), "").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("a/b/up")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("a/root")).unwrap();
+
+        // Not followed, the links are plain entries
+        let mut seen = 0;
+        let summary = walk(&dir, &walk_options(32, false), |_, _| {
+            seen += 1;
+            true
+        });
+        assert_eq!((seen, summary.cycles), (5, 0));
+
+        // Followed, each directory is still entered once
+        let mut seen = 0;
+        let summary = walk(&dir, &walk_options(32, true), |_, _| {
+            seen += 1;
+            true
+        });
+        assert_eq!((seen, summary.cycles), (5, 2));
+        assert!(summary.depth_limited.is_empty());
+    }
+}
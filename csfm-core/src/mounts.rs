@@ -0,0 +1,112 @@
+//! Mounted filesystems, from `/proc/self/mountinfo`.
+
+use std::{
+    ffi::OsString,
+    fs,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+};
+
+/// Kernel and memory filesystems, which are never where the user's files live.
+const VIRTUAL_FS_TYPES: &[&str] = &[
+    "autofs", "binfmt_misc", "bpf", "cgroup", "cgroup2", "configfs", "debugfs", "devpts", "devtmpfs", "efivarfs",
+    "fusectl", "hugetlbfs", "mqueue", "nsfs", "proc", "pstore", "ramfs", "securityfs", "sysfs", "tmpfs", "tracefs",
+];
+
+pub struct Mount {
+    pub mount_point: PathBuf,
+    /// What is mounted, e.g. `/dev/sdb1` or `server:/export`
+    pub source: String,
+}
+
+/// The innermost mount holding `path`, leaving out the root filesystem, virtual filesystems
+/// and mounts directly below the root such as `/home` or `/boot`.
+pub fn containing(path: &Path) -> Option<Mount> {
+    let info = fs::read_to_string("/proc/self/mountinfo").ok()?;
+
+    info.lines()
+        .filter_map(parse_line)
+        .filter(|m| m.mount_point.components().count() > 2 && path.starts_with(&m.mount_point))
+        .max_by_key(|m| m.mount_point.components().count())
+}
+
+/// The volume label of `mount`, or the name of its mount point when it has none.
+pub fn label(mount: &Mount) -> String {
+    let device = fs::canonicalize(&mount.source).ok();
+    let by_label = fs::read_dir("/dev/disk/by-label").into_iter().flatten().flatten().find(|e| {
+        device.is_some() && fs::canonicalize(e.path()).ok() == device
+    });
+
+    match by_label {
+        Some(entry) => unescape(&entry.file_name().to_string_lossy()).to_string_lossy().into_owned(),
+        None => mount.mount_point.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+    }
+}
+
+// "36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue", see proc(5)
+fn parse_line(line: &str) -> Option<Mount> {
+    let (mount, filesystem) = line.split_once(" - ")?;
+    let mount_point = mount.split(' ').nth(4)?;
+    let mut filesystem = filesystem.split(' ');
+    let fs_type = filesystem.next()?;
+    let source = filesystem.next()?;
+    if VIRTUAL_FS_TYPES.contains(&fs_type) {
+        return None;
+    }
+
+    Some(Mount { mount_point: PathBuf::from(unescape(mount_point)), source: unescape(source).to_string_lossy().into_owned() })
+}
+
+/// Undoes the escapes in mountinfo fields (`\040` for a space) and in udev's link names
+/// (`\x20`).
+fn unescape(field: &str) -> OsString {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let digits = |range: std::ops::Range<usize>| bytes.get(range).and_then(|d| std::str::from_utf8(d).ok());
+        let escaped = match bytes.get(i + 1) {
+            _ if bytes[i] != b'\\' => None,
+            Some(b'x') => digits(i + 2..i + 4).and_then(|d| u8::from_str_radix(d, 16).ok()),
+            _ => digits(i + 1..i + 4).and_then(|d| u8::from_str_radix(d, 8).ok()),
+        };
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    OsString::from_vec(out)
+}
+
+/// Whether `path` is on a network mount according to /proc/self/mounts, where speculative
+/// listing costs round trips and may wake up a sleeping server.
+pub fn on_network_filesystem(path: &Path) -> bool {
+    const NETWORK_TYPES: &[&str] = &[
+        "nfs", "nfs4", "cifs", "smb3", "smbfs", "9p", "afs", "ceph", "glusterfs",
+        "fuse.sshfs", "fuse.rclone", "fuse.davfs2", "fuse.gvfsd-fuse",
+    ];
+
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+        return false;
+    };
+
+    // The longest mount point containing the path is the one it lives on
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            Some((PathBuf::from(mount_point), fs_type.to_string()))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.as_os_str().len())
+        .is_some_and(|(_, fs_type)| NETWORK_TYPES.contains(&fs_type.as_str()))
+}
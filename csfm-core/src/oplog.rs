@@ -0,0 +1,209 @@
+//! An append-only record of the file operations csfm carried out, kept as JSON lines so
+//! a moved file can still be found weeks later. Writing is best effort: a failure to log
+//! never fails the operation itself.
+
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+
+/// Size at which the log is moved to `operations.log.1`, replacing the older one.
+const ROTATE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Created,
+    Copied,
+    Moved,
+    Renamed,
+    Trashed,
+    Restored,
+    Deleted,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Action::Created => "Created",
+            Action::Copied => "Copied",
+            Action::Moved => "Moved",
+            Action::Renamed => "Renamed",
+            Action::Trashed => "Trashed",
+            Action::Restored => "Restored",
+            Action::Deleted => "Deleted",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "LogLine", from = "LogLine")]
+pub struct Operation {
+    /// Seconds since the Unix epoch
+    pub time: i64,
+    pub action: Action,
+    pub source: PathBuf,
+    /// Where the result ended up, for copies and moves
+    pub dest: Option<PathBuf>,
+}
+
+/// How an operation is written to the log. JSON strings must be UTF-8, which paths on Linux
+/// needn't be, so those are written as an array of their bytes instead.
+#[derive(Serialize, Deserialize)]
+struct LogLine {
+    time: i64,
+    action: Action,
+    source: LoggedPath,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dest: Option<LoggedPath>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LoggedPath {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<&Path> for LoggedPath {
+    fn from(path: &Path) -> Self {
+        match path.to_str() {
+            Some(text) => LoggedPath::Text(text.to_string()),
+            None => LoggedPath::Bytes(path.as_os_str().as_bytes().to_vec()),
+        }
+    }
+}
+
+impl From<LoggedPath> for PathBuf {
+    fn from(path: LoggedPath) -> Self {
+        match path {
+            LoggedPath::Text(text) => PathBuf::from(text),
+            LoggedPath::Bytes(bytes) => PathBuf::from(OsString::from_vec(bytes)),
+        }
+    }
+}
+
+impl From<Operation> for LogLine {
+    fn from(operation: Operation) -> Self {
+        LogLine {
+            time: operation.time,
+            action: operation.action,
+            source: operation.source.as_path().into(),
+            dest: operation.dest.as_deref().map(LoggedPath::from),
+        }
+    }
+}
+
+impl From<LogLine> for Operation {
+    fn from(line: LogLine) -> Self {
+        Operation { time: line.time, action: line.action, source: line.source.into(), dest: line.dest.map(PathBuf::from) }
+    }
+}
+
+impl Operation {
+    pub fn now(action: Action, source: &Path, dest: Option<&Path>) -> Self {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+        Operation { time, action, source: source.to_path_buf(), dest: dest.map(Path::to_path_buf) }
+    }
+
+    /// The path to show the user the result at: the destination, or where a deleted item was.
+    pub fn result_path(&self) -> &Path {
+        self.dest.as_deref().unwrap_or(&self.source)
+    }
+}
+
+pub fn log_path() -> PathBuf {
+    paths::state_home().join("csfm/operations.log")
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_os_string();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Appends `operations` to the log, reporting but otherwise ignoring any failure.
+pub fn record(operations: &[Operation]) {
+    if operations.is_empty() {
+        return;
+    }
+    if let Err(e) = append(&log_path(), operations) {
+        eprintln!("Failed to write the operation log: {}", e);
+    }
+}
+
+/// Like `record`, on a thread of its own so the window never waits for the log to be written.
+pub fn record_in_background(operations: Vec<Operation>) {
+    if operations.is_empty() {
+        return;
+    }
+    std::thread::spawn(move || record(&operations));
+}
+
+fn append(path: &Path, operations: &[Operation]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::metadata(path).is_ok_and(|m| m.len() >= ROTATE_SIZE) {
+        fs::rename(path, rotated_path(path))?;
+    }
+
+    let mut data = String::new();
+    for operation in operations {
+        data += &serde_json::to_string(operation).map_err(io::Error::other)?;
+        data.push('\n');
+    }
+    // A single write keeps lines from concurrent windows whole
+    fs::OpenOptions::new().create(true).append(true).open(path)?.write_all(data.as_bytes())
+}
+
+/// Every logged operation, oldest first. Lines that don't parse are skipped.
+pub fn read() -> Vec<Operation> {
+    let path = log_path();
+    [rotated_path(&path), path]
+        .iter()
+        .filter_map(|p| fs::read_to_string(p).ok())
+        .flat_map(|data| data.lines().filter_map(|line| serde_json::from_str(line).ok()).collect::<Vec<_>>())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScratchDir;
+
+    #[test]
+    fn non_utf8_paths_round_trip() {
+        let dir = ScratchDir::new("non-utf8");
+        let log = dir.join("operations.log");
+        let source = PathBuf::from(OsString::from_vec(b"/tmp/caf\xe9.txt".to_vec()));
+        let dest = PathBuf::from("/tmp/café.txt");
+        let operations = [
+            Operation::now(Action::Moved, &source, Some(&dest)),
+            Operation::now(Action::Deleted, &dest, None),
+        ];
+
+        append(&log, &operations).unwrap();
+        let read: Vec<Operation> =
+            fs::read_to_string(&log).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(read, operations);
+    }
+
+    #[test]
+    fn reads_lines_written_with_plain_strings() {
+        let line = r#"{"time":1,"action":"copied","source":"/a","dest":"/b"}"#;
+        let operation: Operation = serde_json::from_str(line).unwrap();
+
+        assert_eq!(operation.source, PathBuf::from("/a"));
+        assert_eq!(operation.dest, Some(PathBuf::from("/b")));
+    }
+}
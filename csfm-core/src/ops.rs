@@ -0,0 +1,473 @@
+//! Changes to the filesystem: creating, renaming, copying and deleting items, and writing
+//! files without leaving them half written.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::listing::InodeFlags;
+
+/// Explains a failed operation on `path`. A bare "Operation not permitted" is confusing when
+/// the real reason is an immutable or append-only attribute on the file or its directory.
+pub fn describe_io_error(path: &Path, e: &io::Error) -> String {
+    if e.kind() == io::ErrorKind::PermissionDenied {
+        if let Some(reason) = InodeFlags::read(path).describe() {
+            return format!("{} is {}", path.display(), reason);
+        }
+        if let Some(parent) = path.parent()
+            && let Some(reason) = InodeFlags::read(parent).describe()
+        {
+            return format!("the folder {} is {}", parent.display(), reason);
+        }
+        // remove_dir_all reports the folder it started from, not the item that stopped it
+        let mut budget = LOCKED_SEARCH_LIMIT;
+        if let Some((locked, reason)) = find_locked(path, &mut budget) {
+            return format!("{} inside it is {}", locked.display(), reason);
+        }
+    }
+
+    e.to_string()
+}
+
+/// Entries looked at when searching a folder for what blocked an operation on it.
+const LOCKED_SEARCH_LIMIT: usize = 10_000;
+
+/// The first item below `dir` with an immutable or append-only attribute, giving up after
+/// `budget` entries.
+fn find_locked(dir: &Path, budget: &mut usize) -> Option<(PathBuf, &'static str)> {
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        if *budget == 0 {
+            return None;
+        }
+        *budget -= 1;
+
+        let path = entry.path();
+        if let Some(reason) = InodeFlags::read(&path).describe() {
+            return Some((path, reason));
+        }
+        if entry.file_type().is_ok_and(|t| t.is_dir())
+            && let Some(found) = find_locked(&path, budget)
+        {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// Whether two paths name the same directory entry's inode, e.g. through a symlinked directory.
+/// Neither path's last component is followed.
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+/// `path` with symlinks in its parent resolved, for comparing where things really live.
+pub fn canonical(path: &Path) -> PathBuf {
+    match (path.parent().and_then(|p| fs::canonicalize(p).ok()), path.file_name()) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// The first of "name (copy).ext", "name (copy 2).ext", … that is free in `dir`.
+pub fn copy_name(dir: &Path, name: &OsStr) -> PathBuf {
+    let is_dir = dir.join(name).is_dir();
+    free_name(dir, name, is_dir, |n| if n == 1 { " (copy)".to_string() } else { format!(" (copy {})", n) })
+}
+
+/// `name` in `dir` if it is free, otherwise the first free "name (2).ext", "name (3).ext", …
+pub fn numbered_name(dir: &Path, name: &OsStr, is_dir: bool) -> PathBuf {
+    let path = dir.join(name);
+    if fs::symlink_metadata(&path).is_err() {
+        return path;
+    }
+    free_name(dir, name, is_dir, |n| format!(" ({})", n + 1))
+}
+
+/// The first free name in `dir` made of `name`'s stem, `label(n)` for n = 1, 2, … and the
+/// extension. Folders keep dots in their names, so `is_dir` names have no extension.
+fn free_name(dir: &Path, name: &OsStr, is_dir: bool, label: impl Fn(usize) -> String) -> PathBuf {
+    let path = Path::new(name);
+    let (stem, extension) = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) if !is_dir => (stem, Some(extension)),
+        _ => (name, None),
+    };
+
+    (1..)
+        .map(|n| {
+            let mut candidate = stem.to_os_string();
+            candidate.push(label(n));
+            if let Some(extension) = extension {
+                candidate.push(".");
+                candidate.push(extension);
+            }
+            dir.join(candidate)
+        })
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .unwrap_or_else(|| dir.join(name))
+}
+
+/// Renames `from` to `to`, failing with `AlreadyExists` instead of replacing anything, with
+/// no window for another program to create `to` in between.
+fn rename_noreplace(from: &Path, to: &Path) -> io::Result<()> {
+    let c_from = std::ffi::CString::new(from.as_os_str().as_bytes())?;
+    let c_to = std::ffi::CString::new(to.as_os_str().as_bytes())?;
+    // SAFETY: both paths are NUL-terminated strings that outlive the call
+    let result = unsafe { libc::renameat2(libc::AT_FDCWD, c_from.as_ptr(), libc::AT_FDCWD, c_to.as_ptr(), libc::RENAME_NOREPLACE) };
+    if result == 0 {
+        return Ok(());
+    }
+
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        // Filesystems without the flag, such as some network and FUSE ones, fall back to checking first
+        Some(libc::EINVAL | libc::ENOSYS) => {
+            if fs::symlink_metadata(to).is_ok() {
+                return Err(io::ErrorKind::AlreadyExists.into());
+            }
+            fs::rename(from, to)
+        }
+        _ => Err(error),
+    }
+}
+
+/// Renames `from` to `to` in the same folder, failing with `AlreadyExists` when `to` is
+/// another entry, even a hard link to the same item.
+pub fn rename_item(from: &Path, to: &Path) -> io::Result<()> {
+    // A case-only rename on a case-insensitive filesystem finds the item itself under the
+    // new name, though no entry is spelled that way
+    if same_file(from, to) && !has_entry(to) {
+        return rename_via_temporary(from, to);
+    }
+
+    rename_noreplace(from, to)
+}
+
+/// Whether the folder of `path` has an entry spelled exactly like its name.
+fn has_entry(path: &Path) -> bool {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return false;
+    };
+    fs::read_dir(parent).is_ok_and(|entries| entries.flatten().any(|e| e.file_name() == name))
+}
+
+/// Renames through a free temporary name. Some case-insensitive filesystems take a direct
+/// rename to the same name in another case as a no-op.
+fn rename_via_temporary(from: &Path, to: &Path) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (from.parent(), from.file_name()) else {
+        return fs::rename(from, to);
+    };
+    let temporary = free_name(dir, name, true, |n| format!(".csfm-rename-{}", n));
+    fs::rename(from, &temporary)?;
+    fs::rename(&temporary, to).inspect_err(|_| {
+        let _ = fs::rename(&temporary, from);
+    })
+}
+
+/// Renames `from` to `to` in the same folder, replacing the entry at `to`. The old item is
+/// only deleted once `from` has taken its place, so a failed rename leaves both as they were.
+pub fn replace_item(from: &Path, to: &Path) -> io::Result<()> {
+    // rename between two hard links of one inode succeeds without doing anything
+    if same_file(from, to) {
+        return fs::remove_file(from);
+    }
+
+    match fs::rename(from, to) {
+        // rename only replaces a file with a file, or a folder with an empty folder
+        Err(e) if matches!(
+            e.kind(),
+            io::ErrorKind::AlreadyExists | io::ErrorKind::DirectoryNotEmpty | io::ErrorKind::IsADirectory | io::ErrorKind::NotADirectory
+        ) => {}
+        result => return result,
+    }
+
+    let Some(aside) = set_aside(to)? else {
+        return fs::rename(from, to);
+    };
+    if let Err(e) = fs::rename(from, to) {
+        let _ = fs::rename(&aside, to);
+        return Err(e);
+    }
+    delete(&aside)
+}
+
+/// Moves whatever is at `path` to a free hidden name next to it, so it can be put back if
+/// what should replace it never gets there. None when nothing is at `path`.
+pub fn set_aside(path: &Path) -> io::Result<Option<PathBuf>> {
+    match fs::symlink_metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+        Ok(_) => {}
+    }
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(io::ErrorKind::InvalidInput.into());
+    };
+
+    let aside = free_name(dir, name, true, |n| format!(".csfm-replaced-{}", n));
+    fs::rename(path, &aside)?;
+    Ok(Some(aside))
+}
+
+/// Deletes `path` for good, a folder with everything in it. A symlink is removed, not what
+/// it points to.
+pub fn delete(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) }
+}
+
+/// Like `delete`, but with nothing at `path` there is nothing to do.
+pub fn remove_existing(path: &Path) -> io::Result<()> {
+    match delete(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Removes `path` if it is still an empty folder or file, as a cancelled creation leaves it,
+/// and returns whether it did.
+pub fn remove_if_empty(path: &Path) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir(path).is_ok(),
+        Ok(metadata) if metadata.len() == 0 => fs::remove_file(path).is_ok(),
+        _ => false,
+    }
+}
+
+/// Copies a file, symlink or whole directory tree. Symlinks are recreated rather than followed.
+pub fn copy_recursive(source: &Path, target: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    if !metadata.is_dir() {
+        return copy_entry(source, target, &metadata);
+    }
+
+    // Unlike walk(), every unreadable directory or entry fails the copy instead of being skipped
+    fs::create_dir(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+    }
+
+    Ok(())
+}
+
+fn copy_entry(source: &Path, target: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    if metadata.is_dir() {
+        fs::create_dir(target)
+    } else if metadata.file_type().is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(source)?, target)
+    } else {
+        fs::copy(source, target).map(|_| ())
+    }
+}
+
+/// Replaces `path` with `data` so that a crash leaves either the old or the new contents,
+/// never a truncated file: the data goes to a temporary file next to it, is synced to disk
+/// and then renamed over the original. A symlinked target is written through the link.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    write_atomic_with(path, data, |from, to| fs::rename(from, to))
+}
+
+/// `write_atomic` with the final rename passed in, so tests can fail it.
+fn write_atomic_with(path: &Path, data: &[u8], rename: impl FnOnce(&Path, &Path) -> io::Result<()>) -> io::Result<()> {
+    use std::io::Write;
+
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut temp_name = OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(format!(".tmp-{}", std::process::id()));
+    let temp = dir.join(temp_name);
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        if let Ok(metadata) = fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(data)?;
+        file.sync_all()?;
+        rename(&temp, &path)?;
+        // Make the rename itself durable
+        fs::File::open(dir)?.sync_all()
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Parses an octal permission string such as `755` or `2775`. Signs, spaces and the
+/// like are refused, from_str_radix alone would take `+755`.
+pub fn parse_mode(mode: &str) -> Option<u32> {
+    if mode.is_empty() || mode.len() > 4 || !mode.bytes().all(|b| (b'0'..=b'7').contains(&b)) {
+        return None;
+    }
+    u32::from_str_radix(mode, 8).ok()
+}
+
+/// Creates an empty folder or file at `path`, failing if anything is there already, and
+/// gives it the octal `mode`. Without a mode the umask decides.
+pub fn create_item(path: &Path, is_dir: bool, mode: Option<&str>) -> io::Result<()> {
+    if is_dir {
+        fs::create_dir(path)?;
+    } else {
+        fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+    }
+    apply_new_mode(path, mode)
+}
+
+fn apply_new_mode(path: &Path, setting: Option<&str>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match setting.and_then(parse_mode) {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScratchDir;
+
+    #[test]
+    fn interrupted_write_keeps_the_old_contents() {
+        let dir = ScratchDir::new("interrupted-write");
+        let path = dir.join("csfm.toml");
+        write_atomic(&path, b"show_hidden_files = true\n").unwrap();
+
+        // The new contents are written and synced, then the rename never happens
+        let result = write_atomic_with(&path, b"show_hidden_files = false\n", |_, _| Err(io::Error::other("crashed")));
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "show_hidden_files = true\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "the temporary file is cleaned up");
+    }
+
+    #[test]
+    fn case_only_rename() {
+        let dir = ScratchDir::new("case-rename");
+        fs::write(dir.join("Readme.md"), "text").unwrap();
+
+        rename_item(&dir.join("Readme.md"), &dir.join("README.md")).unwrap();
+        let names: Vec<_> = fs::read_dir(&dir).unwrap().flatten().map(|e| e.file_name()).collect();
+        assert_eq!(names, ["README.md"]);
+
+        // What a case-insensitive filesystem needs, leaving nothing behind
+        rename_via_temporary(&dir.join("README.md"), &dir.join("readme.md")).unwrap();
+        let names: Vec<_> = fs::read_dir(&dir).unwrap().flatten().map(|e| e.file_name()).collect();
+        assert_eq!(names, ["readme.md"]);
+        assert_eq!(fs::read_to_string(dir.join("readme.md")).unwrap(), "text");
+    }
+
+    #[test]
+    fn renaming_onto_a_hard_link_is_a_conflict() {
+        let dir = ScratchDir::new("hard-link-rename");
+        fs::write(dir.join("a"), "text").unwrap();
+        fs::hard_link(dir.join("a"), dir.join("b")).unwrap();
+        fs::write(dir.join("c"), "other").unwrap();
+
+        // Same inode, but a separate entry, unlike a case-insensitive match
+        assert!(same_file(&dir.join("a"), &dir.join("b")));
+        assert_eq!(rename_item(&dir.join("a"), &dir.join("b")).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(rename_item(&dir.join("a"), &dir.join("c")).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert!(dir.join("a").exists());
+    }
+
+    #[test]
+    fn replacing_keeps_the_target_until_the_rename_is_done() {
+        let dir = ScratchDir::new("replace");
+        fs::write(dir.join("a"), "new").unwrap();
+        fs::create_dir_all(dir.join("b/inside")).unwrap();
+        fs::write(dir.join("b/inside/old"), "old").unwrap();
+
+        // A file over a folder that isn't empty
+        replace_item(&dir.join("a"), &dir.join("b")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("b")).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // A failed rename puts the target back
+        fs::create_dir_all(dir.join("c/inside")).unwrap();
+        assert!(replace_item(&dir.join("missing"), &dir.join("c")).is_err());
+        assert!(dir.join("c/inside").is_dir());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // Two hard links of one file leave just the target
+        fs::hard_link(dir.join("b"), dir.join("d")).unwrap();
+        replace_item(&dir.join("d"), &dir.join("b")).unwrap();
+        assert!(!dir.join("d").exists());
+        assert_eq!(fs::read_to_string(dir.join("b")).unwrap(), "new");
+
+        // A symlink to the source is its own entry, not a hard link
+        std::os::unix::fs::symlink("b", dir.join("e")).unwrap();
+        replace_item(&dir.join("b"), &dir.join("e")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("e")).unwrap(), "new");
+        assert!(!fs::symlink_metadata(dir.join("e")).unwrap().file_type().is_symlink());
+    }
+
+    #[test]
+    fn non_utf8_names_keep_their_bytes() {
+        let dir = ScratchDir::new("non-utf8");
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        fs::write(dir.join(name), "latin-1").unwrap();
+
+        let copy = copy_name(&dir, name);
+        assert_eq!(copy.file_name().unwrap().as_bytes(), b"caf\xe9 (copy).txt");
+        copy_recursive(&dir.join(name), &copy).unwrap();
+        remove_existing(&copy).unwrap();
+
+        rename_item(&dir.join(name), &dir.join("cafe.txt")).unwrap();
+        rename_item(&dir.join("cafe.txt"), &dir.join(name)).unwrap();
+        assert_eq!(fs::read_to_string(dir.join(name)).unwrap(), "latin-1");
+        remove_existing(&dir.join(name)).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn modes_are_plain_octal() {
+        assert_eq!(parse_mode("755"), Some(0o755));
+        assert_eq!(parse_mode("2775"), Some(0o2775));
+        assert_eq!(parse_mode("0"), Some(0));
+        for bad in ["", "+755", "-755", "+75", " 755", "0x7", "758", "12345", "７５５"] {
+            assert_eq!(parse_mode(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn new_items_get_their_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = ScratchDir::new("create");
+        create_item(&dir.join("folder"), true, Some("750")).unwrap();
+        create_item(&dir.join("file"), false, None).unwrap();
+        assert_eq!(fs::metadata(dir.join("folder")).unwrap().permissions().mode() & 0o7777, 0o750);
+        assert!(fs::metadata(dir.join("file")).unwrap().is_file());
+        // Never over an existing item
+        assert_eq!(create_item(&dir.join("file"), false, None).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn cancelled_creations_go_only_while_empty() {
+        let dir = ScratchDir::new("remove-if-empty");
+        fs::create_dir_all(dir.join("used")).unwrap();
+        fs::write(dir.join("used/kept"), "").unwrap();
+        fs::write(dir.join("written"), "text").unwrap();
+        fs::write(dir.join("empty"), "").unwrap();
+
+        assert!(!remove_if_empty(&dir.join("used")));
+        assert!(!remove_if_empty(&dir.join("written")));
+        assert!(remove_if_empty(&dir.join("empty")));
+        assert!(!remove_if_empty(&dir.join("empty")));
+
+        delete(&dir.join("used")).unwrap();
+        assert_eq!(delete(&dir.join("used")).unwrap_err().kind(), io::ErrorKind::NotFound);
+        remove_existing(&dir.join("used")).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+//! Where csfm keeps its files, following the XDG Base Directory spec.
+
+use std::{env, ffi::OsString, os::unix::fs::MetadataExt, path::PathBuf};
+
+/// Looks up an environment variable. Tests pass their own instead of changing the process
+/// environment.
+type Env<'a> = &'a dyn Fn(&str) -> Option<OsString>;
+
+fn process_env(name: &str) -> Option<OsString> {
+    env::var_os(name)
+}
+
+/// The user's home directory. Without a usable `$HOME` this is a per-user directory under
+/// the temp dir, so nothing csfm writes ends up relative to the working directory.
+pub fn home() -> PathBuf {
+    home_in(&process_env)
+}
+
+/// Whether `$HOME` is missing and `home()` is a stand-in that won't outlive a reboot.
+pub fn home_missing() -> bool {
+    absolute_var(&process_env, "HOME").is_none()
+}
+
+/// `$XDG_CONFIG_HOME`, `~/.config` by default.
+pub fn config_home() -> PathBuf {
+    xdg_dir(&process_env, "XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_STATE_HOME`, `~/.local/state` by default.
+pub fn state_home() -> PathBuf {
+    xdg_dir(&process_env, "XDG_STATE_HOME", ".local/state")
+}
+
+/// `$XDG_DATA_HOME`, `~/.local/share` by default.
+pub fn data_home() -> PathBuf {
+    xdg_dir(&process_env, "XDG_DATA_HOME", ".local/share")
+}
+
+fn home_in(env: Env) -> PathBuf {
+    absolute_var(env, "HOME").unwrap_or_else(fallback_home)
+}
+
+fn xdg_dir(env: Env, name: &str, default: &str) -> PathBuf {
+    absolute_var(env, name).unwrap_or_else(|| home_in(env).join(default))
+}
+
+// The spec says relative paths in these variables are invalid and must be ignored
+fn absolute_var(env: Env, name: &str) -> Option<PathBuf> {
+    env(name)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
+fn fallback_home() -> PathBuf {
+    let uid = std::fs::metadata("/proc/self").map(|m| m.uid()).unwrap_or(0);
+    env::temp_dir().join(format!("csfm-{}", uid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_of(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<OsString> {
+        move |name| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| OsString::from(value))
+    }
+
+    #[test]
+    fn xdg_variables_override_the_defaults() {
+        let env = env_of(&[("HOME", "/home/u"), ("XDG_CONFIG_HOME", "/cfg"), ("XDG_DATA_HOME", "/data")]);
+
+        assert_eq!(xdg_dir(&env, "XDG_CONFIG_HOME", ".config"), PathBuf::from("/cfg"));
+        assert_eq!(xdg_dir(&env, "XDG_DATA_HOME", ".local/share"), PathBuf::from("/data"));
+        assert_eq!(xdg_dir(&env, "XDG_STATE_HOME", ".local/state"), PathBuf::from("/home/u/.local/state"));
+    }
+
+    #[test]
+    fn relative_and_empty_variables_are_ignored() {
+        let env = env_of(&[("HOME", "/home/u"), ("XDG_CONFIG_HOME", "relative/cfg"), ("XDG_DATA_HOME", "")]);
+
+        assert_eq!(xdg_dir(&env, "XDG_CONFIG_HOME", ".config"), PathBuf::from("/home/u/.config"));
+        assert_eq!(xdg_dir(&env, "XDG_DATA_HOME", ".local/share"), PathBuf::from("/home/u/.local/share"));
+    }
+
+    #[test]
+    fn missing_home_falls_back_to_the_temp_dir() {
+        for env in [env_of(&[]), env_of(&[("HOME", "")]), env_of(&[("HOME", "home")])] {
+            let home = home_in(&env);
+            assert!(home.starts_with(env::temp_dir()), "{}", home.display());
+            assert_eq!(xdg_dir(&env, "XDG_CONFIG_HOME", ".config"), home.join(".config"));
+        }
+        // An XDG variable still counts without a home
+        assert_eq!(xdg_dir(&env_of(&[("XDG_STATE_HOME", "/state")]), "XDG_STATE_HOME", ".local/state"), PathBuf::from("/state"));
+    }
+}
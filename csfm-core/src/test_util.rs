@@ -0,0 +1,52 @@
+//! Scratch directories for tests, here and in csfm itself, which turns on the `test-util`
+//! feature for its tests.
+
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// An empty directory under the system temp dir, removed with everything in it when dropped,
+/// so a failing assertion doesn't leave it behind.
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    /// `name` tells the directory apart when a test leaves it behind, e.g. after a crash.
+    /// Every directory gets its own number, tests running at the same time never share one.
+    pub fn new(name: &str) -> Self {
+        Self::new_in(&std::env::temp_dir(), name)
+    }
+
+    /// Like `new`, but in `base`, e.g. on another filesystem.
+    pub fn new_in(base: &Path, name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = base.join(format!("csfm-test-{}-{}-{}", name, std::process::id(), n));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        ScratchDir(dir)
+    }
+}
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for ScratchDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
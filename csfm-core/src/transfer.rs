@@ -0,0 +1,368 @@
+//! Copying, moving and trashing items into a folder: where each one goes, whether it can go
+//! there, whether it fits, and carrying it out.
+
+use std::{
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use crate::{
+    listing::{WalkOptions, walk},
+    mounts::on_network_filesystem,
+    ops::{canonical, copy_name, copy_recursive, delete, describe_io_error, remove_existing, same_file, set_aside},
+    trash,
+};
+
+/// What a transfer does with its sources.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transfer {
+    Copy,
+    Move,
+    /// Into the trash, the targets are ignored
+    Trash,
+}
+
+/// Why a source can't go into the destination folder.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Refusal {
+    /// A move into the folder the source already is in
+    AlreadyThere,
+    /// A folder into itself or a folder inside it
+    IntoItself,
+    /// The target holds the source, so replacing it would delete the source along with it
+    InsideTarget(PathBuf),
+}
+
+/// Where `source` goes in `dest`, or why it can't. The target may exist, replacing it is up
+/// to the caller.
+pub fn plan(transfer: Transfer, source: &Path, dest: &Path, keep_both: bool) -> Result<PathBuf, Refusal> {
+    let target = transfer_target(transfer, source, dest, keep_both).ok_or(Refusal::AlreadyThere)?;
+    if source.is_dir() && canonical(dest).starts_with(canonical(source)) {
+        return Err(Refusal::IntoItself);
+    }
+    if canonical(source).starts_with(canonical(&target)) {
+        return Err(Refusal::InsideTarget(target));
+    }
+
+    Ok(target)
+}
+
+/// Where `source` goes in `dest`, or None for a move that would leave it where it is.
+fn transfer_target(transfer: Transfer, source: &Path, dest: &Path, keep_both: bool) -> Option<PathBuf> {
+    let name = source.file_name()?;
+    let target = dest.join(name);
+
+    // Compared by inode, so a destination reached through a symlink counts too. Copying
+    // over the source itself would truncate it before reading it.
+    if same_file(source, &target) {
+        return (transfer != Transfer::Move).then(|| copy_name(dest, name));
+    }
+    if transfer == Transfer::Copy && keep_both && fs::symlink_metadata(&target).is_ok() {
+        return Some(copy_name(dest, name));
+    }
+
+    Some(target)
+}
+
+/// Bytes needed and available when the transfer clearly won't fit into `dest`. Moves within
+/// one filesystem need no space. Filesystems that don't report sizes (blocks == 0) and network
+/// mounts, whose numbers are often made up, are not checked.
+pub fn space_shortfall(transfer: Transfer, pairs: &[(PathBuf, PathBuf)], dest: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    if on_network_filesystem(dest) {
+        return None;
+    }
+    let dest_dev = fs::metadata(dest).ok()?.dev();
+
+    let mut needed = 0;
+    for (source, _) in pairs {
+        let Ok(metadata) = fs::symlink_metadata(source) else {
+            continue;
+        };
+        if transfer == Transfer::Move && metadata.dev() == dest_dev {
+            continue;
+        }
+        if !metadata.is_dir() {
+            needed += metadata.len();
+            continue;
+        }
+        let options = WalkOptions { max_depth: usize::MAX, follow_symlinks: false, show_hidden_files: true };
+        walk(source, &options, |_, metadata| {
+            if metadata.is_file() {
+                needed += metadata.len();
+            }
+            true
+        });
+    }
+
+    let c_dest = std::ffi::CString::new(dest.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is NUL-terminated and stat is a valid statvfs to fill in
+    if unsafe { libc::statvfs(c_dest.as_ptr(), &mut stat) } != 0 || stat.f_blocks == 0 {
+        return None;
+    }
+    let available = stat.f_bavail as u64 * stat.f_frsize as u64;
+
+    (needed > available).then_some((needed, available))
+}
+
+/// Hears about every item of a transfer as soon as it is done, e.g. to log it or to move a
+/// progress bar. Closures and channel senders are progress reports too.
+pub trait Progress {
+    /// `source` is now at `target`, or in the trash at `target`.
+    fn done(&mut self, source: &Path, target: &Path);
+}
+
+impl<F: FnMut(&Path, &Path)> Progress for F {
+    fn done(&mut self, source: &Path, target: &Path) {
+        self(source, target)
+    }
+}
+
+impl Progress for mpsc::Sender<(PathBuf, PathBuf)> {
+    fn done(&mut self, source: &Path, target: &Path) {
+        // Nobody listening any more is no reason to stop
+        let _ = self.send((source.to_path_buf(), target.to_path_buf()));
+    }
+}
+
+/// How a transfer ended.
+#[derive(Debug, Clone, Default)]
+pub struct Outcome {
+    /// A message for every pair that failed
+    pub errors: Vec<String>,
+    /// The pairs left when the destination filled up, starting with the one cut short.
+    /// Running them once there is space again resumes the transfer.
+    pub stalled: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Copies or moves each source onto its target, replacing what is already there. The old
+/// item is only deleted once the new one is in place, and put back if it never gets there.
+/// Running out of space pauses the whole transfer rather than failing every following
+/// item the same way, see [`Outcome::stalled`].
+pub fn run_transfer(transfer: Transfer, pairs: Vec<(PathBuf, PathBuf)>, progress: &mut impl Progress) -> Outcome {
+    run_transfer_with(transfer, pairs, progress, copy_recursive)
+}
+
+/// `run_transfer` with the copy passed in, so tests can fail it.
+fn run_transfer_with(
+    transfer: Transfer,
+    pairs: Vec<(PathBuf, PathBuf)>,
+    progress: &mut impl Progress,
+    copy: impl Fn(&Path, &Path) -> io::Result<()>,
+) -> Outcome {
+    let mut outcome = Outcome::default();
+    let mut pairs = pairs.into_iter();
+
+    while let Some((source, target)) = pairs.next() {
+        if transfer == Transfer::Trash {
+            match trash::trash(&source) {
+                Ok(trashed) => progress.done(&source, &trashed),
+                Err(e) => outcome.errors.push(format!("{}: {}", source.display(), describe_io_error(&source, &e))),
+            }
+            continue;
+        }
+
+        let aside = match set_aside(&target) {
+            Ok(aside) => aside,
+            Err(e) => {
+                outcome.errors.push(format!("{}: {}", target.display(), describe_io_error(&target, &e)));
+                continue;
+            }
+        };
+        let result = copy_or_move(transfer, &source, &target, &copy);
+        if let Some(aside) = aside {
+            // Nothing took the old item's place, so it goes back
+            let settled = if result.is_err() && fs::symlink_metadata(&target).is_err() { fs::rename(&aside, &target) } else { delete(&aside) };
+            if let Err(e) = settled {
+                outcome.errors.push(format!("The replaced {} is left at {}: {}", target.display(), aside.display(), describe_io_error(&aside, &e)));
+            }
+        }
+
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::StorageFull => {
+                // The partial copy is gone and the item it replaced is back, so resuming
+                // starts this pair over
+                outcome.stalled = std::iter::once((source, target)).chain(pairs).collect();
+                break;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound && target.parent().is_some_and(|p| !p.is_dir()) => {
+                outcome.errors.push(format!("Stopped: the destination folder {} no longer exists", target.parent().unwrap_or(&target).display()));
+                break;
+            }
+            Err(e) => outcome.errors.push(format!("{}: {}", source.display(), describe_io_error(&source, &e))),
+            Ok(()) => progress.done(&source, &target),
+        }
+    }
+
+    outcome
+}
+
+/// Puts `source` at `target`, where nothing is any more.
+fn copy_or_move(transfer: Transfer, source: &Path, target: &Path, copy: impl Fn(&Path, &Path) -> io::Result<()>) -> io::Result<()> {
+    match transfer {
+        Transfer::Copy => copy_or_clean_up(source, target, copy),
+        Transfer::Move | Transfer::Trash => match fs::rename(source, target) {
+            // rename can't cross filesystems, so copy and then delete the original. Only a
+            // whole copy lets the original go.
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                copy_or_clean_up(source, target, copy)?;
+                remove_existing(source)
+            }
+            result => result,
+        },
+    }
+}
+
+/// Copies `source` to `target`, removing what was written of it when the copy fails.
+fn copy_or_clean_up(source: &Path, target: &Path, copy: impl Fn(&Path, &Path) -> io::Result<()>) -> io::Result<()> {
+    copy(source, target).inspect_err(|_| {
+        let _ = remove_existing(target);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScratchDir;
+
+    #[test]
+    fn copying_a_file_onto_itself_through_a_symlink_makes_a_copy() {
+        let dir = ScratchDir::new("self-copy");
+        fs::create_dir(dir.join("real")).unwrap();
+        fs::write(dir.join("real/notes.txt"), "keep me").unwrap();
+        std::os::unix::fs::symlink("real", dir.join("alias")).unwrap();
+        let source = dir.join("real/notes.txt");
+
+        assert!(same_file(&source, &dir.join("alias/notes.txt")));
+        assert_eq!(transfer_target(Transfer::Move, &source, &dir.join("alias"), false), None);
+        let target = transfer_target(Transfer::Copy, &source, &dir.join("alias"), false).unwrap();
+        assert_eq!(target, dir.join("alias/notes (copy).txt"));
+
+        copy_recursive(&source, &target).unwrap();
+        assert_eq!(fs::read_to_string(&source).unwrap(), "keep me");
+        assert_eq!(fs::read_to_string(dir.join("real/notes (copy).txt")).unwrap(), "keep me");
+    }
+
+    #[test]
+    fn refuses_what_would_lose_the_source() {
+        let dir = ScratchDir::new("refusals");
+        fs::create_dir_all(dir.join("outer/inner/outer")).unwrap();
+        let outer = dir.join("outer");
+
+        assert_eq!(plan(Transfer::Move, &outer, &dir, false), Err(Refusal::AlreadyThere));
+        assert_eq!(plan(Transfer::Copy, &outer, &outer.join("inner"), false), Err(Refusal::IntoItself));
+        // Replacing dir/outer would delete the source inside it
+        assert_eq!(plan(Transfer::Move, &outer.join("inner/outer"), &dir, false), Err(Refusal::InsideTarget(outer.clone())));
+        // A copy next to the source gets a free name
+        assert_eq!(plan(Transfer::Copy, &outer, &dir, false), Ok(dir.join("outer (copy)")));
+    }
+
+    #[test]
+    fn a_failed_copy_keeps_the_item_it_would_replace() {
+        let dir = ScratchDir::new("failed-replace");
+        fs::create_dir_all(dir.join("from/pics")).unwrap();
+        fs::write(dir.join("from/pics/a.jpg"), "new").unwrap();
+        // Sockets can't be opened for reading, so copying one fails
+        let _socket = std::os::unix::net::UnixListener::bind(dir.join("from/pics/socket")).unwrap();
+        fs::create_dir_all(dir.join("to/pics")).unwrap();
+        fs::write(dir.join("to/pics/old.jpg"), "old").unwrap();
+
+        let outcome = run_transfer(Transfer::Copy, vec![(dir.join("from/pics"), dir.join("to/pics"))], &mut |_: &Path, _: &Path| {});
+        assert_eq!(outcome.errors.len(), 1, "{:?}", outcome.errors);
+        assert_eq!(fs::read_to_string(dir.join("to/pics/old.jpg")).unwrap(), "old");
+        assert_eq!(fs::read_dir(dir.join("to/pics")).unwrap().count(), 1);
+        assert_eq!(fs::read_dir(dir.join("to")).unwrap().count(), 1);
+
+        // Replacing works once the copy can be done
+        fs::remove_file(dir.join("from/pics/socket")).unwrap();
+        let outcome = run_transfer(Transfer::Copy, vec![(dir.join("from/pics"), dir.join("to/pics"))], &mut |_: &Path, _: &Path| {});
+        assert!(outcome.errors.is_empty());
+        assert!(!dir.join("to/pics/old.jpg").exists());
+        assert_eq!(fs::read_to_string(dir.join("to/pics/a.jpg")).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.join("to")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn a_full_disk_stalls_and_keeps_the_item_it_would_replace() {
+        let dir = ScratchDir::new("full");
+        fs::create_dir_all(dir.join("from")).unwrap();
+        fs::create_dir_all(dir.join("to")).unwrap();
+        for name in ["a", "b"] {
+            fs::write(dir.join("from").join(name), "new").unwrap();
+        }
+        fs::write(dir.join("to/a"), "old").unwrap();
+        let pairs = vec![(dir.join("from/a"), dir.join("to/a")), (dir.join("from/b"), dir.join("to/b"))];
+
+        let full = |_: &Path, target: &Path| {
+            fs::write(target, "ne")?;
+            Err(io::Error::from(io::ErrorKind::StorageFull))
+        };
+        let outcome = run_transfer_with(Transfer::Copy, pairs.clone(), &mut |_: &Path, _: &Path| {}, full);
+        assert!(outcome.errors.is_empty());
+        assert_eq!(outcome.stalled, pairs);
+        assert_eq!(fs::read_to_string(dir.join("to/a")).unwrap(), "old");
+        assert_eq!(fs::read_dir(dir.join("to")).unwrap().count(), 1);
+
+        // Resuming replaces it after all
+        let outcome = run_transfer(Transfer::Copy, outcome.stalled, &mut |_: &Path, _: &Path| {});
+        assert!(outcome.errors.is_empty() && outcome.stalled.is_empty());
+        assert_eq!(fs::read_to_string(dir.join("to/a")).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.join("to")).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn a_failed_move_across_filesystems_leaves_no_partial_copy() {
+        use std::os::unix::fs::MetadataExt;
+
+        // Needs a second filesystem, /dev/shm is usually one
+        let other = Path::new("/dev/shm");
+        let dir = ScratchDir::new("cross-device");
+        let dev = |p: &Path| fs::metadata(p).map(|m| m.dev()).ok();
+        if dev(other).is_none() || dev(other) == dev(&dir) {
+            return;
+        }
+        fs::create_dir_all(dir.join("pics")).unwrap();
+        fs::write(dir.join("pics/a.jpg"), "kept").unwrap();
+        let _socket = std::os::unix::net::UnixListener::bind(dir.join("pics/socket")).unwrap();
+        let other = ScratchDir::new_in(other, "cross-device");
+        let target = other.join("pics");
+
+        let outcome = run_transfer(Transfer::Move, vec![(dir.join("pics"), target.clone())], &mut |_: &Path, _: &Path| {});
+        assert_eq!(outcome.errors.len(), 1, "{:?}", outcome.errors);
+        assert!(fs::symlink_metadata(&target).is_err());
+        assert_eq!(fs::read_to_string(dir.join("pics/a.jpg")).unwrap(), "kept");
+    }
+
+    #[test]
+    fn reports_each_item_as_it_is_done() {
+        let dir = ScratchDir::new("progress");
+        fs::create_dir_all(dir.join("from")).unwrap();
+        fs::create_dir_all(dir.join("to")).unwrap();
+        fs::write(dir.join("from/a"), "a").unwrap();
+        fs::write(dir.join("to/b"), "old").unwrap();
+        let pairs = vec![
+            (dir.join("from/a"), dir.join("to/a")),
+            (dir.join("from/missing"), dir.join("to/missing")),
+            (dir.join("from/a"), dir.join("to/b")),
+        ];
+
+        let mut done = vec![];
+        let outcome = run_transfer(Transfer::Copy, pairs.clone(), &mut |source: &Path, target: &Path| {
+            done.push((source.to_path_buf(), target.to_path_buf()))
+        });
+        assert_eq!(done, [pairs[0].clone(), pairs[2].clone()]);
+        assert_eq!(outcome.errors.len(), 1);
+        assert!(outcome.stalled.is_empty());
+        assert_eq!(fs::read_to_string(dir.join("to/b")).unwrap(), "a");
+
+        let (sender, receiver) = mpsc::channel::<(PathBuf, PathBuf)>();
+        let outcome = run_transfer(Transfer::Move, vec![pairs[0].clone()], &mut sender.clone());
+        assert!(outcome.errors.is_empty());
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [pairs[0].clone()]);
+        assert!(!dir.join("from/a").exists());
+    }
+}
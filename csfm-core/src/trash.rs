@@ -0,0 +1,137 @@
+//! The freedesktop.org trash in `$XDG_DATA_HOME/Trash`: every trashed item sits in `files/`
+//! next to an `info/<name>.trashinfo` recording where it came from and when.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    io::Write,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ops::{copy_recursive, remove_existing},
+    paths,
+    uri::{percent_decode, percent_encode},
+};
+
+pub fn trash_dir() -> PathBuf {
+    paths::data_home().join("Trash")
+}
+
+pub fn files_dir() -> PathBuf {
+    trash_dir().join("files")
+}
+
+fn info_path(name: &OsStr) -> PathBuf {
+    let mut file = name.to_os_string();
+    file.push(".trashinfo");
+    trash_dir().join("info").join(file)
+}
+
+/// Moves `path` into the trash and returns where it ended up. Items on another filesystem
+/// are copied and then removed.
+pub fn trash(path: &Path) -> io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let name = path.file_name().ok_or_else(|| io::Error::other("can't trash the root folder"))?;
+    fs::create_dir_all(files_dir())?;
+    fs::create_dir_all(trash_dir().join("info"))?;
+
+    // Creating the info file exclusively reserves the name, even against another process
+    let (trashed_name, mut info) = (1..)
+        .map(|n| {
+            let mut candidate = name.to_os_string();
+            if n > 1 {
+                candidate.push(format!(".{}", n));
+            }
+            candidate
+        })
+        .filter(|candidate| fs::symlink_metadata(files_dir().join(candidate)).is_err())
+        .find_map(|candidate| {
+            match fs::OpenOptions::new().write(true).create_new(true).open(info_path(&candidate)) {
+                Ok(file) => Some(Ok((candidate, file))),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => None,
+                Err(e) => Some(Err(e)),
+            }
+        })
+        .expect("the candidate names never run out")?;
+
+    let deleted = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S");
+    let target = files_dir().join(&trashed_name);
+    let forget_info = || {
+        let _ = fs::remove_file(info_path(&trashed_name));
+    };
+    if let Err(e) = info.write_all(format!("[Trash Info]\nPath={}\nDeletionDate={}\n", percent_encode(path.as_os_str().as_bytes()), deleted).as_bytes()) {
+        forget_info();
+        return Err(e);
+    }
+
+    match fs::rename(&path, &target) {
+        Ok(()) => Ok(target),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            if let Err(e) = copy_recursive(&path, &target) {
+                // Only the incomplete copy goes, the original is still whole
+                let _ = remove_existing(&target);
+                forget_info();
+                return Err(e);
+            }
+            // From here the trash holds the only complete copy, so it stays even when part
+            // of the original can't be removed
+            remove_existing(&path).map_err(|e| {
+                io::Error::other(format!(
+                    "it was copied to the trash as {}, but part of the original couldn't be removed: {}",
+                    target.display(),
+                    e
+                ))
+            })?;
+            Ok(target)
+        }
+        Err(e) => {
+            forget_info();
+            Err(e)
+        }
+    }
+}
+
+/// Drops the info file of a trashed item that was deleted for good.
+pub fn forget(trashed: &Path) {
+    if trashed.parent() == Some(files_dir().as_path())
+        && let Some(name) = trashed.file_name()
+    {
+        let _ = fs::remove_file(info_path(name));
+    }
+}
+
+/// Where the trashed item at `trashed` came from, per its info file.
+pub fn original_path(trashed: &Path) -> io::Result<PathBuf> {
+    let name = trashed.file_name().ok_or_else(|| io::Error::other("not a trashed item"))?;
+    let info = fs::read(info_path(name))?;
+
+    info.split(|&b| b == b'\n')
+        .find_map(|line| line.strip_prefix(b"Path="))
+        .map(|encoded| PathBuf::from(OsString::from_vec(percent_decode(encoded))))
+        .ok_or_else(|| io::Error::other("the trash info has no original path"))
+}
+
+/// Moves a trashed item back to where it was deleted from and returns that path.
+pub fn restore(trashed: &Path) -> io::Result<PathBuf> {
+    let original = original_path(trashed)?;
+    if fs::symlink_metadata(&original).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists again", original.display())));
+    }
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::rename(trashed, &original) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_recursive(trashed, &original)?;
+            remove_existing(trashed)?;
+        }
+        result => result?,
+    }
+    if let Some(name) = trashed.file_name() {
+        let _ = fs::remove_file(info_path(name));
+    }
+    Ok(original)
+}
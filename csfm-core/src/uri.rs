@@ -0,0 +1,65 @@
+//! Percent-encoding of paths, as in `file://` URIs, GTK bookmarks and trash info files.
+
+/// Percent-encodes everything but unreserved characters and `/`.
+pub fn percent_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Undoes `%XX` escapes. Malformed ones are kept as they are.
+pub fn percent_decode(bytes: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16);
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let decoded = match bytes.get(i..i + 3) {
+            Some([b'%', hi, lo]) => hex(*hi).zip(hex(*lo)).map(|(hi, lo)| (hi * 16 + lo) as u8),
+            _ => None,
+        };
+        match decoded {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode(b"/a%20b/%C3%A9"), "/a b/é".as_bytes());
+        assert_eq!(percent_decode(b"%2f%2F"), b"//");
+        // Bytes that aren't UTF-8 are kept as they are
+        assert_eq!(percent_decode(b"/%FF"), b"/\xff");
+        // Malformed escapes are left alone
+        assert_eq!(percent_decode(b"100%"), b"100%");
+        assert_eq!(percent_decode(b"%zz%4"), b"%zz%4");
+        assert_eq!(percent_decode(b"%%41"), b"%A");
+        assert_eq!(percent_decode(b"%+5"), b"%+5");
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let path = b"/home/u/My Files/caf\xc3\xa9 100%/\xff";
+        assert_eq!(percent_encode(path), "/home/u/My%20Files/caf%C3%A9%20100%25/%FF");
+        assert_eq!(percent_decode(percent_encode(path).as_bytes()), path);
+    }
+}
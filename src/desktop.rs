@@ -0,0 +1,238 @@
+//! Registering csfm with the desktop as a folder handler, and reading the paths and
+//! `file://` URIs it is launched with.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use csfm_core::{ops::write_atomic, paths, uri::percent_decode};
+
+const DESKTOP_FILE_NAME: &str = "csfm.desktop";
+const FOLDER_MIME: &str = "inode/directory";
+
+fn desktop_file_path() -> PathBuf {
+    paths::data_home().join("applications").join(DESKTOP_FILE_NAME)
+}
+
+/// The desktop entry launching `exec`, which receives every dropped or opened item as a URI.
+pub fn desktop_entry(exec: &Path) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=CsFM\n\
+         GenericName=File Manager\n\
+         Comment=Browse the file system\n\
+         Exec={} %U\n\
+         Terminal=false\n\
+         Categories=System;FileTools;FileManager;\n\
+         MimeType={};\n",
+        exec_arg(&exec.to_string_lossy()),
+        FOLDER_MIME,
+    )
+}
+
+/// Quotes an Exec argument as the desktop entry spec requires when it has reserved characters.
+fn exec_arg(arg: &str) -> String {
+    const RESERVED: &[char] = &[' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')', '`', '%'];
+    if !arg.contains(RESERVED) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            // Escaped once for the quoting, and backslashes once more for the string value
+            '"' | '`' | '$' => quoted.push_str(&format!("\\\\{}", c)),
+            '\\' => quoted.push_str("\\\\\\\\"),
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes the desktop entry and refreshes the handler cache. With `set_default` csfm also
+/// becomes the default folder handler. Returns what was done, one line per step.
+pub fn install(set_default: bool) -> io::Result<Vec<String>> {
+    let exec = std::env::current_exe()?;
+    let mut done = write_desktop_file(&desktop_file_path(), &exec)?;
+    if set_default {
+        let status = Command::new("xdg-mime").args(["default", DESKTOP_FILE_NAME, FOLDER_MIME]).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("xdg-mime exited with {}", status)));
+        }
+        done.push(format!("Made csfm the default handler for {}", FOLDER_MIME));
+    }
+
+    Ok(done)
+}
+
+fn write_desktop_file(path: &Path, exec: &Path) -> io::Result<Vec<String>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(path, desktop_entry(exec).as_bytes())?;
+
+    let mut done = vec![format!("Wrote {}", path.display())];
+    done.extend(update_database(path));
+    Ok(done)
+}
+
+/// Removes the desktop entry, and the default handler association if it points at it.
+pub fn uninstall() -> io::Result<Vec<String>> {
+    remove_desktop_file(&desktop_file_path(), &paths::config_home().join("mimeapps.list"))
+}
+
+fn remove_desktop_file(path: &Path, mimeapps: &Path) -> io::Result<Vec<String>> {
+    let mut done = vec![];
+    match fs::remove_file(path) {
+        Ok(()) => done.push(format!("Removed {}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => done.push(format!("{} wasn't installed", path.display())),
+        Err(e) => return Err(e),
+    }
+    done.extend(update_database(path));
+
+    if let Ok(data) = fs::read_to_string(mimeapps)
+        && let Some(edited) = without_association(&data)
+    {
+        write_atomic(mimeapps, edited.as_bytes())?;
+        done.push(format!("Removed the default handler association from {}", mimeapps.display()));
+    }
+
+    Ok(done)
+}
+
+/// `mimeapps.list` without csfm among the folder handlers, or None when it isn't one.
+/// Handlers are a `;` separated list, so others in it are kept, and the line goes when
+/// csfm was the only one.
+fn without_association(data: &str) -> Option<String> {
+    let mut changed = false;
+    let mut kept = vec![];
+    for line in data.lines() {
+        let handlers = line.split_once('=').filter(|(mime, _)| mime.trim() == FOLDER_MIME).map(|(_, handlers)| handlers);
+        let Some(handlers) = handlers.filter(|h| h.split(';').any(|h| h.trim() == DESKTOP_FILE_NAME)) else {
+            kept.push(line.to_string());
+            continue;
+        };
+
+        changed = true;
+        let others: Vec<&str> = handlers.split(';').map(str::trim).filter(|h| !h.is_empty() && *h != DESKTOP_FILE_NAME).collect();
+        if !others.is_empty() {
+            kept.push(format!("{}={};", FOLDER_MIME, others.join(";")));
+        }
+    }
+
+    changed.then(|| kept.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+// The database is a cache. Without the tool the entry is still found, just later.
+fn update_database(desktop_file: &Path) -> Option<String> {
+    let dir = desktop_file.parent()?;
+    match Command::new("update-desktop-database").arg(dir).status() {
+        Ok(status) if status.success() => Some(format!("Updated the desktop database in {}", dir.display())),
+        Ok(status) => Some(format!("update-desktop-database exited with {}", status)),
+        Err(e) => Some(format!("Couldn't run update-desktop-database: {}", e)),
+    }
+}
+
+/// Turns a command line argument into a path. `file://` URIs are percent-decoded, other
+/// URI schemes give None, and anything else is taken as a path.
+pub fn arg_to_path(arg: &OsStr) -> Option<PathBuf> {
+    let bytes = arg.as_bytes();
+    let Some(rest) = bytes.strip_prefix(b"file://") else {
+        let has_scheme = bytes
+            .iter()
+            .position(|&b| b == b':')
+            .is_some_and(|i| i > 0 && bytes[..i].iter().all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(b)) && bytes[i..].starts_with(b"://"));
+        return (!has_scheme).then(|| PathBuf::from(arg));
+    };
+
+    // Only local files: an empty host or localhost
+    let rest = rest.strip_prefix(b"localhost").unwrap_or(rest);
+    if !rest.starts_with(b"/") {
+        return None;
+    }
+    let rest = rest.split(|&b| b == b'?' || b == b'#').next().unwrap_or(rest);
+
+    // Paths can't hold a NUL byte, `%00` would cut them short
+    let path = percent_decode(rest);
+    (!path.contains(&0)).then(|| PathBuf::from(OsString::from_vec(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csfm_core::test_util::ScratchDir;
+
+    fn path(arg: &str) -> Option<PathBuf> {
+        arg_to_path(OsStr::new(arg))
+    }
+
+    #[test]
+    fn reads_file_uris_and_paths() {
+        assert_eq!(path("file:///home/u/My%20Files"), Some(PathBuf::from("/home/u/My Files")));
+        assert_eq!(path("file://localhost/tmp"), Some(PathBuf::from("/tmp")));
+        assert_eq!(path("file:///tmp/a%23b?query#fragment"), Some(PathBuf::from("/tmp/a#b")));
+        assert_eq!(path("file:///tmp/%FF"), Some(PathBuf::from(OsString::from_vec(b"/tmp/\xff".to_vec()))));
+        assert_eq!(path("relative/dir"), Some(PathBuf::from("relative/dir")));
+        assert_eq!(path("a:b"), Some(PathBuf::from("a:b")));
+
+        assert_eq!(path("file://server/share"), None);
+        assert_eq!(path("https://example.com/"), None);
+        assert_eq!(path("sftp://host/home"), None);
+    }
+
+    #[test]
+    fn rejects_nul_bytes_in_uris() {
+        assert_eq!(path("file:///tmp/a%00b"), None);
+        assert_eq!(path("file:///%00"), None);
+    }
+
+    #[test]
+    fn quotes_exec_arguments() {
+        assert_eq!(exec_arg("/usr/bin/csfm"), "/usr/bin/csfm");
+        assert_eq!(exec_arg("/opt/my apps/csfm"), "\"/opt/my apps/csfm\"");
+        assert_eq!(exec_arg("/opt/$x/100%"), "\"/opt/\\\\$x/100%%\"");
+        assert_eq!(exec_arg("/a\\b"), "\"/a\\\\\\\\b\"");
+    }
+
+    #[test]
+    fn install_then_uninstall() {
+        let dir = ScratchDir::new("install");
+        let desktop_file = dir.join("applications").join(DESKTOP_FILE_NAME);
+        let mimeapps = dir.join("mimeapps.list");
+
+        write_desktop_file(&desktop_file, Path::new("/opt/csfm/csfm")).unwrap();
+        let entry = fs::read_to_string(&desktop_file).unwrap();
+        assert!(entry.contains("Exec=/opt/csfm/csfm %U\n"));
+        assert!(entry.contains("MimeType=inode/directory;\n"));
+
+        fs::write(&mimeapps, "[Default Applications]\ninode/directory=csfm.desktop;\ntext/plain=gedit.desktop;\n").unwrap();
+        let done = remove_desktop_file(&desktop_file, &mimeapps).unwrap();
+        assert!(!desktop_file.exists());
+        assert!(done.iter().any(|line| line.starts_with("Removed the default handler association")));
+        assert_eq!(fs::read_to_string(&mimeapps).unwrap(), "[Default Applications]\ntext/plain=gedit.desktop;\n");
+
+        // Nothing left to remove
+        let done = remove_desktop_file(&desktop_file, &mimeapps).unwrap();
+        assert!(done[0].ends_with("wasn't installed"));
+        assert!(!done.iter().any(|line| line.starts_with("Removed the default handler association")));
+    }
+
+    #[test]
+    fn uninstall_keeps_other_folder_handlers() {
+        let list = "[Default Applications]\ninode/directory=a.desktop;csfm.desktop;\n\n[Added Associations]\ninode/directory = csfm.desktop;b.desktop\n";
+        assert_eq!(
+            without_association(list).unwrap(),
+            "[Default Applications]\ninode/directory=a.desktop;\n\n[Added Associations]\ninode/directory=b.desktop;\n"
+        );
+
+        assert_eq!(without_association("inode/directory=csfm.desktop\n").unwrap(), "");
+        assert_eq!(without_association("inode/directory=a.desktop;notcsfm.desktop;\ntext/plain=csfm.desktop;\n"), None);
+    }
+}
@@ -1,369 +1,6828 @@
-use std::{ffi::OsStr, fmt::Error, fs, io, iter, path::{Path, PathBuf}, process::Command, vec};
+use std::{collections::HashSet, ffi::{OsStr, OsString}, fs, io, os::unix::ffi::{OsStrExt, OsStringExt}, path::{Path, PathBuf}, process::Command, vec};
 
 use iced::{
-    self, Alignment, Background, Border, Element, Length, Task, Theme, advanced::graphics::{core::Element as CoreElement, text::cosmic_text::ttf_parser::loca}, border::Radius, widget::{button::{self, Style}, column, container, row, scrollable, text, text_input}, window::Id
+    self, Alignment, Background, Border, Element, Length, Subscription, Task, Theme, event, keyboard, border::Radius, widget::{button::Style, center, checkbox, column, container, mouse_area, opaque, pick_list, row, scrollable, stack, text, text_input, tooltip}, window::Id
 };
-use iced_aw::{ContextMenu, DropDown, Menu, MenuBar, context_menu, drop_down, menu::Item};
-use serde::Deserialize;
+use iced_aw::{DropDown, context_menu};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use unicode_normalization::UnicodeNormalization;
+
+use csfm_core::{
+    listing::{FileEntry, InodeFlags, Listing, SortKeys, SortMode, SortOrder, WalkOptions, WalkSummary, folded_name, get_files, normalized_name, sort_by_name, sort_files, walk},
+    mounts::{self, on_network_filesystem},
+    ops::{create_item, delete, describe_io_error, numbered_name, parse_mode, remove_if_empty, rename_item, replace_item, write_atomic},
+    oplog, paths,
+    transfer::{self, Outcome, Refusal, Transfer, run_transfer, space_shortfall},
+    trash,
+    uri::{percent_decode, percent_encode},
+};
+
+mod desktop;
 
 #[derive(Debug, Clone)]
 enum Message {
     PathChanged(String),
     CDToPath,
+    FilesLoaded(PathBuf, bool, Result<Listing, String>),
     CD(PathBuf),
     QuitApp(Option<Id>),
     Open(PathBuf),
+    OpenInEditor(PathBuf, Option<usize>),
+    CopyDataUri(PathBuf),
+    DiffWithClipboard(PathBuf),
+    DiffWithClipboardContents(PathBuf, Option<String>),
+    WelcomeTheme(Theme),
+    WelcomeHiddenFiles(bool),
+    WelcomeAddBookmark(String, PathBuf),
+    WelcomeFinish,
+    WelcomeUseDefaults,
+    DismissConfigIssues,
+    AddBookmark(PathBuf),
+    RemoveBookmark(usize),
+    OpenBookmark(usize),
+    SaveBookmarkView(usize),
+    ExportBookmarks,
+    ImportBookmarks(bool),
     DeleteFile(PathBuf),
     DeleteDir(PathBuf),
+    FollowExternalLink(PathBuf),
     ToggleSidebar,
+    ToggleFullPaths,
+    ToggleTreeView,
+    ToggleHighlightRecent,
+    /// Redraws the list so changes that aged out of the recent window lose their highlight
+    RecentChangesTick,
+    /// Clears the toast once it has been shown long enough
+    ToastTick,
+    ToggleCrumbMenu,
+    FilterChanged(String),
+    Back,
+    Forward,
+    FocusFilter,
+    SetSort(SortMode),
+    /// A click on a column header: sorts by it, or reverses the order when it is already used
+    SortByColumn(SortMode),
+    ToggleSortDirection,
+    ToggleMixFolders,
+    /// Re-read the current directory, and with `true` stop boosting the entries changed so far
+    Refresh(bool),
+    ToggleTreeNode(PathBuf),
+    TreeNodeLoaded(PathBuf, Result<Listing, String>),
+    TreeLoadAnyway(PathBuf),
+    AddNetworkLocation,
+    OpenNetworkLocation(usize),
+    /// The location's uri and its folder, or why it couldn't be opened
+    NetworkLocationOpened(String, Result<PathBuf, String>),
+    MoveBookmark(usize, usize),
+    SubmitPath,
+    ToggleHistory,
+    /// Alt+Down, which opens the history only from the path input
+    HistoryShortcut,
+    CloseHistory,
+    HistoryMove(isize),
+    HistoryCommit,
+    ReloadSidebar,
+    ReloadConfig,
+    OpenSmartFolder(usize),
+    SmartFolderLoaded(usize, Vec<FileEntry>, WalkSummary),
+    SearchInput(String),
+    Search(String),
+    /// Results of the search started as that generation
+    SearchResults(u64, Vec<FileEntry>, WalkSummary),
+    CheckBookmarks,
+    BookmarksChecked(Vec<String>),
+    OpenMissingBookmark(PathBuf),
+    HoverEntry(Option<PathBuf>),
+    /// A click on an entry, with what opening it does
+    SelectEntry(PathBuf, Box<Message>),
+    ModifiersChanged(keyboard::Modifiers),
+    CopySelection,
+    CutSelection,
+    TrashSelection,
+    DeleteSelection,
+    HoverSettled(PathBuf),
+    /// The folder shown when the parent prefetch was scheduled
+    PrefetchParentDue(PathBuf),
+    FileListScrolled(ListView),
+    TooltipDue(PathBuf),
+    Prefetched(PathBuf, bool, Option<Listing>),
+    RevealHidden,
+    OpenInNewWindow(PathBuf),
+    ChooseTransferTarget(Transfer, Vec<PathBuf>),
+    ShowProperties(PathBuf),
+    ToggleAclSection,
+    UseModeAsDefault(bool, u32),
+    GoToLinkTarget(PathBuf, bool),
+    OpenLarge(PathBuf, LargeFileAction),
+    LargeFileRemember(bool),
+    ChooserCD(PathBuf),
+    ChooserConfirm,
+    CopyPath(PathBuf),
+    CutPath(PathBuf),
+    Paste,
+    ShowOperationLog,
+    OperationLogFilter(String),
+    OperationLogDate(String),
+    OpenContainingFolder(PathBuf),
+    QuickLook,
+    NewDir,
+    WindowFocused,
+    CheckCurrentDir,
+    Trash(PathBuf),
+    Restore(PathBuf),
+    NewFile,
+    StartRename(PathBuf),
+    RenameInput(String),
+    Rename(PathBuf, String),
+    QuickLookStep(isize),
+    StartTransfer(Transfer, PathBuf, Vec<(PathBuf, PathBuf)>, Option<(u64, u64)>),
+    TransferDone(usize, Outcome),
+    ResumeTransfer(usize),
+    CancelTransfer(usize),
+    ToggleMenu(TopMenu),
+    CloseMenu,
+    MenuSelect(Box<Message>),
+    ShowModal(Modal),
+    CloseModal,
+    CopyToClipboard(String),
+    Escape,
+    KeyPressed(keyboard::Key, keyboard::Modifiers, event::Status, Id),
     Up,
-    None,
 }
 
-struct CsFM {
-    config: Config,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TopMenu {
+    File,
+    View,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Modal {
+    About,
+    Shortcuts,
+    ChooseFolder,
+    Properties,
+    LargeFile,
+    Preview,
+    OperationLog,
+}
+
+/// Results of a walk below the current directory, shown in place of its listing.
+#[derive(Debug, Clone, PartialEq)]
+enum WalkView {
+    /// Index into the configured smart folders
+    SmartFolder(usize),
+    /// Entries whose name contains the query
+    Search(String),
+}
+
+/// How full paths are shown, in search results and smart folders or with `show_full_paths`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PathStyle {
+    /// From the folder being listed or searched, e.g. `photos/2024/a.jpg`
+    #[default]
+    Relative,
+    Absolute,
+}
+
+/// What to do with a file too large to hand to its default application without asking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LargeFileAction {
+    OpenAnyway,
+    Preview,
+    Pager,
+}
+
+struct LargeFilePrompt {
     path: PathBuf,
-    current_files: Vec<(PathBuf, bool)>,
-    sidebar_open: bool
+    size: u64,
+    /// Reuse the choice for files with the same extension this session
+    remember: bool
 }
 
-#[derive(Clone, Deserialize, Default)]
-struct Config {
-    pub theme: String,
-    pub show_hidden_files: bool,
-    pub sidebar_loc: Vec<Location>
+/// The operation log as shown in its dialog.
+struct OperationLogView {
+    /// Newest first
+    operations: Vec<oplog::Operation>,
+    /// Path substring to look for
+    filter: String,
+    /// Date prefix such as `2024-05` or `2024-05-17`
+    date: String
 }
 
-#[derive(Clone, Deserialize)]
-struct Location {
-    pub title: String,
-    pub path: String
+/// The start of a file, shown inside csfm.
+struct Preview {
+    path: PathBuf,
+    contents: String,
+    /// Shown as a hex dump because the file looked binary
+    hex: bool,
+    truncated: bool,
+    /// Opened with Space, filling most of the window and stepping through the listing
+    quick_look: bool
 }
 
-fn theme(_state: &CsFM) -> Theme {
-    Theme::GruvboxDark
+/// A copy, move or trashing running in the background
+struct RunningTransfer {
+    id: usize,
+    transfer: Transfer,
+    sources: Vec<PathBuf>,
+    dest: PathBuf,
+    /// What is left after the destination filled up. The transfer waits until it is
+    /// resumed or cancelled.
+    stalled: Vec<(PathBuf, PathBuf)>
 }
 
-fn question_zenity(question: String) -> bool {
-    let out = Command::new("zenity")
-        .arg("--question")
-        .arg("--title=CsFM")
-        .arg(format!("--text={}", question))
-        .output();
+/// State of the folder chooser dialog used by "Copy to…" and "Move to…".
+struct FolderChooser {
+    transfer: Transfer,
+    sources: Vec<PathBuf>,
+    dir: PathBuf,
+    subdirs: Vec<PathBuf>
+}
 
-    match out {
-        Ok(o) => {
-            return o.status.success();
-        }
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            error_zenity(format!("Error: {}", e));
-            return false;
-        }
-    }
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BindingCategory {
+    Navigation,
+    View,
+    FileOperations,
+    Application,
 }
 
-fn error_zenity(message: String) -> bool {
-    let out = Command::new("zenity")
-        .arg("--error")
-        .arg("--title=CsFM")
-        .arg(format!("--text={}", message))
-        .output();
+impl BindingCategory {
+    const ALL: [BindingCategory; 4] =
+        [BindingCategory::Navigation, BindingCategory::View, BindingCategory::FileOperations, BindingCategory::Application];
 
-    match out {
-        Ok(o) => {
-            return o.status.success();
-        }
-        Err(e) => {
-            println!("Error: {}", e);
-            return false;
+    fn title(self) -> &'static str {
+        match self {
+            BindingCategory::Navigation => "Navigation",
+            BindingCategory::View => "View",
+            BindingCategory::FileOperations => "File operations",
+            BindingCategory::Application => "Application",
         }
     }
 }
 
-fn update(state: &mut CsFM, message: Message) -> Task<Message> {
-    match message {
-        Message::None => {
-            Task::none()
-        }
-        Message::PathChanged(s) => {
-            let path = PathBuf::from(s);
-            let path_str = path.clone().to_string_lossy().to_string();
-            state.path = path;
-            Task::none()
-        }
-        Message::CDToPath => {
-            let files = get_files(PathBuf::from(&state.path), state.config.show_hidden_files);
-            
-            if !files.is_empty() {
-                state.current_files = files.clone();
-            }
+#[derive(Debug, Clone, PartialEq)]
+struct Shortcut {
+    key: keyboard::Key,
+    modifiers: keyboard::Modifiers,
+    /// Skip the shortcut while a text input has consumed the key
+    unfocused_only: bool,
+}
 
-            Task::none()
-        }
-        Message::Up => {
-            state.path = state.path.parent().unwrap_or(PathBuf::from("/").as_path()).to_path_buf();
+impl Shortcut {
+    fn matches(&self, key: keyboard::Key<&str>, modifiers: keyboard::Modifiers) -> bool {
+        let key_matches = match (self.key.as_ref(), key) {
+            (keyboard::Key::Character(a), keyboard::Key::Character(b)) => a.eq_ignore_ascii_case(b),
+            (a, b) => a == b,
+        };
 
-            Task::done(Message::CDToPath)
-        }
-        Message::DeleteFile(path) => {
-            let file_name = path.file_name().unwrap().to_string_lossy().to_string();
-            let out = question_zenity(format!("Delete '{}'?", file_name));
-            if out {
-                if let Err(e) = std::fs::remove_file(&path) {
-                    error_zenity(format!("Failed to delete: {}", e));
-                }
-            }
-            Task::done(Message::CDToPath)
-        }
-        Message::DeleteDir(path) => {
-            let file_name = path.file_name().unwrap().to_string_lossy().to_string();
-            let out = question_zenity(format!("Delete '{}' and all contents?", file_name));
-            if out {
-                if let Err(e) = std::fs::remove_dir_all(&path) {
-                    error_zenity(format!("Failed to delete dir: {}", e));
-                }
-            }
-            Task::done(Message::CDToPath)
-        }
-        Message::Open(path) => {
-            open::that_detached(path).unwrap();
-            
-            Task::none()
-        }
-        Message::CD(path) => {
-            state.path = path;
+        key_matches && self.modifiers == modifiers
+    }
 
-            Task::done(Message::CDToPath)
-        }
-        Message::QuitApp(id) => {
-            iced::window::close(id.unwrap())
-        }
-        Message::ToggleSidebar => {
-            state.sidebar_open = !state.sidebar_open;
+    /// Human readable chord such as "Ctrl+Shift+N".
+    fn chord(&self) -> String {
+        let mut parts = vec![];
+        if self.modifiers.control() { parts.push("Ctrl".to_string()); }
+        if self.modifiers.alt() { parts.push("Alt".to_string()); }
+        if self.modifiers.shift() { parts.push("Shift".to_string()); }
+        if self.modifiers.logo() { parts.push("Super".to_string()); }
 
-            Task::none()
+        parts.push(match self.key.as_ref() {
+            keyboard::Key::Character(c) => c.to_uppercase(),
+            keyboard::Key::Named(keyboard::key::Named::ArrowUp) => "Up".to_string(),
+            keyboard::Key::Named(keyboard::key::Named::ArrowDown) => "Down".to_string(),
+            keyboard::Key::Named(keyboard::key::Named::ArrowLeft) => "Left".to_string(),
+            keyboard::Key::Named(keyboard::key::Named::ArrowRight) => "Right".to_string(),
+            keyboard::Key::Named(keyboard::key::Named::Escape) => "Esc".to_string(),
+            keyboard::Key::Named(named) => format!("{:?}", named),
+            keyboard::Key::Unidentified => "?".to_string(),
+        });
+
+        parts.join("+")
+    }
+
+    /// Reads a chord the way `chord()` writes it, with modifiers in any order and case.
+    fn parse_chord(chord: &str) -> Option<(keyboard::Key, keyboard::Modifiers)> {
+        use keyboard::{Key, Modifiers, key::Named};
+
+        const NAMED_KEYS: &[(&str, Named)] = &[
+            ("Up", Named::ArrowUp), ("Down", Named::ArrowDown), ("Left", Named::ArrowLeft), ("Right", Named::ArrowRight),
+            ("Esc", Named::Escape), ("Escape", Named::Escape), ("Enter", Named::Enter), ("Tab", Named::Tab),
+            ("Space", Named::Space), ("Backspace", Named::Backspace), ("Delete", Named::Delete), ("Insert", Named::Insert),
+            ("Home", Named::Home), ("End", Named::End), ("PageUp", Named::PageUp), ("PageDown", Named::PageDown),
+            ("F1", Named::F1), ("F2", Named::F2), ("F3", Named::F3), ("F4", Named::F4), ("F5", Named::F5), ("F6", Named::F6),
+            ("F7", Named::F7), ("F8", Named::F8), ("F9", Named::F9), ("F10", Named::F10), ("F11", Named::F11), ("F12", Named::F12),
+        ];
+
+        let chord = chord.trim();
+        // "Ctrl++" is the plus key
+        let (modifier_names, key) = match chord.strip_suffix("++") {
+            Some(rest) => (rest, "+"),
+            None => chord.rsplit_once('+').unwrap_or(("", chord)),
+        };
+
+        let mut modifiers = Modifiers::empty();
+        for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            modifiers |= match name.trim().to_lowercase().as_str() {
+                "ctrl" | "control" => Modifiers::CTRL,
+                "alt" => Modifiers::ALT,
+                "shift" => Modifiers::SHIFT,
+                "super" | "logo" | "meta" => Modifiers::LOGO,
+                _ => return None,
+            };
         }
+
+        let key = key.trim();
+        let key = match NAMED_KEYS.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)) {
+            Some((_, named)) => Key::Named(*named),
+            None if key.chars().count() == 1 => Key::Character(key.to_lowercase().into()),
+            None => return None,
+        };
+        Some((key, modifiers))
     }
 }
 
-fn dir_button(state: &'_ CsFM) -> iced::widget::button::Style {
-    let theme = theme(state);
-    
-    Style {
-        border: Border {
-            color: theme.palette().primary,
-            width: 2.0,
-            radius: Radius::new(10.0)
-        },
-        text_color: theme.extended_palette().primary.strong.color,
-        ..Default::default()
-    }
+/// What an action works on, which decides where it is offered.
+enum Target {
+    /// The window as a whole, from the keyboard
+    Window(fn(Id) -> Message),
+    /// The folder shown, from the keyboard and the context menu of the list background
+    Folder { enabled: fn(&CsFM) -> bool, message: fn() -> Message },
+    /// A file list entry from its context menu, or the selected entries from the keyboard
+    Entry(EntryAction),
 }
 
-fn file_button(state: &'_ CsFM) -> iced::widget::button::Style {
-    let theme = theme(state);
-    
-    Style {
-        border: Border {
-            color: theme.extended_palette().secondary.strong.color,
-            width: 2.0,
-            radius: Radius::new(10.0)
-        },
-        text_color: theme.extended_palette().secondary.strong.color,
-        ..Default::default()
-    }
+/// Something the user can do. The context menus, keyboard handling and the F1 cheat sheet
+/// all read from `actions()`, so a new action only needs to be added once.
+struct Action {
+    category: BindingCategory,
+    label: &'static str,
+    shortcut: Option<Shortcut>,
+    /// Whether the shortcut applies right now, otherwise the key is left to other actions
+    active: fn(&CsFM) -> bool,
+    target: Target,
 }
 
-fn container_style(theme: &Theme) -> iced::widget::container::Style {
-   iced::widget::container::Style { border: Border { color: theme.palette().primary, width: 5.0, radius: Radius::new(10) }, ..Default::default() } 
+/// How an entry action applies to file list entries.
+struct EntryAction {
+    /// Whether the menu offers the action for this entry
+    applies: fn(&CsFM, &FileEntry) -> bool,
+    message: fn(&FileEntry) -> Message,
+    /// Label, with `{}` for the count, and message when offered for several selected entries.
+    /// Offered when it applies to every one of them.
+    selection: Option<(&'static str, SelectionMessage)>
 }
 
-fn locations(state: &CsFM) -> Vec<Element<Message>> {
-    let mut locs = vec![
-        iced::widget::text("Places").into(),
-    ];
+/// Builds the message acting on the selected paths, in listing order.
+type SelectionMessage = fn(Vec<PathBuf>) -> Message;
 
-    for location in state.config.sidebar_loc.iter() {
-        locs.push(iced::widget::button(text(location.title.clone())).style(|t, s| dir_button(state)).on_press(Message::CD(PathBuf::from(PathBuf::from(location.path.clone())))).into());
+impl Action {
+    fn window(category: BindingCategory, label: &'static str, message: fn(Id) -> Message) -> Self {
+        Action { category, label, shortcut: None, active: |_| true, target: Target::Window(message) }
     }
 
-    locs
+    fn folder(label: &'static str, enabled: fn(&CsFM) -> bool, message: fn() -> Message) -> Self {
+        let target = Target::Folder { enabled, message };
+        Action { category: BindingCategory::FileOperations, label, shortcut: None, active: |_| true, target }
+    }
+
+    fn entry(label: &'static str, applies: fn(&CsFM, &FileEntry) -> bool, message: fn(&FileEntry) -> Message) -> Self {
+        let target = Target::Entry(EntryAction { applies, message, selection: None });
+        Action { category: BindingCategory::FileOperations, label, shortcut: None, active: |_| true, target }
+    }
+
+    fn shortcut(mut self, key: keyboard::Key, modifiers: keyboard::Modifiers) -> Self {
+        self.shortcut = Some(Shortcut { key, modifiers, unfocused_only: false });
+        self
+    }
+
+    fn unfocused_only(mut self) -> Self {
+        if let Some(shortcut) = self.shortcut.as_mut() {
+            shortcut.unfocused_only = true;
+        }
+        self
+    }
+
+    fn active_when(mut self, active: fn(&CsFM) -> bool) -> Self {
+        self.active = active;
+        self
+    }
+
+    fn for_selection(mut self, label: &'static str, message: SelectionMessage) -> Self {
+        if let Target::Entry(action) = &mut self.target {
+            action.selection = Some((label, message));
+        }
+        self
+    }
 }
 
-fn context_menu_container_style(theme: &Theme) -> iced::widget::container::Style {
-   iced::widget::container::Style { border: Border { color: theme.palette().primary, width: 5.0, radius: Radius::new(10) }, background: Option::from(Background::Color(theme.palette().background)), ..Default::default() } 
+/// Whether `name` from the `keybindings` config names the action labelled `label`.
+fn names_action(name: &str, label: &str) -> bool {
+    name.trim().trim_end_matches('…').eq_ignore_ascii_case(label.trim_end_matches('…'))
 }
 
-fn view(state: &CsFM) -> Element<'_, Message> {
-    // ----- FILE LIST -----
-    let files: Vec<Element<Message>> = state
-        .current_files
-        .iter()
-        .map(|f| {
-            let name = f
-                .0
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_string();
-            
-            if f.1 {
-                // Directory
-                let btn = iced::widget::button(text(name))
-                    .style(|_, _| dir_button(state))
-                    .on_press(Message::CD(f.0.clone()));
-                context_menu::ContextMenu::new(btn, || container(column![iced::widget::button(text("Open")).on_press(Message::CD(f.0.clone())),
-                    iced::widget::button(text("Delete")).on_press(Message::DeleteDir(f.0.clone()))].spacing(5)).style(context_menu_container_style).padding(10).into()).into()
-            } else {
-                // File
-                let btn = iced::widget::button(text(name))
-                    .style(|_, _| file_button(state))
-                    .on_press(Message::Open(f.0.clone()));
-                context_menu::ContextMenu::new(btn, || container(column![
-                    iced::widget::button(text("Open")).on_press(Message::Open(f.0.clone())),
-                    iced::widget::button(text("Delete")).on_press(Message::DeleteFile(f.0.clone()))
-                ].spacing(5)).style(context_menu_container_style).padding(10).into() ).into()
+/// `actions()` with the shortcuts from the `keybindings` config in place of the built-in
+/// ones. An action whose chord is taken by an override loses its shortcut.
+fn bound_actions(config: &Config) -> Vec<Action> {
+    let mut actions = actions();
+    // Taken before any override, which may take another action's shortcut away
+    let unfocused_only: Vec<bool> = actions.iter().map(|a| a.shortcut.as_ref().is_none_or(|s| s.unfocused_only)).collect();
+    for (name, chord) in &config.keybindings {
+        let Some(i) = actions.iter().position(|a| names_action(name, a.label)) else {
+            continue;
+        };
+        actions[i].shortcut = None;
+
+        let Some((key, modifiers)) = Shortcut::parse_chord(chord) else {
+            continue;
+        };
+        for other in actions.iter_mut() {
+            if other.shortcut.as_ref().is_some_and(|s| s.matches(key.as_ref(), modifiers)) {
+                other.shortcut = None;
             }
-        })
-        .collect();
+        }
+        // Keys that type something must not fire while a text input has them
+        let types = !modifiers.control() && !modifiers.alt() && !modifiers.logo();
+        actions[i].shortcut = Some(Shortcut { key, modifiers, unfocused_only: unfocused_only[i] || types });
+    }
 
-    let file_list = container(
-        scrollable(
-            column(files)
-                .spacing(5)
-                .padding(5)
-        )
-        .width(Length::Fill)
-        .height(Length::Fill)
-    )
-    .style(container_style)
-    .padding(5);
+    actions
+}
 
-    // ----- SIDEBAR -----
-    let mut main_view = row![].padding(5).spacing(5);
+/// The action a key press triggers. `captured` is whether a widget, such as a focused text
+/// input, already handled it.
+fn pressed_action<'a>(actions: &'a [Action], state: &CsFM, key: keyboard::Key<&str>, modifiers: keyboard::Modifiers, captured: bool) -> Option<&'a Action> {
+    actions.iter().find(|a| {
+        a.shortcut.as_ref().is_some_and(|s| s.matches(key.clone(), modifiers) && !(s.unfocused_only && captured)) && (a.active)(state)
+    })
+}
 
-    if state.sidebar_open {
-        let sidebar =
-            column![]
-                .extend(locations(state))  // <── FIXED HERE
-                .padding(5)
-                .spacing(5);
+/// The chord shown next to the menu item of the action labelled `label`.
+fn chord_for(actions: &[Action], label: &str) -> String {
+    actions.iter().find(|a| a.label == label).and_then(|a| a.shortcut.as_ref()).map(Shortcut::chord).unwrap_or_default()
+}
 
-        main_view = main_view.push(
-            container(sidebar)
-                .padding(5)
-                .style(container_style)
-                .width(150)
-                .height(Length::Fill),
-        );
-    }
+/// Every action, in menu order within each kind.
+fn actions() -> Vec<Action> {
+    use keyboard::{Key, Modifiers, key::Named};
+    use BindingCategory::*;
 
-    // Push FILE LIST into main_view
-    main_view = main_view.push(file_list);
+    let any = |_: &CsFM, _: &FileEntry| true;
+    let dir = |_: &CsFM, f: &FileEntry| f.is_dir;
+    let file = |_: &CsFM, f: &FileEntry| !f.is_dir;
+    let listing = |state: &CsFM| state.walk_view.is_none();
+    let character = |c: &str| Key::Character(c.into());
+    let history_open = |state: &CsFM| state.history_open;
+
+    vec![
+        Action::window(Navigation, "Back", |_| Message::Back).shortcut(Key::Named(Named::ArrowLeft), Modifiers::ALT),
+        Action::window(Navigation, "Forward", |_| Message::Forward).shortcut(Key::Named(Named::ArrowRight), Modifiers::ALT),
+        Action::window(Navigation, "Go to home folder", |_| Message::CD(paths::home())).shortcut(Key::Named(Named::Home), Modifiers::ALT),
+        Action::window(Navigation, "Go to parent folder", |_| Message::Up).shortcut(Key::Named(Named::ArrowUp), Modifiers::ALT),
+        Action::window(Navigation, "Show path history", |_| Message::HistoryShortcut).shortcut(Key::Named(Named::ArrowDown), Modifiers::ALT),
+        Action::window(Navigation, "Next history entry", |_| Message::HistoryMove(1))
+            .shortcut(Key::Named(Named::ArrowDown), Modifiers::empty())
+            .active_when(history_open),
+        Action::window(Navigation, "Previous history entry", |_| Message::HistoryMove(-1))
+            .shortcut(Key::Named(Named::ArrowUp), Modifiers::empty())
+            .active_when(history_open),
+        // A focused path input already turns Enter into SubmitPath
+        Action::window(Navigation, "Open history entry", |_| Message::HistoryCommit).shortcut(Key::Named(Named::Enter), Modifiers::empty()).unfocused_only(),
+        Action::window(View, "Quick look at the file under the pointer", |_| Message::QuickLook).shortcut(Key::Named(Named::Space), Modifiers::empty()).unfocused_only(),
+        Action::window(View, "Previous file in quick look", |_| Message::QuickLookStep(-1)).shortcut(Key::Named(Named::ArrowLeft), Modifiers::empty()).unfocused_only(),
+        Action::window(View, "Next file in quick look", |_| Message::QuickLookStep(1)).shortcut(Key::Named(Named::ArrowRight), Modifiers::empty()).unfocused_only(),
+        Action::window(View, "Filter the current folder", |_| Message::FocusFilter).shortcut(character("/"), Modifiers::empty()).unfocused_only(),
+        Action::window(View, "Refresh", |_| Message::Refresh(false)).shortcut(Key::Named(Named::F5), Modifiers::empty()),
+        Action::window(View, "Refresh and settle recent changes", |_| Message::Refresh(true)).shortcut(Key::Named(Named::F5), Modifiers::SHIFT),
+        Action::window(View, "Reload config", |_| Message::ReloadConfig).shortcut(character("r"), Modifiers::CTRL),
+        Action::window(Application, "Keyboard shortcuts", |_| Message::ShowModal(Modal::Shortcuts)).shortcut(Key::Named(Named::F1), Modifiers::empty()),
+        Action::window(Application, "Close menu or dialog", |_| Message::Escape).shortcut(Key::Named(Named::Escape), Modifiers::empty()),
+        Action::window(Application, "Quit", |id| Message::QuitApp(Some(id))).shortcut(character("q"), Modifiers::CTRL),
+        Action::folder("New folder…", listing, || Message::NewDir).shortcut(character("n"), Modifiers::CTRL | Modifiers::SHIFT),
+        Action::folder("New file…", listing, || Message::NewFile),
+        Action::folder("Paste", |state| state.clipboard.is_some() && state.walk_view.is_none(), || Message::Paste)
+            .shortcut(character("v"), Modifiers::CTRL)
+            .unfocused_only(),
+        Action::entry("Open", any, |f| if f.is_dir { Message::CD(f.path.clone()) } else { Message::Open(f.path.clone()) }),
+        Action::entry("Open containing folder", |state, _| state.walk_view.is_some(), |f| Message::OpenContainingFolder(f.path.clone())),
+        Action::entry("Open in new window", |state, f| f.is_dir && !state.config.safe_mode, |f| Message::OpenInNewWindow(f.path.clone())),
+        Action::entry(
+            "Open in editor",
+            |state, f| !f.is_dir && state.config.editor.is_some() && !state.config.safe_mode,
+            |f| Message::OpenInEditor(f.path.clone(), None),
+        ),
+        Action::entry("Go to link target", |_, f| f.is_symlink, |f| Message::GoToLinkTarget(f.path.clone(), false)),
+        Action::entry("Go to final link target", |_, f| f.is_symlink, |f| Message::GoToLinkTarget(f.path.clone(), true)),
+        Action::entry("Add to bookmarks", dir, |f| Message::AddBookmark(f.path.clone())),
+        Action::entry("Copy as data URI", file, |f| Message::CopyDataUri(f.path.clone())),
+        Action::entry(
+            "Compare with clipboard",
+            |state, f| !f.is_dir && !state.config.safe_mode,
+            |f| Message::DiffWithClipboard(f.path.clone()),
+        ),
+        Action::entry("Rename", |state, _| state.walk_view.is_none(), |f| Message::StartRename(f.path.clone()))
+            .shortcut(Key::Named(Named::F2), Modifiers::empty())
+            .unfocused_only(),
+        Action::entry("Copy", any, |f| Message::CopyPath(f.path.clone()))
+            .for_selection("Copy {} items", |_| Message::CopySelection)
+            .shortcut(character("c"), Modifiers::CTRL)
+            .unfocused_only(),
+        Action::entry("Cut", any, |f| Message::CutPath(f.path.clone()))
+            .for_selection("Cut {} items", |_| Message::CutSelection)
+            .shortcut(character("x"), Modifiers::CTRL)
+            .unfocused_only(),
+        Action::entry("Copy to…", any, |f| Message::ChooseTransferTarget(Transfer::Copy, vec![f.path.clone()]))
+            .for_selection("Copy {} items to…", |paths| Message::ChooseTransferTarget(Transfer::Copy, paths)),
+        Action::entry("Move to…", any, |f| Message::ChooseTransferTarget(Transfer::Move, vec![f.path.clone()]))
+            .for_selection("Move {} items to…", |paths| Message::ChooseTransferTarget(Transfer::Move, paths)),
+        Action::entry("Restore", |_, f| f.path.parent() == Some(trash::files_dir().as_path()), |f| Message::Restore(f.path.clone())),
+        Action::entry("Move to trash", |_, f| !f.path.starts_with(trash::trash_dir()), |f| Message::Trash(f.path.clone()))
+            .for_selection("Move {} items to trash", |_| Message::TrashSelection)
+            .shortcut(Key::Named(Named::Delete), Modifiers::empty())
+            .unfocused_only(),
+        Action::entry("Delete permanently", any, |f| if f.is_dir { Message::DeleteDir(f.path.clone()) } else { Message::DeleteFile(f.path.clone()) })
+            .for_selection("Delete {} items permanently", |_| Message::DeleteSelection)
+            .shortcut(Key::Named(Named::Delete), Modifiers::SHIFT)
+            .unfocused_only(),
+        Action::entry("Properties", any, |f| Message::ShowProperties(f.path.clone())),
+    ]
+}
 
+/// What pressing the shortcut of `action` does: window and folder actions act as they are,
+/// entry actions on the selected entries.
+fn shortcut_message(state: &CsFM, action: &Action, id: Id) -> Option<Message> {
+    match &action.target {
+        Target::Window(message) => Some(message(id)),
+        Target::Folder { enabled, message } => enabled(state).then(message),
+        Target::Entry(entry_action) => {
+            let entries: Vec<&FileEntry> = state.current_files.iter().filter(|f| state.selected.contains(&f.path)).collect();
+            if entries.is_empty() || !entries.iter().all(|f| (entry_action.applies)(state, f)) {
+                return None;
+            }
+            match (entries.as_slice(), entry_action.selection) {
+                ([entry], _) => Some((entry_action.message)(entry)),
+                (_, Some((_, message))) => Some(message(selection_in_order(state))),
+                _ => None,
+            }
+        }
+    }
+}
 
-    // ----- TOP BAR -----
-    let top_bar = container(
-        row![
-            iced::widget::button(if state.sidebar_open { "<" } else { ">" })
-                .on_press(Message::ToggleSidebar),
+struct CsFM {
+    config: Config,
+    path: PathBuf,
+    current_files: Vec<FileEntry>,
+    /// Entries of the current directory or search left out because they are hidden
+    hidden_count: usize,
+    /// Directory whose hidden files are shown or left out for this visit only, overriding
+    /// the global setting
+    hidden_override: Option<(PathBuf, bool)>,
+    sidebar_open: bool,
+    path_history: Vec<PathBuf>,
+    history_open: bool,
+    history_cursor: Option<usize>,
+    /// Directories visited in this window, for Back and Forward
+    nav_history: Vec<PathBuf>,
+    /// Position of the shown directory in `nav_history`
+    nav_index: usize,
+    /// Smart folder or search results shown instead of the listing of `path`
+    walk_view: Option<WalkView>,
+    /// What the last smart folder or search walk left out
+    walk_view_notice: Option<String>,
+    /// Text in the search field, searched for below the current directory on Enter
+    search_input: String,
+    /// Bumped by every search, so results of an earlier one are told apart even for the same query
+    search_generation: u64,
+    open_menu: Option<TopMenu>,
+    modal: Option<Modal>,
+    welcome: bool,
+    config_issues: Vec<ConfigIssue>,
+    listing_cache: ListingCache,
+    /// Row under the pointer. Directories are prefetched once the pointer settles on them.
+    hovered_entry: Option<PathBuf>,
+    /// Row showing its metadata tooltip
+    tooltip_entry: Option<PathBuf>,
+    prefetches_running: usize,
+    /// Bookmarked paths that weren't directories at the last check, e.g. unmounted drives
+    missing_bookmarks: Vec<String>,
+    /// Directory `current_files` was listed from, to tell a refresh from navigation
+    listed_path: Option<PathBuf>,
+    folder_chooser: Option<FolderChooser>,
+    properties: Option<Properties>,
+    /// Destination of the last copy or move this session
+    last_transfer_dir: Option<PathBuf>,
+    transfers: Vec<RunningTransfer>,
+    next_transfer_id: usize,
+    large_file: Option<LargeFilePrompt>,
+    /// Choices made for large files this session, by lowercase extension
+    large_file_choices: std::collections::HashMap<String, LargeFileAction>,
+    preview: Option<Preview>,
+    /// Items copied or cut for pasting
+    clipboard: Option<(Vec<PathBuf>, Transfer)>,
+    /// Entries in the current directory picked with clicks, for acting on several at once
+    selected: HashSet<PathBuf>,
+    /// Where a shift-click range starts
+    selection_anchor: Option<PathBuf>,
+    /// Part of the file list scrolled into view
+    file_list_view: Option<ListView>,
+    /// Entry last clicked and when, to tell a double-click
+    last_click: Option<(PathBuf, std::time::Instant)>,
+    /// Modifier keys currently held, for ctrl- and shift-clicks
+    modifiers: keyboard::Modifiers,
+    operation_log: Option<OperationLogView>,
+    /// The current directory is being read in the background
+    loading: bool,
+    /// A note about how csfm got to a folder, shown while that folder is open
+    folder_notice: Option<(PathBuf, String)>,
+    /// Entry whose name is being edited in place, and the edited name
+    renaming: Option<(PathBuf, String)>,
+    /// Item just created under a placeholder name, removed again if its rename is cancelled
+    placeholder: Option<PathBuf>,
+    /// Entry to point out in the listing, e.g. a link target that was navigated to
+    revealed_entry: Option<PathBuf>,
+    tree_view: bool,
+    /// Removable or network mount holding the current directory and its volume label
+    path_mount: Option<(PathBuf, String)>,
+    /// The dropdown with the ancestors hidden behind the first breadcrumb
+    crumb_menu_open: bool,
+    /// Only entries whose name contains this are shown
+    filter: String,
+    /// Mark entries changed in the last few minutes and list them first
+    highlight_recent: bool,
+    /// Changes before this no longer count as recent, set by Shift+F5
+    recent_settled_at: Option<std::time::SystemTime>,
+    /// Directories expanded in the tree view during this visit
+    tree: std::collections::HashMap<PathBuf, TreeNode>,
+    /// Short note about what was just done and when, shown for `TOAST_DURATION`
+    toast: Option<(String, std::time::Instant)>,
+    /// Uri of the network location being connected to
+    connecting: Option<String>
+}
 
-            iced::widget::button("Up")
-                .on_press(Message::Up),
+/// An expanded directory in the tree view.
+struct TreeNode {
+    /// None while loading
+    children: Option<Result<Listing, String>>,
+    /// Show the children even though there are more than `TREE_EXPAND_LIMIT`
+    load_anyway: bool
+}
 
-            text_input(
-                "Path",
-                &state.path.to_string_lossy().to_string()
-            )
-            .on_input(Message::PathChanged)
-            .on_submit(Message::CDToPath)
-            .padding(5),
-        ]
-        .padding(5)
-        .spacing(5)
-    )
-    .style(container_style)
-    .padding(5);
+const PATH_HISTORY_LEN: usize = 15;
+const DATA_URI_MAX_SIZE: u64 = 256 * 1024;
+const LARGE_FILE_THRESHOLD_MIB: u64 = 512;
+const LARGE_FILE_THRESHOLD_MAX_MIB: u64 = 1024 * 1024 * 1024;
+const RECENT_CHANGES_MINUTES: u64 = 10;
+const RECENT_CHANGES_MAX_MINUTES: u64 = 365 * 24 * 60;
+/// How often the recent highlights are brought up to date, so old changes drop out
+const RECENT_CHANGES_REFRESH: std::time::Duration = std::time::Duration::from_secs(30);
+const TOAST_DURATION: std::time::Duration = std::time::Duration::from_secs(4);
+const PREVIEW_TEXT_BYTES: usize = 64 * 1024;
+const PREVIEW_HEX_BYTES: usize = 16 * 1024;
+/// Levels below the current directory the tree view can expand
+const TREE_MAX_DEPTH: usize = 8;
+/// Children shown without asking when expanding a directory in the tree view
+const TREE_EXPAND_LIMIT: usize = 1000;
 
+/// Recently built listings, so going back to a directory doesn't hit the disk again.
+/// A listing is reused only while the directory's mtime is unchanged.
+#[derive(Default)]
+struct ListingCache {
+    listings: Vec<CachedListing>,
+    hits: usize,
+    misses: usize,
+    /// Hits on listings that were loaded by prefetching and not visited before
+    prefetch_hits: usize,
+    prefetches: usize
+}
 
-    // ----- FINAL LAYOUT -----
-    column![
-        top_bar,
-        main_view,
-    ]
-    .padding(5)
-    .into()
+struct CachedListing {
+    path: PathBuf,
+    show_hidden_files: bool,
+    modified: std::time::SystemTime,
+    listing: Listing,
+    prefetched: bool
 }
 
+const LISTING_CACHE_DIRS: usize = 16;
+const LISTING_CACHE_ENTRIES: usize = 50_000;
 
-fn get_files(path: PathBuf, show_hidden_files: bool) -> Vec<(PathBuf, bool)> {
-    let mut files_and_dirs = vec![];
+impl ListingCache {
+    fn get(&mut self, path: &Path, show_hidden_files: bool) -> Option<Listing> {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
 
-    let entries = match fs::read_dir(&path) {
-        Ok(e) => e,
-        Err(_) => return files_and_dirs,
-    };
+        let found = self
+            .listings
+            .iter_mut()
+            .find(|l| l.path == path && l.show_hidden_files == show_hidden_files && Some(l.modified) == modified);
 
-    for entry in entries {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
+        match found {
+            Some(listing) => {
+                self.hits += 1;
+                if listing.prefetched {
+                    listing.prefetched = false;
+                    self.prefetch_hits += 1;
+                }
+                Some(listing.listing.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
 
-        let file_name = entry.file_name().to_string_lossy().to_string();
+    fn contains(&self, path: &Path, show_hidden_files: bool) -> bool {
+        self.listings.iter().any(|l| l.path == path && l.show_hidden_files == show_hidden_files)
+    }
 
-        if !show_hidden_files && file_name.starts_with('.') {
-            continue;
-        }
+    fn stats(&self) -> String {
+        let lookups = (self.hits + self.misses).max(1);
+        format!(
+            "{} hits, {} misses ({}%), {} of {} prefetches used",
+            self.hits,
+            self.misses,
+            self.hits * 100 / lookups,
+            self.prefetch_hits,
+            self.prefetches
+        )
+    }
 
-        let p = entry.path();
-        let is_dir = p.is_dir();
+    fn insert(&mut self, path: &Path, show_hidden_files: bool, listing: &Listing) {
+        self.insert_listing(path, show_hidden_files, listing, false);
+    }
 
-        files_and_dirs.push((p, is_dir));
+    fn insert_prefetched(&mut self, path: &Path, show_hidden_files: bool, listing: &Listing) {
+        self.prefetches += 1;
+        self.insert_listing(path, show_hidden_files, listing, true);
     }
 
-    // ---- SORT HERE ----
-    files_and_dirs.sort_by(|a, b| {
-        match (a.1, b.1) {
-            (true, false) => std::cmp::Ordering::Less,   // directories first
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => a.0.file_name().cmp(&b.0.file_name())     // alphabetic inside groups
+    fn insert_listing(&mut self, path: &Path, show_hidden_files: bool, listing: &Listing, prefetched: bool) {
+        self.invalidate(path);
+        let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) else {
+            return;
+        };
+        if listing.files.len() > LISTING_CACHE_ENTRIES {
+            return;
         }
-    });
 
-    files_and_dirs
-}
+        self.listings.insert(0, CachedListing { path: path.to_path_buf(), show_hidden_files, modified, listing: listing.clone(), prefetched });
 
+        // Drop the least recently listed directories until both bounds hold
+        let mut total = 0;
+        let keep = self
+            .listings
+            .iter()
+            .take(LISTING_CACHE_DIRS)
+            .take_while(|l| {
+                total += l.listing.files.len();
+                total <= LISTING_CACHE_ENTRIES
+            })
+            .count();
+        self.listings.truncate(keep);
+    }
 
+    fn invalidate(&mut self, path: &Path) {
+        self.listings.retain(|l| l.path != path);
+    }
+}
 
-fn load_config() -> Config {
-    let path = std::env::home_dir()
-        .unwrap()
-        .join(".config/csdesktop/csfm.toml");
+/// What the properties dialog shows about an entry.
+struct Properties {
+    entry: FileEntry,
+    /// Creation time, for filesystems that record one
+    created: Option<std::time::SystemTime>,
+    mode: Option<u32>,
+    /// The access ACL, when the filesystem has one beyond the mode bits, with who each entry is
+    /// for looked up once when the dialog opens
+    acl: Option<Vec<(String, AclEntry)>>,
+    acl_expanded: bool,
+    /// The `[open_overrides]` entry that applies, as shown in the dialog
+    opens_with: Option<String>
+}
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AclTag {
+    UserObj,
+    User(u32),
+    GroupObj,
+    Group(u32),
+    Mask,
+    Other,
+}
 
-    let data = std::fs::read_to_string(path).unwrap();
-    let config: Config = toml::from_str(&data).unwrap();
-    
-    config
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AclEntry {
+    tag: AclTag,
+    /// rwx as 4, 2, 1
+    perm: u16
 }
 
+const ACL_XATTR_VERSION: u32 = 2;
 
-impl Default for CsFM {
-    fn default() -> Self {
-        let path = std::env::current_dir().unwrap_or(PathBuf::from("/"));
-        let cfg = load_config();
-        let current_files = get_files(path.clone(), false);
-        CsFM {
-            config: cfg,
-            path,
-            current_files,
-            sidebar_open: true
+/// Reads the `system.posix_acl_access` attribute. Files without one, or filesystems without
+/// ACL support, give None.
+fn read_acl(path: &Path) -> Option<Vec<AclEntry>> {
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let name = c"system.posix_acl_access";
+
+    // The ACL may grow between asking for its size and reading it, so retry a few times
+    for _ in 0..3 {
+        // SAFETY: both strings are NUL-terminated, a null buffer of size 0 only asks for the size
+        let size = unsafe { libc::lgetxattr(c_path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return None;
+        }
+
+        let mut buf = vec![0u8; size as usize];
+        // SAFETY: both strings are NUL-terminated and the buffer length is passed along
+        let len = unsafe { libc::lgetxattr(c_path.as_ptr(), name.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+        if len >= 0 {
+            buf.truncate(len as usize);
+            return parse_acl_xattr(&buf);
+        }
+        if io::Error::last_os_error().raw_os_error() != Some(libc::ERANGE) {
+            return None;
         }
     }
+
+    None
+}
+
+/// Parses the kernel's xattr form of an ACL: a little-endian u32 version followed by
+/// 8-byte entries of u16 tag, u16 permissions and u32 uid or gid.
+fn parse_acl_xattr(data: &[u8]) -> Option<Vec<AclEntry>> {
+    let (header, entries) = data.split_at_checked(4)?;
+    if u32::from_le_bytes(header.try_into().ok()?) != ACL_XATTR_VERSION || entries.len() % 8 != 0 {
+        return None;
+    }
+
+    entries
+        .chunks_exact(8)
+        .map(|e| {
+            let tag = u16::from_le_bytes([e[0], e[1]]);
+            let perm = u16::from_le_bytes([e[2], e[3]]) & 0o7;
+            let id = u32::from_le_bytes([e[4], e[5], e[6], e[7]]);
+            let tag = match tag {
+                0x01 => AclTag::UserObj,
+                0x02 => AclTag::User(id),
+                0x04 => AclTag::GroupObj,
+                0x08 => AclTag::Group(id),
+                0x10 => AclTag::Mask,
+                0x20 => AclTag::Other,
+                _ => return None,
+            };
+            Some(AclEntry { tag, perm })
+        })
+        .collect()
+}
+
+fn format_perm(perm: u16) -> String {
+    [(4, 'r'), (2, 'w'), (1, 'x')]
+        .iter()
+        .map(|&(bit, c)| if perm & bit != 0 { c } else { '-' })
+        .collect()
+}
+
+/// A mode as `ls` shows it followed by the octal digits, e.g. "rwxr-x--- (750)".
+fn mode_summary(mode: u32) -> String {
+    let symbolic: String = [6, 3, 0].iter().map(|shift| format_perm(((mode >> shift) & 0o7) as u16)).collect();
+    format!("{} ({:03o})", symbolic, mode & 0o7777)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Account {
+    User,
+    Group,
+}
+
+/// Looks up a user or group name through NSS, so LDAP and similar accounts are found too.
+/// Falls back to the number.
+fn account_name(account: Account, id: u32) -> String {
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let mut result: *mut libc::c_void = std::ptr::null_mut();
+        // SAFETY: the records are plain C structs to fill in, the buffer length is passed along
+        // and the name points into the buffer, which is still alive when it is read
+        let (status, name) = unsafe {
+            match account {
+                Account::User => {
+                    let mut passwd: libc::passwd = std::mem::zeroed();
+                    let status = libc::getpwuid_r(id, &mut passwd, buf.as_mut_ptr(), buf.len(), (&raw mut result).cast());
+                    (status, passwd.pw_name)
+                }
+                Account::Group => {
+                    let mut group: libc::group = std::mem::zeroed();
+                    let status = libc::getgrgid_r(id, &mut group, buf.as_mut_ptr(), buf.len(), (&raw mut result).cast());
+                    (status, group.gr_name)
+                }
+            }
+        };
+
+        match status {
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            // SAFETY: a found record's name is a NUL-terminated string in the buffer
+            0 if !result.is_null() && !name.is_null() => {
+                return unsafe { std::ffi::CStr::from_ptr(name) }.to_string_lossy().into_owned();
+            }
+            _ => return id.to_string(),
+        }
+    }
+}
+
+/// Who an ACL entry applies to, as the properties dialog names it.
+fn acl_entry_who(tag: AclTag) -> String {
+    match tag {
+        AclTag::UserObj => "owner".to_string(),
+        AclTag::User(uid) => format!("user {}", account_name(Account::User, uid)),
+        AclTag::GroupObj => "owning group".to_string(),
+        AclTag::Group(gid) => format!("group {}", account_name(Account::Group, gid)),
+        AclTag::Mask => "mask".to_string(),
+        AclTag::Other => "others".to_string(),
+    }
+}
+
+/// A byte-accurate rendering of a name: printable ASCII as is, everything else as `\xNN`.
+fn escaped_name(name: &OsStr) -> String {
+    let mut out = String::new();
+    for &b in name.as_bytes() {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out
+}
+
+/// Version written by this build. Older files are brought up to date by `migrate_config`.
+const CONFIG_VERSION: u32 = 1;
+
+#[derive(Clone, Deserialize, Serialize, Default)]
+struct Config {
+    #[serde(default)]
+    pub config_version: u32,
+    pub theme: String,
+    pub show_hidden_files: bool,
+    #[serde(default)]
+    pub sidebar: SidebarConfig,
+    #[serde(default)]
+    pub network_location: Vec<NetworkLocation>,
+    #[serde(default)]
+    pub persist_path_history: bool,
+    #[serde(default)]
+    pub show_full_paths: bool,
+    #[serde(default)]
+    pub full_path_style: PathStyle,
+    /// Order of the file list
+    #[serde(default)]
+    pub sort_mode: SortMode,
+    #[serde(default)]
+    pub sort_descending: bool,
+    /// List directories among the files instead of before them
+    #[serde(default)]
+    pub mix_folders: bool,
+    #[serde(default)]
+    pub smart_folder: Vec<SmartFolder>,
+    #[serde(default)]
+    pub confirm_symlink_leave: bool,
+    /// Command template such as `code -g {file}:{line}`
+    #[serde(default)]
+    pub editor: Option<String>,
+    /// Disables everything that launches external programs
+    #[serde(default)]
+    pub safe_mode: bool,
+    /// Program used to compare two files, `meld` when unset
+    #[serde(default)]
+    pub diff_tool: Option<String>,
+    /// Share bookmarks with GTK applications through ~/.config/gtk-3.0/bookmarks
+    #[serde(default)]
+    pub sync_gtk_bookmarks: bool,
+    /// Show the full path instead of the directory name in the window title
+    #[serde(default)]
+    pub full_path_title: bool,
+    /// Don't list the parent or hovered directories ahead of time
+    #[serde(default)]
+    pub disable_prefetch: bool,
+    /// How deep recursive searches go, 32 when unset
+    #[serde(default)]
+    pub walk_max_depth: Option<usize>,
+    /// Let recursive searches descend into symlinked directories
+    #[serde(default)]
+    pub walk_follow_symlinks: bool,
+    /// Don't show metadata when hovering a file list row
+    #[serde(default)]
+    pub disable_tooltips: bool,
+    /// Octal mode applied to folders csfm creates, otherwise the umask decides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_dir_mode: Option<String>,
+    /// Octal mode applied to files csfm creates, otherwise the umask decides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_file_mode: Option<String>,
+    /// Create new folders and files under a placeholder name and rename them in the list,
+    /// instead of asking for the name in a dialog first
+    #[serde(default)]
+    pub inline_create: bool,
+    /// Size in MiB above which opening a text-like file asks first, 512 when unset and
+    /// never when 0
+    #[serde(default)]
+    pub large_file_threshold: Option<u64>,
+    /// Minutes an entry counts as recently changed when highlighting recent changes, 10 when unset
+    #[serde(default)]
+    pub recent_changes_minutes: Option<u64>,
+    /// Terminal emulator for the pager, `$TERMINAL` or x-terminal-emulator when unset
+    #[serde(default)]
+    pub terminal: Option<String>,
+    /// Commands for opening files, by extension (`pdf`) or MIME type (`text/markdown`, `image/*`)
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub open_overrides: std::collections::BTreeMap<String, OpenOverride>,
+    /// Shortcuts replacing the built-in ones, by action name as the F1 cheat sheet lists it,
+    /// e.g. `"Reload config" = "Ctrl+Shift+R"`. An empty chord removes the shortcut.
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub keybindings: std::collections::BTreeMap<String, String>
+}
+
+impl Config {
+    /// The order listings are shown in.
+    fn sort_order(&self) -> SortOrder {
+        SortOrder { mode: self.sort_mode, descending: self.sort_descending, mix_folders: self.mix_folders }
+    }
+
+    /// Limits shared by every recursive walk, such as searches and smart folders.
+    fn walk_options(&self) -> WalkOptions {
+        WalkOptions {
+            max_depth: self.walk_max_depth.unwrap_or(WALK_DEFAULT_MAX_DEPTH),
+            follow_symlinks: self.walk_follow_symlinks,
+            show_hidden_files: self.show_hidden_files,
+        }
+    }
+}
+
+/// What a `ConfigIssue` is about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IssueKind {
+    /// A setting with a wrong value, or an unknown key
+    Setting,
+    /// Something worth knowing that didn't stop a setting from being used
+    Notice,
+    /// The file isn't valid TOML, so no setting was read
+    Syntax,
+}
+
+/// A problem or notice from loading the config. A setting with a problem keeps its default.
+#[derive(Debug, Clone, PartialEq)]
+struct ConfigIssue {
+    pub kind: IssueKind,
+    pub key: String,
+    pub found: String,
+    pub expected: String,
+    /// 1-based line and column
+    pub location: Option<(usize, usize)>
+}
+
+impl std::fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((line, column)) = self.location {
+            write!(f, "line {}, column {}: ", line, column)?;
+        }
+        match self.kind {
+            IssueKind::Setting => write!(f, "{} = {} (expected {})", self.key, self.found, self.expected),
+            IssueKind::Notice | IssueKind::Syntax => write!(f, "{}", self.found),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Default)]
+struct SidebarConfig {
+    #[serde(default)]
+    pub bookmarks: Vec<Location>,
+    /// Paths of all bookmarks in sidebar order, GTK ones included. Those aren't saved with the
+    /// others, so this is what keeps them where they were moved to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<String>
+}
+
+/// Standalone bookmarks file for moving bookmarks between machines.
+#[derive(Deserialize, Serialize)]
+struct BookmarkExport {
+    #[serde(default)]
+    pub bookmarks: Vec<Location>
+}
+
+fn export_bookmarks(bookmarks: &[Location]) -> Result<String, String> {
+    toml::to_string_pretty(&BookmarkExport { bookmarks: bookmarks.to_vec() }).map_err(|e| e.to_string())
+}
+
+fn import_bookmarks(data: &str) -> Result<Vec<Location>, String> {
+    toml::from_str::<BookmarkExport>(data).map(|export| export.bookmarks).map_err(|e| e.to_string())
+}
+
+/// Adds `imported` to `bookmarks`, or puts it in their place with `replace`, skipping
+/// paths already there and entries without a title or path. Returns the bookmarks added
+/// and the paths of those removed.
+fn merge_bookmarks(bookmarks: &mut Vec<Location>, imported: Vec<Location>, replace: bool) -> (Vec<Location>, Vec<String>) {
+    // Paths are kept even when missing here, they may be on a drive that isn't mounted yet
+    let removed: Vec<String> = if replace {
+        bookmarks.drain(..).map(|l| l.path).collect()
+    } else {
+        vec![]
+    };
+    let mut added = vec![];
+    for location in imported {
+        if location.title.trim().is_empty() || location.path.trim().is_empty() {
+            continue;
+        }
+        if !bookmarks.iter().any(|l| l.path == location.path) {
+            bookmarks.push(location.clone());
+            added.push(location);
+        }
+    }
+
+    let removed = removed.into_iter().filter(|p| !added.iter().any(|l| l.path == *p)).collect();
+    (added, removed)
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct Location {
+    pub title: String,
+    pub path: String,
+    /// Read from the GTK bookmarks file rather than csfm.toml
+    #[serde(skip)]
+    pub from_gtk: bool,
+    /// Show hidden files when opened through this bookmark, whatever the global setting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hidden: Option<bool>
+}
+
+/// A command to open files with instead of their default application, either
+/// `"zathura {}"` or `{ command = "glow -p {}", terminal = true }`.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum OpenOverride {
+    Command(String),
+    Detailed {
+        command: String,
+        /// Run inside the terminal emulator
+        #[serde(default)]
+        terminal: bool
+    },
+}
+
+impl OpenOverride {
+    fn command(&self) -> &str {
+        match self {
+            OpenOverride::Command(command) | OpenOverride::Detailed { command, .. } => command,
+        }
+    }
+
+    fn terminal(&self) -> bool {
+        matches!(self, OpenOverride::Detailed { terminal: true, .. })
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct NetworkLocation {
+    pub title: String,
+    pub uri: String
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct SmartFolder {
+    pub title: String,
+    pub root: String,
+    #[serde(flatten)]
+    pub rule: SmartRule
+}
+
+/// Conditions an entry must meet to show up in a smart folder. Unset fields match everything.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+struct SmartRule {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_glob: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_within_days: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>
+}
+
+const SMART_FOLDER_MAX_RESULTS: usize = 10_000;
+
+impl SmartRule {
+    fn matches(&self, path: &Path, metadata: &fs::Metadata) -> bool {
+        let name = normalized_name(path.file_name().unwrap_or_default()).to_lowercase();
+
+        if !self.extensions.is_empty() {
+            let ext = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+            if !self.extensions.iter().any(|e| e.trim_start_matches('.').to_lowercase() == ext) {
+                return false;
+            }
+        }
+
+        if let Some(glob) = &self.name_glob
+            && !glob_match(&glob.nfc().collect::<String>().to_lowercase(), &name)
+        {
+            return false;
+        }
+
+        if let Some(days) = self.modified_within_days {
+            let max_age = std::time::Duration::from_secs(days.saturating_mul(24 * 60 * 60));
+            let age = metadata.modified().ok().and_then(|m| m.elapsed().ok());
+            if age.is_none_or(|a| a > max_age) {
+                return false;
+            }
+        }
+
+        if self.min_size.is_some_and(|min| metadata.len() < min) {
+            return false;
+        }
+        if self.max_size.is_some_and(|max| metadata.len() > max) {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Shell-style matching supporting `*` and `?`.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((bp, bn)) = backtrack {
+            p = bp + 1;
+            n = bn + 1;
+            backtrack = Some((bp, bn + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => paths::home().join(rest),
+        None if path == "~" => paths::home(),
+        None => PathBuf::from(path),
+    }
+}
+
+const WALK_DEFAULT_MAX_DEPTH: usize = 32;
+
+/// Files and directories below `root` whose name contains `query`, ignoring case.
+fn run_search(root: PathBuf, query: &str, options: WalkOptions) -> (Vec<FileEntry>, WalkSummary) {
+    let query = query.nfc().collect::<String>().to_lowercase();
+    let mut results = vec![];
+
+    let summary = walk(&root, &options, |p, metadata| {
+        let name = folded_name(p);
+        if name.contains(&query) {
+            results.push(FileEntry {
+                path: p.to_path_buf(),
+                is_dir: metadata.is_dir(),
+                leaves_tree: false,
+                is_symlink: p.is_symlink(),
+                size: Some(metadata.len()),
+                modified: metadata.modified().ok(),
+                flags: InodeFlags::read(p),
+                folded_name: name,
+                sort_keys: SortKeys::of(p),
+            });
+        }
+        results.len() < SMART_FOLDER_MAX_RESULTS
+    });
+
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+    (results, summary)
+}
+
+fn run_smart_folder(root: PathBuf, rule: SmartRule, options: WalkOptions) -> (Vec<FileEntry>, WalkSummary) {
+    let mut results = vec![];
+
+    let summary = walk(&root, &options, |p, metadata| {
+        if !metadata.is_dir() && rule.matches(p, metadata) {
+            results.push(FileEntry {
+                path: p.to_path_buf(),
+                is_dir: false,
+                leaves_tree: false,
+                is_symlink: p.is_symlink(),
+                size: Some(metadata.len()),
+                modified: metadata.modified().ok(),
+                flags: InodeFlags::read(p),
+                folded_name: folded_name(p),
+                sort_keys: SortKeys::of(p),
+            });
+        }
+        results.len() < SMART_FOLDER_MAX_RESULTS
+    });
+
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+    (results, summary)
+}
+
+/// Describes what a smart folder search left out, if anything.
+fn walk_notice(summary: &WalkSummary) -> Option<String> {
+    let mut notes = vec![];
+    if summary.stopped {
+        notes.push(format!("showing the first {} results", SMART_FOLDER_MAX_RESULTS));
+    }
+    if !summary.depth_limited.is_empty() {
+        notes.push(format!("depth limit reached in {} directories", summary.depth_limited.len()));
+    }
+    if summary.cycles > 0 {
+        notes.push(format!("skipped {} directory loops", summary.cycles));
+    }
+
+    (!notes.is_empty()).then(|| notes.join(", "))
+}
+
+enum NetworkTarget {
+    Local(PathBuf),
+    Unavailable(String)
+}
+
+fn resolve_network_uri(uri: &str) -> NetworkTarget {
+    let Some((scheme, rest)) = uri.split_once("://") else {
+        return NetworkTarget::Local(PathBuf::from(uri));
+    };
+
+    match scheme.to_ascii_lowercase().as_str() {
+        // Percent-decoded, and only on this machine: no host or localhost
+        "file" => match desktop::arg_to_path(OsStr::new(&format!("file://{}", rest))) {
+            Some(path) => NetworkTarget::Local(path),
+            None => NetworkTarget::Unavailable(format!("{} isn't a file on this computer", uri)),
+        },
+        // No remote backends are compiled into this build yet
+        "sftp" | "smb" | "ftp" => NetworkTarget::Unavailable(format!("{}:// support is not included in this build of CsFM", scheme)),
+        _ => NetworkTarget::Unavailable(format!("Unknown location type '{}://'", scheme))
+    }
+}
+
+fn title(state: &CsFM) -> String {
+    const MAX_CHARS: usize = 80;
+
+    let location = if state.config.full_path_title {
+        state.path.as_os_str()
+    } else {
+        state.path.file_name().unwrap_or(state.path.as_os_str())
+    };
+    let (location, _) = sanitize_name(&location.to_string_lossy());
+
+    let location = if location.chars().count() > MAX_CHARS {
+        // Keep the end of the path, it is the part that tells directories apart
+        let tail: String = location.chars().rev().take(MAX_CHARS - 1).collect::<Vec<_>>().into_iter().rev().collect();
+        format!("…{}", tail)
+    } else {
+        location
+    };
+
+    match state.transfers.len() {
+        0 => format!("{} — CsFM", location),
+        1 => format!("{} • 1 operation — CsFM", location),
+        n => format!("{} • {} operations — CsFM", location, n),
+    }
+}
+
+fn theme(state: &CsFM) -> Theme {
+    theme_by_name(&state.config.theme).unwrap_or(Theme::GruvboxDark)
+}
+
+/// Looks up a built-in theme ignoring case, surrounding whitespace, and the spaces, dashes
+/// and underscores inside the name, so "solarized dark" and "SolarizedDark" both work.
+fn theme_by_name(name: &str) -> Option<Theme> {
+    let key = |name: &str| -> String {
+        name.chars()
+            .filter(|c| !c.is_whitespace() && *c != '-' && *c != '_')
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let wanted = key(name);
+    if wanted.is_empty() {
+        return None;
+    }
+
+    Theme::ALL.iter().find(|t| key(&t.to_string()) == wanted).cloned()
+}
+
+fn question_zenity(question: String) -> bool {
+    let out = Command::new("zenity")
+        .arg("--question")
+        .arg("--title=CsFM")
+        .arg(format!("--text={}", question))
+        .output();
+
+    match out {
+        Ok(o) => {
+            o.status.success()
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            error_zenity(format!("Error: {}", e));
+            false
+        }
+    }
+}
+
+fn input_zenity(prompt: String) -> Option<String> {
+    entry_zenity(prompt, "")
+}
+
+/// `input_zenity` with the entry prefilled with `initial`.
+fn entry_zenity(prompt: String, initial: &str) -> Option<String> {
+    let out = Command::new("zenity")
+        .arg("--entry")
+        .arg("--title=CsFM")
+        .arg(format!("--text={}", prompt))
+        .arg(format!("--entry-text={}", initial))
+        .output();
+
+    match out {
+        Ok(o) => {
+            if !o.status.success() {
+                return None;
+            }
+            let input = String::from_utf8_lossy(&o.stdout).trim_end_matches('\n').to_string();
+            Some(input)
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            error_zenity(format!("Error: {}", e));
+            None
+        }
+    }
+}
+
+fn file_zenity(title: &str, save: bool) -> Option<PathBuf> {
+    let mut cmd = Command::new("zenity");
+    cmd.arg("--file-selection").arg(format!("--title={}", title));
+    if save {
+        cmd.arg("--save").arg("--confirm-overwrite");
+    }
+
+    match cmd.output() {
+        Ok(o) => {
+            if !o.status.success() {
+                return None;
+            }
+            let path = String::from_utf8_lossy(&o.stdout).trim_end_matches('\n').to_string();
+            (!path.is_empty()).then(|| PathBuf::from(path))
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            error_zenity(format!("Error: {}", e));
+            None
+        }
+    }
+}
+
+fn info_zenity(message: String) -> bool {
+    let out = Command::new("zenity")
+        .arg("--info")
+        .arg("--title=CsFM")
+        .arg(format!("--text={}", message))
+        .output();
+
+    match out {
+        Ok(o) => {
+            o.status.success()
+        }
+        Err(e) => {
+            println!("Error: {}", e);
+            false
+        }
+    }
+}
+
+fn error_zenity(message: String) -> bool {
+    let out = Command::new("zenity")
+        .arg("--error")
+        .arg("--title=CsFM")
+        .arg(format!("--text={}", message))
+        .output();
+
+    match out {
+        Ok(o) => {
+            o.status.success()
+        }
+        Err(e) => {
+            println!("Error: {}", e);
+            false
+        }
+    }
+}
+
+/// Expands an editor template into program and arguments. Without a line number the
+/// `{line}` placeholder and the separator in front of it are dropped.
+fn editor_command(template: &str, file: &Path, line: Option<usize>) -> Vec<String> {
+    let file = file.to_string_lossy();
+
+    template
+        .split_whitespace()
+        .filter_map(|arg| {
+            let arg = match line {
+                Some(line) => arg.replace("{line}", &line.to_string()),
+                None => match arg.find("{line}") {
+                    Some(i) => {
+                        let prefix = arg[..i].trim_end_matches([':', '+', '#']);
+                        format!("{}{}", prefix, &arg[i + "{line}".len()..])
+                    }
+                    None => arg.to_string(),
+                },
+            };
+            let arg = arg.replace("{file}", &file);
+            (!arg.is_empty()).then_some(arg)
+        })
+        .collect()
+}
+
+/// Splits an override command into program and arguments, with `{}` replaced by the
+/// file, or the file appended when there is no `{}`.
+fn expand_command(template: &str, file: &Path) -> Vec<String> {
+    let file = file.to_string_lossy();
+    let mut command: Vec<String> = template.split_whitespace().map(|arg| arg.replace("{}", &file)).collect();
+    if !template.contains("{}") {
+        command.push(file.to_string());
+    }
+    command
+}
+
+/// The `[open_overrides]` entry for `path` and its key. An extension beats the exact MIME
+/// type, which beats a `type/*` entry.
+fn open_override<'a>(config: &'a Config, path: &Path) -> Option<(&'a str, &'a OpenOverride)> {
+    let mime = mime_type(path);
+    let family = format!("{}/*", mime.split('/').next().unwrap_or_default());
+    let extension = extension_key(path);
+
+    [extension.as_str(), mime, family.as_str()]
+        .into_iter()
+        .filter(|key| !key.is_empty())
+        .find_map(|key| config.open_overrides.get_key_value(key))
+        .map(|(key, o)| (key.as_str(), o))
+}
+
+fn terminal_program(config: &Config) -> String {
+    config.terminal.clone()
+        .or_else(|| std::env::var("TERMINAL").ok())
+        .unwrap_or_else(|| "x-terminal-emulator".to_string())
+}
+
+/// Reports and returns true when `action` would launch an external program in safe mode.
+fn blocked_by_safe_mode(state: &CsFM, action: &str) -> bool {
+    if state.config.safe_mode {
+        error_zenity(format!("{} is disabled in safe mode", action));
+    }
+
+    state.config.safe_mode
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    let ext = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+        "txt" => "text/plain",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "md" => "text/markdown",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The size of `path` when it is over the large file threshold and of a type whose
+/// default application tends to load it whole. Media players stream, so they are exempt.
+fn large_file_size(state: &CsFM, path: &Path) -> Option<u64> {
+    let threshold = state.config.large_file_threshold.unwrap_or(LARGE_FILE_THRESHOLD_MIB);
+    if threshold == 0 {
+        return None;
+    }
+
+    let mime = mime_type(path);
+    if ["image/", "audio/", "video/", "font/"].iter().any(|p| mime.starts_with(p)) || mime == "application/pdf" {
+        return None;
+    }
+
+    let size = match state.current_files.iter().find(|f| f.path == path).and_then(|f| f.size) {
+        Some(size) => size,
+        None => fs::metadata(path).ok()?.len(),
+    };
+    (size > threshold.saturating_mul(1024 * 1024)).then_some(size)
+}
+
+fn extension_key(path: &Path) -> String {
+    path.extension().unwrap_or_default().to_string_lossy().to_lowercase()
+}
+
+/// Reads the start of a file, as text when it looks like text and as a hex dump otherwise.
+fn read_preview(path: &Path) -> io::Result<Preview> {
+    use std::io::Read;
+
+    let mut data = vec![];
+    let file = fs::File::open(path)?;
+    file.take(PREVIEW_TEXT_BYTES as u64 + 1).read_to_end(&mut data)?;
+    let mut truncated = data.len() > PREVIEW_TEXT_BYTES;
+    data.truncate(PREVIEW_TEXT_BYTES);
+
+    let hex = data.contains(&0);
+    let contents = if hex {
+        truncated |= data.len() > PREVIEW_HEX_BYTES;
+        data.truncate(PREVIEW_HEX_BYTES);
+        data.chunks(16)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+                let ascii: String = chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+                format!("{:08x}  {:<47}  {}", i * 16, bytes.join(" "), ascii)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        String::from_utf8_lossy(&data).into_owned()
+    };
+
+    Ok(Preview { path: path.to_path_buf(), contents, hex, truncated, quick_look: false })
+}
+
+/// The quick look for `entry`: the start of the file, or a summary card for media whose
+/// contents wouldn't mean anything as text.
+fn quick_look(entry: &FileEntry) -> Preview {
+    let mime = mime_type(&entry.path);
+    let media = ["image/", "audio/", "video/", "font/"].iter().any(|p| mime.starts_with(p)) || mime == "application/pdf";
+
+    let mut preview = if media {
+        let mut card = vec![format!("Type: {}", mime), format!("Size: {}", exact_size(entry.size))];
+        if let Some(modified) = entry.modified {
+            let modified: chrono::DateTime<chrono::Local> = modified.into();
+            card.push(format!("Modified: {}", modified.format("%Y-%m-%d %H:%M:%S")));
+        }
+        Preview { path: entry.path.clone(), contents: card.join("\n"), hex: false, truncated: false, quick_look: true }
+    } else {
+        read_preview(&entry.path).unwrap_or_else(|e| Preview {
+            path: entry.path.clone(),
+            contents: format!("Couldn't read the file: {}", e),
+            hex: false,
+            truncated: false,
+            quick_look: true,
+        })
+    };
+    preview.quick_look = true;
+    preview
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        out.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 { ALPHABET[(n >> 6) as usize & 63] as char } else { '=' });
+        out.push(if chunk.len() > 2 { ALPHABET[n as usize & 63] as char } else { '=' });
+    }
+
+    out
+}
+
+/// Persists bookmark edits, mirroring them into the GTK bookmarks file when syncing is on.
+fn bookmarks_changed(state: &CsFM, added: &[Location], removed: &[String]) {
+    if let Err(e) = save_config(&state.config) {
+        error_zenity(format!("Failed to save config: {}", e));
+    }
+
+    if state.config.sync_gtk_bookmarks
+        && let Err(e) = write_gtk_bookmarks(added, removed)
+    {
+        error_zenity(format!("Failed to update {}: {}", gtk_bookmarks_path().display(), e));
+    }
+}
+
+const PREFETCH_HOVER_DELAY: std::time::Duration = std::time::Duration::from_millis(300);
+const TOOLTIP_DELAY: std::time::Duration = std::time::Duration::from_millis(600);
+const DOUBLE_CLICK_TIME: std::time::Duration = std::time::Duration::from_millis(400);
+/// Gives the folder just opened time to draw and settle before reading its parent.
+const PREFETCH_PARENT_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Resolves once `delay` has passed. The executor has no timer, and sleeping inside a future
+/// would hold up one of its workers, so the waiting happens on a thread of its own.
+fn sleep(delay: std::time::Duration) -> impl std::future::Future<Output = ()> {
+    let (sender, receiver) = iced::futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        std::thread::sleep(delay);
+        let _ = sender.send(());
+    });
+
+    async move {
+        let _ = receiver.await;
+    }
+}
+
+/// Runs `work` on a thread of its own and resolves to its result. Reading directories and
+/// copying files can block for long on slow disks and network mounts, which would hold up
+/// the executor's few workers and with them every other task.
+fn blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> impl std::future::Future<Output = T> {
+    let (sender, receiver) = iced::futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(work());
+    });
+
+    async move {
+        match receiver.await {
+            Ok(result) => result,
+            // The work panicked, its message never comes
+            Err(_) => std::future::pending().await,
+        }
+    }
+}
+
+/// Produces `message` once `delay` has passed.
+fn after(delay: std::time::Duration, message: Message) -> Task<Message> {
+    Task::perform(sleep(delay), move |_| message.clone())
+}
+
+/// Ticks every `period` for as long as it stays in the subscription.
+fn every(period: std::time::Duration) -> Subscription<std::time::Instant> {
+    Subscription::run_with_id(
+        ("every", period),
+        iced::futures::stream::unfold((), move |()| async move {
+            sleep(period).await;
+            Some((std::time::Instant::now(), ()))
+        }),
+    )
+}
+
+const PREFETCH_MAX_RUNNING: usize = 2;
+
+/// Lists `path` in the background into the listing cache, ahead of the user opening it.
+/// Navigation itself never waits on this, so a prefetch is dropped rather than queued when
+/// others are already running or a folder the user opened is still loading.
+fn prefetch(state: &mut CsFM, path: PathBuf) -> Task<Message> {
+    let show_hidden_files = state.config.show_hidden_files;
+    if state.config.disable_prefetch
+        || state.loading
+        || state.prefetches_running >= PREFETCH_MAX_RUNNING
+        || state.listing_cache.contains(&path, show_hidden_files)
+        || on_network_filesystem(&path)
+    {
+        return Task::none();
+    }
+
+    state.prefetches_running += 1;
+    let order = state.config.sort_order();
+    Task::perform(
+        blocking(move || {
+            let listing = get_files(path.clone(), show_hidden_files).ok().map(|l| l.sorted(order));
+            (path, listing)
+        }),
+        move |(path, listing)| Message::Prefetched(path, show_hidden_files, listing),
+    )
+}
+
+/// Up is the most common next step after entering a directory. The listing is read a moment
+/// later, once it is clear the user is staying.
+fn prefetch_parent(state: &CsFM) -> Task<Message> {
+    if state.config.disable_prefetch || state.path.parent().is_none() {
+        return Task::none();
+    }
+    after(PREFETCH_PARENT_DELAY, Message::PrefetchParentDue(state.path.clone()))
+}
+
+/// Opens `path` in another window. csfm has no multi-window support yet, so this starts
+/// a second process. The installed binary is preferred; when csfm isn't on PATH (e.g. run
+/// from a build directory) the running executable is used instead.
+fn open_new_window(path: &Path) -> io::Result<()> {
+    let spawn = |program: &OsStr| {
+        Command::new(program)
+            .arg(path)
+            .stdin(std::process::Stdio::null())
+            .spawn()
+            .map(|_| ())
+    };
+
+    match spawn(OsStr::new("csfm")) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => spawn(std::env::current_exe()?.as_os_str()),
+        result => result,
+    }
+}
+
+/// Checks copying or moving `sources` into `dest` and starts it for the sources that can
+/// go. Copies colliding with an existing name get a free " (copy)" name when `keep_both`
+/// is set, and otherwise ask whether to replace it.
+fn plan_transfer(transfer: Transfer, sources: Vec<PathBuf>, dest: PathBuf, keep_both: bool) -> Task<Message> {
+    let mut pairs = vec![];
+    for source in sources {
+        let Some(name) = source.file_name() else {
+            continue;
+        };
+        let target = match transfer::plan(transfer, &source, &dest, keep_both) {
+            Ok(target) => target,
+            Err(Refusal::AlreadyThere) => {
+                info_zenity(format!("{} is already in {}.", name.to_string_lossy(), dest.display()));
+                continue;
+            }
+            Err(Refusal::IntoItself) => {
+                error_zenity(format!("Can't put {} inside itself.", source.display()));
+                continue;
+            }
+            Err(Refusal::InsideTarget(target)) => {
+                error_zenity(format!("Can't replace {} with something inside it.", target.display()));
+                continue;
+            }
+        };
+        if fs::symlink_metadata(&target).is_ok()
+            && !question_zenity(format!("{} already exists in {}. Replace it?", name.to_string_lossy(), dest.display()))
+        {
+            continue;
+        }
+        pairs.push((source, target));
+    }
+    if pairs.is_empty() {
+        return Task::none();
+    }
+
+    // Sizing the sources can take a while for large trees
+    Task::perform(
+        blocking(move || {
+            let shortfall = space_shortfall(transfer, &pairs, &dest);
+            (dest, pairs, shortfall)
+        }),
+        move |(dest, pairs, shortfall)| Message::StartTransfer(transfer, dest, pairs, shortfall),
+    )
+}
+
+/// Directories directly inside `dir`, for the folder chooser.
+fn subdirs(state: &CsFM, dir: &Path) -> Vec<PathBuf> {
+    get_files(dir.to_path_buf(), state.config.show_hidden_files)
+        .map(|mut listing| {
+            sort_by_name(&mut listing.files);
+            listing.files.into_iter().filter(|f| f.is_dir).map(|f| f.path).collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Shows a freshly loaded listing of `state.path`. Reloading the directory already shown
+/// keeps the scroll position, the selection and the hover state of entries that are still
+/// there, while a different directory starts at the top.
+fn apply_listing(state: &mut CsFM, mut listing: Listing) -> Task<Message> {
+    state.loading = false;
+    let same_dir = state.listed_path.as_ref() == Some(&state.path);
+    // Already sorted when read in the background, unless the order changed since
+    listing.sort(state.config.sort_order());
+    let old_files = std::mem::replace(&mut state.current_files, listing.files);
+    state.hidden_count = listing.hidden;
+    state.listed_path = Some(state.path.clone());
+
+    let survived = |entry: &Option<PathBuf>| same_dir && entry.as_ref().is_some_and(|p| state.current_files.iter().any(|f| &f.path == p));
+    if !survived(&state.hovered_entry) {
+        state.hovered_entry = None;
+    }
+    if !survived(&state.tooltip_entry) {
+        state.tooltip_entry = None;
+    }
+    let revealed = state.revealed_entry.as_ref().and_then(|p| state.current_files.iter().position(|f| &f.path == p));
+    if revealed.is_none() {
+        state.revealed_entry = None;
+    }
+
+    let mut reload_tree = Task::none();
+    let mut scroll_to_anchor = None;
+    if same_dir {
+        reconcile_selection(&old_files, &state.current_files, &mut state.selected, &mut state.selection_anchor);
+        scroll_to_anchor = state.selection_anchor.as_ref().and_then(|anchor| {
+            let shown = shown_files(state);
+            let i = shown.iter().position(|f| &f.path == anchor)?;
+            scroll_into_view(i, shown.len(), state.file_list_view?)
+        });
+        reload_tree = reload_tree_nodes(state);
+    } else {
+        state.tree.clear();
+        state.renaming = None;
+        // Left with its placeholder name when the user navigated away mid-edit
+        state.placeholder = None;
+        state.selected.clear();
+        state.selection_anchor = None;
+        state.crumb_menu_open = false;
+        state.filter.clear();
+        // Navigation may have crossed into or out of a mount
+        state.path_mount = path_mount(&state.path);
+    }
+
+    push_path_history(state);
+    if !same_dir && state.nav_history.get(state.nav_index) != Some(&state.path) {
+        // Like a browser, going somewhere new drops what was ahead
+        state.nav_history.truncate(state.nav_index + 1);
+        state.nav_history.push(state.path.clone());
+        state.nav_index = state.nav_history.len() - 1;
+    }
+    let scroll = match revealed {
+        Some(i) if !same_dir => {
+            let y = i as f32 / state.current_files.len().saturating_sub(1).max(1) as f32;
+            scrollable::snap_to(file_list_id(), scrollable::RelativeOffset { x: 0.0, y })
+        }
+        _ if same_dir => match scroll_to_anchor {
+            Some(y) => scrollable::snap_to(file_list_id(), scrollable::RelativeOffset { x: 0.0, y }),
+            None => Task::none(),
+        },
+        _ => scrollable::snap_to(file_list_id(), scrollable::RelativeOffset::START),
+    };
+    Task::batch([scroll, reload_tree, prefetch_parent(state)])
+}
+
+/// Carries the selection over a reload of the same directory. Entries that are gone leave
+/// it, and a selection anchor that is gone moves to its nearest neighbour that survived,
+/// the one after it first.
+fn reconcile_selection(old: &[FileEntry], new: &[FileEntry], selected: &mut HashSet<PathBuf>, anchor: &mut Option<PathBuf>) {
+    let listed: HashSet<&PathBuf> = new.iter().map(|f| &f.path).collect();
+    selected.retain(|p| listed.contains(p));
+
+    let Some(gone) = anchor.take_if(|a| !listed.contains(a)) else {
+        return;
+    };
+    let Some(at) = old.iter().position(|f| f.path == gone) else {
+        return;
+    };
+    *anchor = (1..old.len())
+        .flat_map(|distance| [old.get(at + distance), at.checked_sub(distance).and_then(|i| old.get(i))])
+        .flatten()
+        .find(|f| listed.contains(&f.path))
+        .map(|f| f.path.clone());
+}
+
+/// The part of the file list in view, as fractions of its length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ListView {
+    /// Where the view starts
+    start: f32,
+    /// How much of the list fits in it
+    visible: f32,
+}
+
+impl ListView {
+    fn of(viewport: scrollable::Viewport) -> Self {
+        let content = viewport.content_bounds().height.max(1.0);
+        ListView {
+            start: viewport.absolute_offset().y / content,
+            visible: (viewport.bounds().height / content).min(1.0),
+        }
+    }
+}
+
+/// Where to snap the list to bring row `i` of `len` back into `view`, None when it is
+/// still in view. Rows are taken to be of even height.
+fn scroll_into_view(i: usize, len: usize, view: ListView) -> Option<f32> {
+    let (top, bottom) = (i as f32 / len.max(1) as f32, (i + 1) as f32 / len.max(1) as f32);
+    if top >= view.start && bottom <= view.start + view.visible {
+        return None;
+    }
+
+    Some(i as f32 / len.saturating_sub(1).max(1) as f32)
+}
+
+/// Re-reads the folders expanded in the tree view on a refresh, as their contents may have
+/// changed as much as the listing's. Folders that are gone are collapsed.
+fn reload_tree_nodes(state: &mut CsFM) -> Task<Message> {
+    state.tree.retain(|p, _| p.is_dir());
+    let show_hidden_files = show_hidden_files(state);
+    let order = state.config.sort_order();
+    let tasks = state.tree.keys().cloned().map(|path| {
+        Task::perform(
+            blocking(move || {
+                let listing =
+                    get_files(path.clone(), show_hidden_files).map(|l| l.sorted(order)).map_err(|e| e.to_string());
+                (path, listing)
+            }),
+            |(path, listing)| Message::TreeNodeLoaded(path, listing),
+        )
+    });
+
+    Task::batch(tasks)
+}
+
+/// Shows the just created `path` in the list right away and opens its name for editing, with
+/// the placeholder name selected so typing replaces it.
+fn start_placeholder_rename(state: &mut CsFM, path: PathBuf, is_dir: bool) -> Task<Message> {
+    let Ok(metadata) = fs::symlink_metadata(&path) else {
+        return Task::done(Message::CDToPath);
+    };
+    state.listing_cache.invalidate(&state.path);
+    state.current_files.push(FileEntry {
+        path: path.clone(),
+        is_dir,
+        leaves_tree: false,
+        is_symlink: false,
+        size: Some(metadata.len()),
+        modified: metadata.modified().ok(),
+        flags: InodeFlags::read(&path),
+        folded_name: folded_name(&path),
+        sort_keys: SortKeys::of(&path),
+    });
+    sort_files(&mut state.current_files, state.config.sort_order());
+    // The placeholder name may not pass the filter, and the row has to be visible to edit it
+    state.filter.clear();
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    state.renaming = Some((path.clone(), name));
+    state.placeholder = Some(path.clone());
+    state.revealed_entry = Some(path.clone());
+
+    let shown = shown_files(state);
+    let i = shown.iter().position(|f| f.path == path).unwrap_or(0);
+    let y = i as f32 / shown.len().saturating_sub(1).max(1) as f32;
+    Task::batch([
+        scrollable::snap_to(file_list_id(), scrollable::RelativeOffset { x: 0.0, y }),
+        text_input::focus(rename_input_id()),
+        text_input::select_all(rename_input_id()),
+    ])
+}
+
+/// Undoes an inline creation whose naming was cancelled. Anything put into the item in the
+/// meantime keeps it.
+fn remove_placeholder(state: &mut CsFM, path: &Path) {
+    if remove_if_empty(path) {
+        oplog::record_in_background(vec![oplog::Operation::now(oplog::Action::Deleted, path, None)]);
+    }
+    state.revealed_entry = None;
+    invalidate_parent_listing(state, path);
+}
+
+fn rename_input_id() -> text_input::Id {
+    text_input::Id::new("rename")
+}
+
+fn path_input_id() -> text_input::Id {
+    text_input::Id::new("path")
+}
+
+fn filter_input_id() -> text_input::Id {
+    text_input::Id::new("filter")
+}
+
+fn file_list_id() -> scrollable::Id {
+    scrollable::Id::new("file-list")
+}
+
+/// How many links a chain may go through, the same limit the kernel uses.
+const MAX_LINK_HOPS: usize = 40;
+
+#[derive(Debug, PartialEq)]
+enum LinkError {
+    NotALink,
+    /// The chain didn't end within `MAX_LINK_HOPS`, most likely a cycle
+    TooManyHops,
+}
+
+/// Where the link at `link` points, one hop away or, with `fully`, at the end of the chain.
+/// `read_link` gives a link's stored target and None for anything else. Relative targets
+/// are resolved against the link's directory, and `.` and `..` are folded without looking
+/// at the file system.
+fn resolve_link(link: &Path, fully: bool, read_link: impl Fn(&Path) -> Option<PathBuf>) -> Result<PathBuf, LinkError> {
+    let mut current = link.to_path_buf();
+
+    for hop in 0..=MAX_LINK_HOPS {
+        let Some(target) = read_link(&current) else {
+            return if hop == 0 { Err(LinkError::NotALink) } else { Ok(current) };
+        };
+        if hop == MAX_LINK_HOPS {
+            break;
+        }
+
+        let target = current.parent().unwrap_or(Path::new("/")).join(target);
+        current = PathBuf::new();
+        for component in target.components() {
+            match component {
+                std::path::Component::CurDir => {}
+                std::path::Component::ParentDir => {
+                    current.pop();
+                }
+                c => current.push(c),
+            }
+        }
+        if !fully {
+            return Ok(current);
+        }
+    }
+
+    Err(LinkError::TooManyHops)
+}
+
+/// Whether hidden files are listed, either everywhere or just for this visit.
+fn show_hidden_files(state: &CsFM) -> bool {
+    match &state.hidden_override {
+        Some((path, show)) if *path == state.path => *show,
+        _ => state.config.show_hidden_files,
+    }
+}
+
+/// Forgets the cached listing of the directory containing `path` after csfm changed it.
+/// The mtime check would catch this too, but not within the same mtime granularity tick.
+fn invalidate_parent_listing(state: &mut CsFM, path: &Path) {
+    if let Some(parent) = path.parent() {
+        state.listing_cache.invalidate(parent);
+    }
+}
+
+/// Runs a transfer in the background, reporting to TransferDone under `id`.
+fn run_transfer_task(id: usize, transfer: Transfer, pairs: Vec<(PathBuf, PathBuf)>) -> Task<Message> {
+    let action = match transfer {
+        Transfer::Copy => oplog::Action::Copied,
+        Transfer::Move => oplog::Action::Moved,
+        Transfer::Trash => oplog::Action::Trashed,
+    };
+    // Logged as each item is done, so a transfer cut short still leaves a record of it
+    let mut log = move |source: &Path, target: &Path| oplog::record(&[oplog::Operation::now(action, source, Some(target))]);
+    Task::perform(blocking(move || run_transfer(transfer, pairs, &mut log)), move |outcome| Message::TransferDone(id, outcome))
+}
+
+/// Drops the cached listings a transfer changed.
+fn refresh_transfer_listings(state: &mut CsFM, i: usize) {
+    for source in state.transfers[i].sources.clone() {
+        invalidate_parent_listing(state, &source);
+    }
+    let dest = state.transfers[i].dest.clone();
+    state.listing_cache.invalidate(&dest);
+}
+
+/// Removes a transfer that finished or was cancelled.
+fn finish_transfer(state: &mut CsFM, i: usize) {
+    refresh_transfer_listings(state, i);
+    let finished = state.transfers.remove(i);
+    // A cut item can only be moved once. Those that failed to move can still be pasted again.
+    if finished.transfer == Transfer::Move
+        && let Some((cut, Transfer::Move)) = state.clipboard.as_mut()
+    {
+        cut.retain(|p| !finished.sources.contains(p) || fs::symlink_metadata(p).is_ok());
+        if cut.is_empty() {
+            state.clipboard = None;
+        }
+    }
+}
+
+fn update(state: &mut CsFM, message: Message) -> Task<Message> {
+    match message {
+        Message::PathChanged(s) => {
+            let path = PathBuf::from(s);
+            state.path = path;
+            state.walk_view = None;
+            Task::none()
+        }
+        Message::CDToPath => {
+            match &state.walk_view {
+                Some(WalkView::SmartFolder(i)) => return Task::done(Message::OpenSmartFolder(*i)),
+                Some(WalkView::Search(query)) => return Task::done(Message::Search(query.clone())),
+                None => {}
+            }
+
+            if !state.path.exists() {
+                if state.listed_path.as_ref() == Some(&state.path) {
+                    // The folder on screen was removed from outside
+                    let vanished = state.path.clone();
+                    state.listing_cache.invalidate(&vanished);
+                    state.path = nearest_existing_ancestor(&vanished);
+                    state.folder_notice = Some((
+                        state.path.clone(),
+                        format!("The folder {} no longer exists — moved to {}", vanished.display(), state.path.display()),
+                    ));
+                } else if let Some(completion) = unique_dir_completion(&state.path) {
+                    state.path = completion;
+                }
+            }
+
+            if state.hidden_override.as_ref().is_some_and(|(path, _)| *path != state.path) {
+                state.hidden_override = None;
+            }
+            let show_hidden_files = show_hidden_files(state);
+            if let Some(listing) = state.listing_cache.get(&state.path, show_hidden_files) {
+                return apply_listing(state, listing);
+            }
+
+            // Large or slow directories would otherwise freeze the window while being read
+            state.loading = true;
+            let path = state.path.clone();
+            let order = state.config.sort_order();
+            Task::perform(
+                blocking(move || {
+                    let listing =
+                        get_files(path.clone(), show_hidden_files).map(|l| l.sorted(order)).map_err(|e| e.to_string());
+                    (path, listing)
+                }),
+                move |(path, listing)| Message::FilesLoaded(path, show_hidden_files, listing),
+            )
+        }
+        Message::FilesLoaded(path, hidden_shown, listing) => {
+            // Navigation moved on or hidden files were toggled while this was loading
+            if path != state.path || hidden_shown != show_hidden_files(state) || state.walk_view.is_some() {
+                return Task::none();
+            }
+            state.loading = false;
+
+            match listing {
+                Ok(listing) => {
+                    state.listing_cache.insert(&path, hidden_shown, &listing);
+                    apply_listing(state, listing)
+                }
+                Err(e) => {
+                    error_zenity(format!("Couldn't open {}: {}", path.display(), e));
+                    Task::none()
+                }
+            }
+        }
+        Message::OpenInNewWindow(path) => {
+            // Every window is a process of its own
+            if blocked_by_safe_mode(state, "Opening new windows") {
+                return Task::none();
+            }
+            if let Err(e) = open_new_window(&path) {
+                error_zenity(format!("Couldn't open a new window: {}", e));
+            }
+
+            Task::none()
+        }
+        Message::ShowProperties(path) => {
+            state.properties = state.current_files.iter().find(|f| f.path == path).map(|entry| {
+                let metadata = fs::symlink_metadata(&path).ok();
+                Properties {
+                    entry: entry.clone(),
+                    created: metadata.as_ref().and_then(|m| birth_time(&path, m)),
+                    mode: metadata.map(|m| std::os::unix::fs::PermissionsExt::mode(&m.permissions())),
+                    acl: read_acl(&path).map(|acl| acl.into_iter().map(|e| (acl_entry_who(e.tag), e)).collect()),
+                    acl_expanded: false,
+                    opens_with: (!entry.is_dir)
+                        .then(|| open_override(&state.config, &path))
+                        .flatten()
+                        .map(|(key, o)| format!("{}{} (open_overrides.{})", o.command(), if o.terminal() { " in a terminal" } else { "" }, key)),
+                }
+            });
+            state.modal = state.properties.is_some().then_some(Modal::Properties);
+
+            Task::none()
+        }
+        Message::GoToLinkTarget(link, fully) => {
+            let target = match resolve_link(&link, fully, |p| fs::read_link(p).ok()) {
+                Ok(target) => target,
+                Err(LinkError::NotALink) => return Task::none(),
+                Err(LinkError::TooManyHops) => {
+                    error_zenity(format!("'{}' leads into a loop of links", link.display()));
+                    return Task::none();
+                }
+            };
+            if fs::symlink_metadata(&target).is_err() {
+                error_zenity(format!("'{}' points to {}, which doesn't exist", link.display(), target.display()));
+                return Task::none();
+            }
+            let Some(parent) = target.parent() else {
+                return Task::done(Message::CD(target));
+            };
+
+            state.path = parent.to_path_buf();
+            state.walk_view = None;
+            let hidden = target.file_name().is_some_and(|n| n.as_bytes().starts_with(b"."));
+            if hidden && !state.config.show_hidden_files {
+                state.hidden_override = Some((state.path.clone(), true));
+            }
+            state.revealed_entry = Some(target);
+
+            Task::done(Message::CDToPath)
+        }
+        Message::ToggleAclSection => {
+            if let Some(properties) = state.properties.as_mut() {
+                properties.acl_expanded = !properties.acl_expanded;
+            }
+
+            Task::none()
+        }
+        Message::UseModeAsDefault(is_dir, mode) => {
+            let mode = Some(format!("{:o}", mode & 0o7777));
+            if is_dir {
+                state.config.new_dir_mode = mode;
+            } else {
+                state.config.new_file_mode = mode;
+            }
+            if let Err(e) = save_config(&state.config) {
+                error_zenity(format!("Failed to save config: {}", e));
+            }
+
+            Task::none()
+        }
+        Message::ChooseTransferTarget(transfer, sources) => {
+            let dir = state.path.clone();
+            state.folder_chooser = Some(FolderChooser { transfer, sources, subdirs: subdirs(state, &dir), dir });
+            state.modal = Some(Modal::ChooseFolder);
+
+            Task::none()
+        }
+        Message::ChooserCD(dir) => {
+            let subdirs = subdirs(state, &dir);
+            if let Some(chooser) = state.folder_chooser.as_mut() {
+                chooser.dir = dir;
+                chooser.subdirs = subdirs;
+            }
+
+            Task::none()
+        }
+        Message::ChooserConfirm => {
+            let Some(chooser) = state.folder_chooser.take() else {
+                return Task::none();
+            };
+            state.modal = None;
+
+            plan_transfer(chooser.transfer, chooser.sources, chooser.dir, false)
+        }
+        Message::NewDir | Message::NewFile => {
+            if state.walk_view.is_some() {
+                return Task::none();
+            }
+            let is_dir = matches!(message, Message::NewDir);
+            let (prompt, initial) = if is_dir { ("Name of the new folder:", "New Folder") } else { ("Name of the new file:", "New File") };
+            let name = if state.config.inline_create {
+                initial.to_string()
+            } else {
+                let Some(name) = entry_zenity(prompt.to_string(), initial) else {
+                    return Task::none();
+                };
+                name
+            };
+            let name = name.trim();
+            if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+                error_zenity(format!("'{}' can't be used as a name", name));
+                return Task::none();
+            }
+
+            // A taken name gets a number rather than an error
+            let path = numbered_name(&state.path, OsStr::new(name), is_dir);
+            let mode = if is_dir { &state.config.new_dir_mode } else { &state.config.new_file_mode };
+            if let Err(e) = create_item(&path, is_dir, mode.as_deref()) {
+                error_zenity(format!("Failed to create {}: {}", path.display(), describe_io_error(&path, &e)));
+                return Task::none();
+            }
+            oplog::record_in_background(vec![oplog::Operation::now(oplog::Action::Created, &path, None)]);
+            // The placeholder name may still change, the permissions won't
+            if let Ok(metadata) = fs::metadata(&path) {
+                use std::os::unix::fs::MetadataExt;
+
+                let kind = if is_dir { "New folder" } else { "New file" };
+                state.toast = Some((format!("{} created with permissions {}", kind, mode_summary(metadata.mode())), std::time::Instant::now()));
+            }
+
+            if state.config.inline_create {
+                return start_placeholder_rename(state, path, is_dir);
+            }
+            state.revealed_entry = Some(path);
+            Task::done(Message::CDToPath)
+        }
+        Message::StartRename(path) => {
+            // Editing a lossy copy of the name would rename the item to the replacement characters
+            let Some(name) = path.file_name().unwrap_or_default().to_str().map(str::to_string) else {
+                error_zenity(format!(
+                    "{} isn't valid UTF-8 and can't be renamed here. Use a terminal, e.g. mv, instead.",
+                    path.display()
+                ));
+                return Task::none();
+            };
+            // Start with the cursor before the extension, where most edits happen
+            let stem_len = match (path.is_dir(), name.rfind('.')) {
+                (false, Some(dot)) if dot > 0 => name[..dot].chars().count(),
+                _ => name.chars().count(),
+            };
+            state.renaming = Some((path, name));
+
+            Task::batch([
+                text_input::focus(rename_input_id()),
+                text_input::move_cursor_to(rename_input_id(), stem_len),
+            ])
+        }
+        Message::RenameInput(name) => {
+            if let Some((_, edited)) = state.renaming.as_mut() {
+                *edited = name;
+            }
+
+            Task::none()
+        }
+        Message::Rename(path, name) => {
+            state.renaming = None;
+            state.placeholder = None;
+            // Not trimmed, leading and trailing spaces are legal and sometimes wanted
+            if name.is_empty() || Some(name.as_str()) == path.file_name().and_then(OsStr::to_str) {
+                return Task::none();
+            }
+            if name.contains('/') || name == "." || name == ".." {
+                error_zenity(format!("'{}' can't be used as a name", name));
+                return Task::none();
+            }
+
+            let target = path.with_file_name(&name);
+            let result = match rename_item(&path, &target) {
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    let kind = if target.is_dir() { "A folder" } else { "A file" };
+                    if !question_zenity(format!("{} named '{}' already exists. Replace it?", kind, name)) {
+                        return Task::none();
+                    }
+                    replace_item(&path, &target)
+                }
+                result => result,
+            };
+            if let Err(e) = result {
+                error_zenity(format!("Failed to rename: {}", describe_io_error(&path, &e)));
+                return Task::none();
+            }
+            oplog::record_in_background(vec![oplog::Operation::now(oplog::Action::Renamed, &path, Some(&target))]);
+
+            invalidate_parent_listing(state, &path);
+            state.revealed_entry = Some(target);
+            Task::done(Message::CDToPath)
+        }
+        Message::QuickLook => {
+            if state.preview.as_ref().is_some_and(|p| p.quick_look) && state.modal == Some(Modal::Preview) {
+                state.modal = None;
+                state.preview = None;
+                return Task::none();
+            }
+            if state.modal.is_some() {
+                return Task::none();
+            }
+
+            let target = state.hovered_entry.as_ref().or(state.revealed_entry.as_ref());
+            let Some(entry) = state.current_files.iter().find(|f| Some(&f.path) == target && !f.is_dir) else {
+                return Task::none();
+            };
+            state.preview = Some(quick_look(entry));
+            state.revealed_entry = Some(entry.path.clone());
+            state.modal = Some(Modal::Preview);
+
+            Task::none()
+        }
+        Message::QuickLookStep(step) => {
+            let Some(current) = state.preview.as_ref().filter(|p| p.quick_look && state.modal == Some(Modal::Preview)) else {
+                return Task::none();
+            };
+            let files: Vec<&FileEntry> = state.current_files.iter().filter(|f| !f.is_dir).collect();
+            let Some(i) = files.iter().position(|f| f.path == current.path) else {
+                return Task::none();
+            };
+            let Some(next) = i.checked_add_signed(step).and_then(|i| files.get(i)) else {
+                return Task::none();
+            };
+
+            // The list highlight follows, so closing leaves the last previewed file marked
+            state.revealed_entry = Some(next.path.clone());
+            state.preview = Some(quick_look(next));
+
+            Task::none()
+        }
+        Message::ShowOperationLog => {
+            let mut operations = oplog::read();
+            operations.reverse();
+            state.operation_log = Some(OperationLogView { operations, filter: String::new(), date: String::new() });
+            state.modal = Some(Modal::OperationLog);
+
+            Task::none()
+        }
+        Message::OperationLogFilter(filter) => {
+            if let Some(log) = state.operation_log.as_mut() {
+                log.filter = filter;
+            }
+
+            Task::none()
+        }
+        Message::OperationLogDate(date) => {
+            if let Some(log) = state.operation_log.as_mut() {
+                log.date = date;
+            }
+
+            Task::none()
+        }
+        Message::OpenContainingFolder(path) => {
+            state.modal = None;
+            state.operation_log = None;
+            let Some(parent) = path.parent().filter(|p| p.is_dir()) else {
+                error_zenity(format!("{} no longer exists", path.parent().unwrap_or(&path).display()));
+                return Task::none();
+            };
+
+            state.path = parent.to_path_buf();
+            state.walk_view = None;
+            state.revealed_entry = Some(path);
+
+            Task::done(Message::CDToPath)
+        }
+        Message::CopyPath(path) => {
+            state.clipboard = Some((vec![path], Transfer::Copy));
+
+            Task::none()
+        }
+        Message::CutPath(path) => {
+            state.clipboard = Some((vec![path], Transfer::Move));
+
+            Task::none()
+        }
+        Message::CopySelection => {
+            state.clipboard = Some((selection_in_order(state), Transfer::Copy));
+
+            Task::none()
+        }
+        Message::CutSelection => {
+            state.clipboard = Some((selection_in_order(state), Transfer::Move));
+
+            Task::none()
+        }
+        Message::Paste => {
+            let Some((sources, transfer)) = state.clipboard.clone() else {
+                return Task::none();
+            };
+
+            // Cut items stay on the clipboard until the move is done, see TransferDone
+            plan_transfer(transfer, sources, state.path.clone(), true)
+        }
+        Message::StartTransfer(transfer, dest, pairs, shortfall) => {
+            if let Some((needed, available)) = shortfall {
+                let question = format!(
+                    "This needs {} but only {} is free in {}. Start anyway?",
+                    format_size(needed),
+                    format_size(available),
+                    dest.display()
+                );
+                if !question_zenity(question) {
+                    return Task::none();
+                }
+            }
+
+            if transfer != Transfer::Trash {
+                state.last_transfer_dir = Some(dest.clone());
+            }
+            let id = state.next_transfer_id;
+            state.next_transfer_id += 1;
+            state.transfers.push(RunningTransfer {
+                id,
+                transfer,
+                sources: pairs.iter().map(|(source, _)| source.clone()).collect(),
+                dest,
+                stalled: vec![],
+            });
+
+            run_transfer_task(id, transfer, pairs)
+        }
+        Message::TransferDone(id, outcome) => {
+            let Some(i) = state.transfers.iter().position(|t| t.id == id) else {
+                return Task::none();
+            };
+            // Whatever got done before the destination filled up is shown either way
+            if outcome.stalled.is_empty() {
+                finish_transfer(state, i);
+            } else {
+                refresh_transfer_listings(state, i);
+                state.transfers[i].stalled = outcome.stalled;
+            }
+            if !outcome.errors.is_empty() {
+                error_zenity(outcome.errors.join("\n"));
+            }
+
+            Task::done(Message::CDToPath)
+        }
+        Message::ResumeTransfer(id) => {
+            let Some(running) = state.transfers.iter_mut().find(|t| t.id == id && !t.stalled.is_empty()) else {
+                return Task::none();
+            };
+
+            run_transfer_task(id, running.transfer, std::mem::take(&mut running.stalled))
+        }
+        Message::CancelTransfer(id) => {
+            let Some(i) = state.transfers.iter().position(|t| t.id == id && !t.stalled.is_empty()) else {
+                return Task::none();
+            };
+            finish_transfer(state, i);
+
+            Task::done(Message::CDToPath)
+        }
+        Message::RevealHidden => {
+            state.hidden_override = Some((state.path.clone(), true));
+
+            // Reruns a smart folder or search too
+            Task::done(Message::CDToPath)
+        }
+        Message::Up => {
+            state.walk_view = None;
+            state.path = state.path.parent().unwrap_or(PathBuf::from("/").as_path()).to_path_buf();
+
+            Task::done(Message::CDToPath)
+        }
+        Message::Trash(path) => {
+            // Trashing across filesystems copies, so it runs in the background like a move
+            Task::done(Message::StartTransfer(Transfer::Trash, trash::files_dir(), vec![(path, trash::files_dir())], None))
+        }
+        Message::TrashSelection => {
+            let pairs = selection_in_order(state).into_iter().map(|path| (path, trash::files_dir())).collect();
+            state.selected.clear();
+
+            Task::done(Message::StartTransfer(Transfer::Trash, trash::files_dir(), pairs, None))
+        }
+        Message::DeleteSelection => {
+            let paths = selection_in_order(state);
+            if !question_zenity(format!("Permanently delete {} items? They won't go to the trash.", paths.len())) {
+                return Task::none();
+            }
+
+            let mut operations = Vec::new();
+            let mut errors = Vec::new();
+            for path in paths {
+                match delete(&path) {
+                    Ok(()) => {
+                        trash::forget(&path);
+                        operations.push(oplog::Operation::now(oplog::Action::Deleted, &path, None));
+                    }
+                    Err(e) => errors.push(format!("{}: {}", path.display(), describe_io_error(&path, &e))),
+                }
+                state.listing_cache.invalidate(&path);
+            }
+            oplog::record_in_background(operations);
+            if !errors.is_empty() {
+                error_zenity(format!("Failed to delete:\n{}", errors.join("\n")));
+            }
+            state.selected.clear();
+            state.listing_cache.invalidate(&state.path);
+
+            Task::done(Message::CDToPath)
+        }
+        Message::Restore(path) => {
+            match trash::restore(&path) {
+                Ok(original) => {
+                    oplog::record_in_background(vec![oplog::Operation::now(oplog::Action::Restored, &path, Some(&original))]);
+                    invalidate_parent_listing(state, &original);
+                }
+                Err(e) => {
+                    error_zenity(format!("Failed to restore {}: {}", path.display(), describe_io_error(&path, &e)));
+                }
+            }
+            invalidate_parent_listing(state, &path);
+
+            Task::done(Message::CDToPath)
+        }
+        Message::DeleteFile(path) => {
+            let file_name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string();
+            let out = question_zenity(format!("Permanently delete '{}'? It won't go to the trash.", file_name));
+            if out {
+                match delete(&path) {
+                    Ok(()) => {
+                        trash::forget(&path);
+                        oplog::record_in_background(vec![oplog::Operation::now(oplog::Action::Deleted, &path, None)]);
+                    }
+                    Err(e) => {
+                        error_zenity(format!("Failed to delete: {}", describe_io_error(&path, &e)));
+                    }
+                }
+                invalidate_parent_listing(state, &path);
+            }
+            Task::done(Message::CDToPath)
+        }
+        Message::DeleteDir(path) => {
+            let file_name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string();
+            let out = question_zenity(format!("Permanently delete '{}' and all contents? It won't go to the trash.", file_name));
+            if out {
+                match delete(&path) {
+                    Ok(()) => {
+                        trash::forget(&path);
+                        oplog::record_in_background(vec![oplog::Operation::now(oplog::Action::Deleted, &path, None)]);
+                    }
+                    Err(e) => {
+                        error_zenity(format!("Failed to delete dir: {}", describe_io_error(&path, &e)));
+                    }
+                }
+                invalidate_parent_listing(state, &path);
+                state.listing_cache.invalidate(&path);
+            }
+            Task::done(Message::CDToPath)
+        }
+        Message::Open(path) => {
+            if blocked_by_safe_mode(state, "Opening files in other applications") {
+                return Task::none();
+            }
+            // Deleted or renamed from outside since the list was read
+            if !path.exists() {
+                error_zenity(format!("Couldn't open {}: it no longer exists", path.display()));
+                invalidate_parent_listing(state, &path);
+                return Task::done(Message::CDToPath);
+            }
+
+            if let Some((_, open_override)) = open_override(&state.config, &path) {
+                let mut command = expand_command(open_override.command(), &path);
+                if open_override.terminal() {
+                    command.splice(0..0, [terminal_program(&state.config), "-e".to_string()]);
+                }
+                if let Some((program, args)) = command.split_first()
+                    && let Err(e) = Command::new(program).args(args).spawn()
+                {
+                    error_zenity(format!("Couldn't start '{}': {}", program, e));
+                }
+                return Task::none();
+            }
+            if let Some(size) = large_file_size(state, &path) {
+                if let Some(&action) = state.large_file_choices.get(&extension_key(&path)) {
+                    return Task::done(Message::OpenLarge(path, action));
+                }
+                state.large_file = Some(LargeFilePrompt { path, size, remember: false });
+                state.modal = Some(Modal::LargeFile);
+                return Task::none();
+            }
+
+            // No handler for the type, or the launcher itself failing, shouldn't take csfm down
+            if let Err(e) = open::that_detached(&path) {
+                error_zenity(format!("Couldn't open {}: {}", path.display(), e));
+            }
+
+            Task::none()
+        }
+        Message::LargeFileRemember(remember) => {
+            if let Some(prompt) = state.large_file.as_mut() {
+                prompt.remember = remember;
+            }
+
+            Task::none()
+        }
+        Message::OpenLarge(path, action) => {
+            if let Some(prompt) = state.large_file.take() {
+                if prompt.remember && prompt.path == path {
+                    state.large_file_choices.insert(extension_key(&path), action);
+                }
+                state.modal = None;
+            }
+
+            match action {
+                LargeFileAction::OpenAnyway => {
+                    if let Err(e) = open::that_detached(&path) {
+                        error_zenity(format!("Failed to open {}: {}", path.display(), e));
+                    }
+                }
+                LargeFileAction::Preview => match read_preview(&path) {
+                    Ok(preview) => {
+                        state.preview = Some(preview);
+                        state.modal = Some(Modal::Preview);
+                    }
+                    Err(e) => {
+                        error_zenity(format!("Failed to read {}: {}", path.display(), e));
+                    }
+                },
+                LargeFileAction::Pager => {
+                    let terminal = terminal_program(&state.config);
+                    let result = Command::new(&terminal).arg("-e").arg("less").arg("--").arg(&path).spawn();
+                    if let Err(e) = result {
+                        error_zenity(format!("Failed to start {}: {}", terminal, e));
+                    }
+                }
+            }
+
+            Task::none()
+        }
+        Message::FollowExternalLink(path) => {
+            if state.config.confirm_symlink_leave {
+                let target = fs::canonicalize(&path).unwrap_or(path.clone());
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                if !question_zenity(format!("'{}' leads outside this folder, to '{}'. Follow it?", file_name, target.display())) {
+                    return Task::none();
+                }
+            }
+
+            Task::done(Message::CD(path))
+        }
+        Message::OpenInEditor(path, line) => {
+            if blocked_by_safe_mode(state, "Opening files in an editor") {
+                return Task::none();
+            }
+            let Some(template) = &state.config.editor else {
+                return Task::none();
+            };
+
+            let command = editor_command(template, &path, line);
+            if let Some((program, args)) = command.split_first()
+                && let Err(e) = Command::new(program).args(args).spawn()
+            {
+                error_zenity(format!("Couldn't start editor '{}': {}", program, e));
+            }
+
+            Task::none()
+        }
+        Message::CopyDataUri(path) => {
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if size > DATA_URI_MAX_SIZE {
+                error_zenity(format!("'{}' is too large for a data URI ({} KiB, limit is {} KiB)", path.display(), size / 1024, DATA_URI_MAX_SIZE / 1024));
+                return Task::none();
+            }
+
+            match fs::read(&path) {
+                Ok(data) => iced::clipboard::write(format!("data:{};base64,{}", mime_type(&path), base64_encode(&data))),
+                Err(e) => {
+                    error_zenity(format!("Couldn't read {}: {}", path.display(), e));
+                    Task::none()
+                }
+            }
+        }
+        Message::WelcomeTheme(theme) => {
+            state.config.theme = theme.to_string();
+
+            Task::none()
+        }
+        Message::WelcomeHiddenFiles(show) => {
+            state.config.show_hidden_files = show;
+
+            Task::done(Message::CDToPath)
+        }
+        Message::WelcomeAddBookmark(title, path) => {
+            let Some(path) = path.to_str().map(str::to_string) else {
+                return Task::none();
+            };
+            if !state.config.sidebar.bookmarks.iter().any(|l| l.path == path) {
+                state.config.sidebar.bookmarks.push(Location { title, path, from_gtk: false, hidden: None });
+            }
+
+            Task::none()
+        }
+        Message::WelcomeFinish => {
+            state.welcome = false;
+            mark_welcome_done();
+            if let Err(e) = save_config(&state.config) {
+                error_zenity(format!("Failed to save config: {}", e));
+            }
+
+            Task::none()
+        }
+        Message::WelcomeUseDefaults => {
+            state.welcome = false;
+            mark_welcome_done();
+            state.config = Config::default();
+            if let Err(e) = save_config(&state.config) {
+                error_zenity(format!("Failed to save config: {}", e));
+            }
+
+            Task::done(Message::CDToPath)
+        }
+        Message::AddBookmark(path) => {
+            // A lossy conversion would bookmark a different, most likely nonexistent path
+            let Some(path_str) = path.to_str() else {
+                error_zenity(format!("{} can't be bookmarked: its name isn't valid UTF-8, which the config file can't store", path.display()));
+                return Task::none();
+            };
+            let location = Location {
+                title: path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string(),
+                path: path_str.to_string(),
+                from_gtk: false,
+                hidden: None,
+            };
+            if state.config.sidebar.bookmarks.iter().any(|l| l.path == location.path) {
+                return Task::none();
+            }
+
+            state.config.sidebar.bookmarks.push(location.clone());
+            bookmarks_changed(state, &[location], &[]);
+
+            Task::none()
+        }
+        Message::OpenBookmark(i) => {
+            let Some(location) = state.config.sidebar.bookmarks.get(i) else {
+                return Task::none();
+            };
+            let path = PathBuf::from(&location.path);
+            state.hidden_override = location.hidden.map(|show| (path.clone(), show));
+
+            Task::done(Message::CD(path))
+        }
+        Message::SaveBookmarkView(i) => {
+            let show = show_hidden_files(state);
+            let Some(location) = state.config.sidebar.bookmarks.get_mut(i) else {
+                return Task::none();
+            };
+            location.hidden = Some(show);
+            if let Err(e) = save_config(&state.config) {
+                error_zenity(format!("Failed to save config: {}", e));
+            }
+
+            Task::none()
+        }
+        Message::RemoveBookmark(i) => {
+            if i >= state.config.sidebar.bookmarks.len() {
+                return Task::none();
+            }
+
+            let location = state.config.sidebar.bookmarks.remove(i);
+            bookmarks_changed(state, &[], &[location.path]);
+
+            Task::none()
+        }
+        Message::ExportBookmarks => {
+            let Some(path) = file_zenity("Export bookmarks", true) else {
+                return Task::none();
+            };
+
+            let result = export_bookmarks(&state.config.sidebar.bookmarks)
+                .and_then(|data| write_atomic(&path, data.as_bytes()).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                error_zenity(format!("Failed to export bookmarks: {}", e));
+            }
+
+            Task::none()
+        }
+        Message::ImportBookmarks(replace) => {
+            let Some(path) = file_zenity("Import bookmarks", false) else {
+                return Task::none();
+            };
+
+            let imported = fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|data| import_bookmarks(&data));
+            let imported = match imported {
+                Ok(imported) => imported,
+                Err(e) => {
+                    error_zenity(format!("Failed to import {}: {}", path.display(), e));
+                    return Task::none();
+                }
+            };
+
+            let (added, removed) = merge_bookmarks(&mut state.config.sidebar.bookmarks, imported, replace);
+            bookmarks_changed(state, &added, &removed);
+
+            Task::none()
+        }
+        Message::DismissConfigIssues => {
+            state.config_issues.clear();
+
+            Task::none()
+        }
+        Message::DiffWithClipboard(path) => {
+            iced::clipboard::read().map(move |contents| Message::DiffWithClipboardContents(path.clone(), contents))
+        }
+        Message::DiffWithClipboardContents(path, contents) => {
+            if blocked_by_safe_mode(state, "Comparing files") {
+                return Task::none();
+            }
+
+            // Accept both plain paths and file:// URIs as copied by other file managers
+            let other = contents
+                .as_deref()
+                .and_then(|c| c.lines().next())
+                .map(|l| l.trim())
+                .map(|l| PathBuf::from(l.strip_prefix("file://").unwrap_or(l)));
+            let Some(other) = other.filter(|p| p.is_file()) else {
+                error_zenity("The clipboard doesn't contain the path of an existing file".to_string());
+                return Task::none();
+            };
+
+            let tool = state.config.diff_tool.clone().unwrap_or("meld".to_string());
+            let mut args = tool.split_whitespace();
+            let Some(program) = args.next() else {
+                return Task::none();
+            };
+            if let Err(e) = Command::new(program).args(args).arg(&other).arg(&path).spawn() {
+                error_zenity(format!("Couldn't start diff tool '{}': {}", program, e));
+            }
+
+            Task::none()
+        }
+        Message::CD(path) => {
+            state.path = path;
+            state.walk_view = None;
+
+            Task::done(Message::CDToPath)
+        }
+        Message::QuitApp(id) => {
+            // Closing the last window ends the application
+            match id {
+                Some(id) => iced::window::close(id),
+                None => iced::window::get_latest().and_then(iced::window::close),
+            }
+        }
+        Message::ToggleSidebar => {
+            state.sidebar_open = !state.sidebar_open;
+
+            Task::none()
+        }
+        Message::ToggleTreeView => {
+            state.tree_view = !state.tree_view;
+
+            Task::none()
+        }
+        Message::SetSort(mode) => {
+            state.config.sort_mode = mode;
+            apply_sort(state)
+        }
+        Message::SortByColumn(mode) => {
+            if state.config.sort_mode == mode {
+                state.config.sort_descending = !state.config.sort_descending;
+            } else {
+                state.config.sort_mode = mode;
+                state.config.sort_descending = false;
+            }
+            apply_sort(state)
+        }
+        Message::ToggleMixFolders => {
+            state.config.mix_folders = !state.config.mix_folders;
+            apply_sort(state)
+        }
+        Message::ToggleSortDirection => {
+            state.config.sort_descending = !state.config.sort_descending;
+            apply_sort(state)
+        }
+        Message::FilterChanged(filter) => {
+            state.filter = filter;
+            // Actions on the selection must not reach entries the filter now hides
+            let shown: HashSet<PathBuf> = shown_files(state).into_iter().map(|f| f.path.clone()).collect();
+            state.selected.retain(|p| shown.contains(p));
+            if state.selection_anchor.as_ref().is_some_and(|a| !shown.contains(a)) {
+                state.selection_anchor = None;
+            }
+
+            Task::none()
+        }
+        Message::FocusFilter => text_input::focus(filter_input_id()),
+        Message::Back | Message::Forward => {
+            let index = match message {
+                Message::Back => state.nav_index.checked_sub(1),
+                _ => Some(state.nav_index + 1).filter(|&i| i < state.nav_history.len()),
+            };
+            let Some(index) = index else {
+                return Task::none();
+            };
+
+            // Moving along the history keeps it, apply_listing only truncates it for a new place
+            state.nav_index = index;
+            state.path = state.nav_history[index].clone();
+            state.walk_view = None;
+
+            Task::done(Message::CDToPath)
+        }
+        Message::ToggleCrumbMenu => {
+            state.crumb_menu_open = !state.crumb_menu_open;
+
+            Task::none()
+        }
+        Message::ToggleHighlightRecent => {
+            state.highlight_recent = !state.highlight_recent;
+
+            Task::none()
+        }
+        Message::RecentChangesTick => Task::none(),
+        Message::ToastTick => {
+            if state.toast.as_ref().is_some_and(|(_, shown)| shown.elapsed() >= TOAST_DURATION) {
+                state.toast = None;
+            }
+
+            Task::none()
+        }
+        Message::Refresh(settle) => {
+            if settle {
+                state.recent_settled_at = Some(std::time::SystemTime::now());
+            }
+            state.listing_cache.invalidate(&state.path);
+
+            Task::done(Message::CDToPath)
+        }
+        Message::ToggleTreeNode(path) => {
+            if state.tree.contains_key(&path) {
+                state.tree.retain(|p, _| !p.starts_with(&path));
+                return Task::none();
+            }
+
+            let show_hidden_files = show_hidden_files(state);
+            let children = state.listing_cache.get(&path, show_hidden_files);
+            let loaded = children.is_some();
+            state.tree.insert(path.clone(), TreeNode { children: children.map(Ok), load_anyway: false });
+            if loaded {
+                return Task::none();
+            }
+            let order = state.config.sort_order();
+            Task::perform(
+                blocking(move || {
+                    let listing =
+                        get_files(path.clone(), show_hidden_files).map(|l| l.sorted(order)).map_err(|e| e.to_string());
+                    (path, listing)
+                }),
+                |(path, listing)| Message::TreeNodeLoaded(path, listing),
+            )
+        }
+        Message::TreeNodeLoaded(path, listing) => {
+            if let Ok(listing) = &listing {
+                state.listing_cache.insert(&path, show_hidden_files(state), listing);
+            }
+            // Collapsed, or the visit ended, while loading
+            if let Some(node) = state.tree.get_mut(&path) {
+                let order = state.config.sort_order();
+                node.children = Some(listing.map(|mut listing| {
+                    listing.sort(order);
+                    listing
+                }));
+            }
+
+            Task::none()
+        }
+        Message::TreeLoadAnyway(path) => {
+            if let Some(node) = state.tree.get_mut(&path) {
+                node.load_anyway = true;
+            }
+
+            Task::none()
+        }
+        Message::ToggleFullPaths => {
+            state.config.show_full_paths = !state.config.show_full_paths;
+
+            Task::none()
+        }
+        Message::OpenNetworkLocation(i) => {
+            let Some(location) = state.config.network_location.get(i) else {
+                return Task::none();
+            };
+            let NetworkTarget::Local(path) = resolve_network_uri(&location.uri) else {
+                return Task::none();
+            };
+            let (uri, title) = (location.uri.clone(), location.title.clone());
+            state.connecting = Some(uri.clone());
+
+            // A mount whose server went away can hang, so the check doesn't block the window
+            Task::perform(
+                blocking(move || {
+                    let result = match fs::read_dir(&path) {
+                        Ok(_) => Ok(path),
+                        Err(e) => Err(format!("Couldn't open {}: {}", title, describe_io_error(&path, &e))),
+                    };
+                    (uri, result)
+                }),
+                |(uri, result)| Message::NetworkLocationOpened(uri, result),
+            )
+        }
+        Message::NetworkLocationOpened(uri, result) => {
+            // Only the latest attempt counts
+            if state.connecting.as_ref() != Some(&uri) {
+                return Task::none();
+            }
+            state.connecting = None;
+
+            match result {
+                Ok(path) => Task::done(Message::CD(path)),
+                Err(error) => {
+                    state.toast = Some((error, std::time::Instant::now()));
+                    Task::none()
+                }
+            }
+        }
+        Message::AddNetworkLocation => {
+            let Some(title) = input_zenity("Name of the network location:".to_string()) else {
+                return Task::none();
+            };
+            let Some(uri) = input_zenity("Address (path, sftp://, smb:// or ftp://):".to_string()) else {
+                return Task::none();
+            };
+            if title.trim().is_empty() || uri.trim().is_empty() {
+                error_zenity("Name and address must not be empty".to_string());
+                return Task::none();
+            }
+
+            state.config.network_location.push(NetworkLocation { title: title.trim().to_string(), uri: uri.trim().to_string() });
+            if let Err(e) = save_config(&state.config) {
+                error_zenity(format!("Failed to save config: {}", e));
+            }
+
+            Task::none()
+        }
+        Message::SubmitPath => {
+            if state.history_open && state.history_cursor.is_some() {
+                return Task::done(Message::HistoryCommit);
+            }
+
+            Task::done(Message::CDToPath)
+        }
+        Message::ToggleHistory => {
+            state.history_open = !state.history_open && !state.path_history.is_empty();
+            state.history_cursor = None;
+
+            Task::none()
+        }
+        Message::HistoryShortcut => {
+            if state.history_open {
+                return Task::done(Message::CloseHistory);
+            }
+
+            let path_input = iced::advanced::widget::Id::from(path_input_id());
+            iced::advanced::widget::operate(iced::advanced::widget::operation::focusable::find_focused()).then(move |focused| {
+                if focused == path_input { Task::done(Message::ToggleHistory) } else { Task::none() }
+            })
+        }
+        Message::CloseHistory => {
+            state.history_open = false;
+            state.history_cursor = None;
+
+            Task::none()
+        }
+        Message::HistoryMove(delta) => {
+            if !state.history_open || state.path_history.is_empty() {
+                return Task::none();
+            }
+
+            let last = state.path_history.len() as isize - 1;
+            let cursor = match state.history_cursor {
+                Some(c) => (c as isize + delta).clamp(0, last),
+                None if delta > 0 => 0,
+                None => last,
+            };
+            state.history_cursor = Some(cursor as usize);
+
+            Task::none()
+        }
+        Message::HistoryCommit => {
+            let selected = state.history_cursor.and_then(|c| state.path_history.get(c).cloned());
+            state.history_open = false;
+            state.history_cursor = None;
+
+            match selected {
+                Some(path) => Task::done(Message::CD(path)),
+                None => Task::none(),
+            }
+        }
+        Message::ReloadSidebar => {
+            match read_config(false) {
+                Ok((mut config, issues)) => {
+                    if config.sync_gtk_bookmarks {
+                        merge_gtk_bookmarks(&mut config);
+                    }
+                    state.config.sidebar = config.sidebar;
+                    state.config.network_location = config.network_location;
+                    state.config_issues = issues;
+                }
+                Err(e) => {
+                    error_zenity(format!("Failed to reload config: {}", e));
+                }
+            }
+
+            Task::done(Message::CheckBookmarks)
+        }
+        Message::WindowFocused => {
+            Task::batch([Task::done(Message::CheckBookmarks), Task::done(Message::CheckCurrentDir)])
+        }
+        Message::CheckCurrentDir => {
+            let shown = state.walk_view.is_none() && state.listed_path.as_ref() == Some(&state.path);
+            if shown && !state.path.is_dir() {
+                return Task::done(Message::CDToPath);
+            }
+
+            Task::none()
+        }
+        Message::CheckBookmarks => {
+            // Stat calls on a dead network mount can block, so keep them off the UI thread
+            let paths: Vec<String> = state.config.sidebar.bookmarks.iter().map(|l| l.path.clone()).collect();
+            Task::perform(
+                blocking(move || paths.into_iter().filter(|p| !Path::new(p).is_dir()).collect()),
+                Message::BookmarksChecked,
+            )
+        }
+        Message::BookmarksChecked(missing) => {
+            state.missing_bookmarks = missing;
+
+            Task::none()
+        }
+        Message::OpenMissingBookmark(path) => {
+            // It may have been mounted since the last check
+            if path.is_dir() {
+                state.missing_bookmarks.retain(|p| Path::new(p) != path);
+                return Task::done(Message::CD(path));
+            }
+
+            error_zenity(format!(
+                "{} isn't available right now.\n\nIf it is on a removable drive or a network share, mount it and try again.",
+                path.display()
+            ));
+            Task::none()
+        }
+        Message::ReloadConfig => {
+            match read_config(false) {
+                Ok((mut config, issues)) => {
+                    if config.sync_gtk_bookmarks {
+                        merge_gtk_bookmarks(&mut config);
+                    }
+                    state.config = config;
+                    state.config_issues = issues;
+                }
+                Err(e) => {
+                    error_zenity(format!("Failed to reload config: {}", e));
+                    return Task::none();
+                }
+            }
+
+            Task::done(Message::CDToPath)
+        }
+        Message::OpenSmartFolder(i) => {
+            let Some(folder) = state.config.smart_folder.get(i) else {
+                return Task::none();
+            };
+
+            let root = expand_home(&folder.root);
+            let rule = folder.rule.clone();
+            if state.walk_view != Some(WalkView::SmartFolder(i)) {
+                state.current_files.clear();
+                state.walk_view_notice = None;
+                state.hidden_count = 0;
+            }
+            if state.hidden_override.as_ref().is_some_and(|(path, _)| *path != root) {
+                state.hidden_override = None;
+            }
+            state.walk_view = Some(WalkView::SmartFolder(i));
+            state.path = root.clone();
+            let options = WalkOptions { show_hidden_files: show_hidden_files(state), ..state.config.walk_options() };
+
+            Task::perform(blocking(move || run_smart_folder(root, rule, options)), move |(files, summary)| Message::SmartFolderLoaded(i, files, summary))
+        }
+        Message::SelectEntry(path, open) => {
+            let now = std::time::Instant::now();
+            let last_click = state.last_click.replace((path.clone(), now));
+            if state.modifiers.control() {
+                if !state.selected.remove(&path) {
+                    state.selected.insert(path.clone());
+                }
+                state.selection_anchor = Some(path);
+                return Task::none();
+            }
+
+            if state.modifiers.shift()
+                && let Some(anchor) = &state.selection_anchor
+            {
+                let shown = shown_files(state);
+                let position = |p: &PathBuf| shown.iter().position(|f| &f.path == p);
+                if let (Some(a), Some(b)) = (position(anchor), position(&path)) {
+                    let range = a.min(b)..=a.max(b);
+                    state.selected = shown[range].iter().map(|f| f.path.clone()).collect();
+                    return Task::none();
+                }
+            }
+
+            // A second click on the same entry in quick succession opens it
+            if last_click.is_some_and(|(clicked, at)| clicked == path && now.duration_since(at) <= DOUBLE_CLICK_TIME) {
+                state.last_click = None;
+                state.selected.clear();
+                return Task::done(*open);
+            }
+            state.selected = HashSet::from([path.clone()]);
+            state.selection_anchor = Some(path);
+
+            Task::none()
+        }
+        Message::ModifiersChanged(modifiers) => {
+            state.modifiers = modifiers;
+
+            Task::none()
+        }
+        Message::HoverEntry(path) => {
+            state.hovered_entry = path.clone();
+            state.tooltip_entry = None;
+            let Some(path) = path else {
+                return Task::none();
+            };
+
+            let mut tasks = vec![];
+            let is_dir = state.current_files.iter().any(|f| f.path == path && f.is_dir);
+            if is_dir && !state.config.disable_prefetch {
+                tasks.push(after(PREFETCH_HOVER_DELAY, Message::HoverSettled(path.clone())));
+            }
+            if !state.config.disable_tooltips {
+                tasks.push(after(TOOLTIP_DELAY, Message::TooltipDue(path)));
+            }
+            Task::batch(tasks)
+        }
+        Message::HoverSettled(path) => {
+            if state.hovered_entry.as_ref() != Some(&path) {
+                return Task::none();
+            }
+            prefetch(state, path)
+        }
+        Message::PrefetchParentDue(path) => {
+            // Dropped when the user already moved on
+            match path.parent() {
+                Some(parent) if path == state.path => prefetch(state, parent.to_path_buf()),
+                _ => Task::none(),
+            }
+        }
+        Message::FileListScrolled(view) => {
+            state.file_list_view = Some(view);
+
+            Task::none()
+        }
+        Message::TooltipDue(path) => {
+            if state.hovered_entry.as_ref() == Some(&path) {
+                state.tooltip_entry = Some(path);
+            }
+
+            Task::none()
+        }
+        Message::Prefetched(path, show_hidden_files, listing) => {
+            state.prefetches_running -= 1;
+            if let Some(listing) = listing {
+                state.listing_cache.insert_prefetched(&path, show_hidden_files, &listing);
+            }
+            Task::none()
+        }
+        Message::SmartFolderLoaded(i, files, summary) => {
+            // Ignore results for a smart folder the user already left
+            if state.walk_view == Some(WalkView::SmartFolder(i)) {
+                // Re-runs keep the selection of results that still match
+                reconcile_selection(&state.current_files, &files, &mut state.selected, &mut state.selection_anchor);
+                state.current_files = files;
+                state.listed_path = None;
+                state.walk_view_notice = walk_notice(&summary);
+                state.hidden_count = summary.hidden;
+            }
+
+            Task::none()
+        }
+        Message::SearchInput(input) => {
+            state.search_input = input;
+
+            Task::none()
+        }
+        Message::Search(query) => {
+            if query.trim().is_empty() {
+                return Task::none();
+            }
+            let search = WalkView::Search(query.clone());
+            if state.walk_view.as_ref() != Some(&search) {
+                state.current_files.clear();
+                state.walk_view_notice = None;
+                state.hidden_count = 0;
+            }
+            state.walk_view = Some(search);
+            state.filter.clear();
+            let root = state.path.clone();
+            let options = WalkOptions { show_hidden_files: show_hidden_files(state), ..state.config.walk_options() };
+            state.search_generation += 1;
+            let generation = state.search_generation;
+
+            Task::perform(blocking(move || run_search(root, &query, options)), move |(files, summary)| {
+                Message::SearchResults(generation, files, summary)
+            })
+        }
+        Message::SearchResults(generation, files, summary) => {
+            // Ignore results for a search that was left or replaced, also by the same query elsewhere
+            if generation == state.search_generation && matches!(state.walk_view, Some(WalkView::Search(_))) {
+                state.current_files = files;
+                state.listed_path = None;
+                state.walk_view_notice = walk_notice(&summary);
+                state.hidden_count = summary.hidden;
+            }
+
+            Task::none()
+        }
+        Message::ToggleMenu(menu) => {
+            state.open_menu = if state.open_menu == Some(menu) { None } else { Some(menu) };
+
+            Task::none()
+        }
+        Message::CloseMenu => {
+            state.open_menu = None;
+
+            Task::none()
+        }
+        Message::MenuSelect(message) => {
+            state.open_menu = None;
+
+            Task::done(*message)
+        }
+        Message::ShowModal(modal) => {
+            state.modal = Some(modal);
+
+            Task::none()
+        }
+        Message::CloseModal => {
+            state.modal = None;
+            state.folder_chooser = None;
+            state.large_file = None;
+            state.preview = None;
+            state.operation_log = None;
+
+            Task::none()
+        }
+        Message::CopyToClipboard(contents) => {
+            iced::clipboard::write(contents)
+        }
+        Message::KeyPressed(key, modifiers, status, id) => {
+            // The cheat sheet goes away on any key
+            if state.modal == Some(Modal::Shortcuts) {
+                state.modal = None;
+                return Task::none();
+            }
+
+            let actions = bound_actions(&state.config);
+            let action = pressed_action(&actions, state, key.as_ref(), modifiers, status == event::Status::Captured);
+
+            match action.and_then(|a| shortcut_message(state, a, id)) {
+                Some(message) => Task::done(message),
+                None => Task::none(),
+            }
+        }
+        Message::Escape => {
+            let closes_something = state.modal.is_some()
+                || state.renaming.is_some()
+                || state.open_menu.is_some()
+                || state.history_open
+                || state.folder_chooser.is_some()
+                || state.crumb_menu_open
+                || state.preview.is_some();
+            state.modal = None;
+            state.folder_chooser = None;
+            state.open_menu = None;
+            state.crumb_menu_open = false;
+            state.preview = None;
+            if let Some((path, _)) = state.renaming.take()
+                && state.placeholder.take() == Some(path.clone())
+            {
+                remove_placeholder(state, &path);
+                return Task::batch([Task::done(Message::CDToPath), Task::done(Message::CloseHistory)]);
+            }
+            // With nothing else to close, Escape leaves search results for the normal listing
+            if !closes_something && matches!(state.walk_view, Some(WalkView::Search(_))) {
+                state.walk_view = None;
+                return Task::done(Message::CDToPath);
+            }
+
+            Task::done(Message::CloseHistory)
+        }
+        Message::MoveBookmark(from, to) => {
+            let bookmarks = &mut state.config.sidebar.bookmarks;
+            if from >= bookmarks.len() || to >= bookmarks.len() || from == to {
+                return Task::none();
+            }
+
+            let location = bookmarks.remove(from);
+            bookmarks.insert(to, location);
+            if let Err(e) = save_config(&state.config) {
+                error_zenity(format!("Failed to save config: {}", e));
+            }
+
+            Task::none()
+        }
+    }
+}
+
+fn dir_button(state: &'_ CsFM) -> iced::widget::button::Style {
+    let theme = theme(state);
+    
+    Style {
+        border: Border {
+            color: theme.palette().primary,
+            width: 2.0,
+            radius: Radius::new(10.0)
+        },
+        text_color: theme.extended_palette().primary.strong.color,
+        ..Default::default()
+    }
+}
+
+fn disabled_button(state: &'_ CsFM) -> iced::widget::button::Style {
+    let theme = theme(state);
+
+    Style {
+        border: Border {
+            color: theme.extended_palette().background.strong.color,
+            width: 2.0,
+            radius: Radius::new(10.0)
+        },
+        text_color: theme.extended_palette().background.strong.color,
+        ..Default::default()
+    }
+}
+
+fn file_button(state: &'_ CsFM) -> iced::widget::button::Style {
+    let theme = theme(state);
+    
+    Style {
+        border: Border {
+            color: theme.extended_palette().secondary.strong.color,
+            width: 2.0,
+            radius: Radius::new(10.0)
+        },
+        text_color: theme.extended_palette().secondary.strong.color,
+        ..Default::default()
+    }
+}
+
+/// `style` with a highlight when `f` is the revealed entry.
+fn revealed_style(state: &CsFM, f: &FileEntry, style: Style) -> Style {
+    if state.revealed_entry.as_ref() != Some(&f.path) {
+        return style;
+    }
+    let palette = theme(state).extended_palette().primary.weak;
+
+    Style {
+        background: Some(Background::Color(palette.color)),
+        text_color: palette.text,
+        ..style
+    }
+}
+
+fn selected_style(state: &CsFM, f: &FileEntry, style: Style) -> Style {
+    if !state.selected.contains(&f.path) {
+        return style;
+    }
+    let palette = theme(state).extended_palette().primary.strong;
+
+    Style {
+        background: Some(Background::Color(palette.color)),
+        text_color: palette.text,
+        ..style
+    }
+}
+
+fn container_style(theme: &Theme) -> iced::widget::container::Style {
+   iced::widget::container::Style { border: Border { color: theme.palette().primary, width: 5.0, radius: Radius::new(10) }, ..Default::default() } 
+}
+
+fn locations(state: &CsFM) -> Vec<Element<'_, Message>> {
+    let reload = iced::widget::button(text("⟳").size(12))
+        .padding(2)
+        .on_press(Message::ReloadSidebar);
+    let mut locs = vec![
+        row![
+            iced::widget::text("Places").width(Length::Fill),
+            tooltip(reload, container(text("Reload places from config")).style(context_menu_container_style).padding(5), tooltip::Position::Bottom),
+        ].align_y(Alignment::Center).into(),
+    ];
+
+    let count = state.config.sidebar.bookmarks.len();
+    for (i, location) in state.config.sidebar.bookmarks.iter().enumerate() {
+        let title = if location.from_gtk { format!("{} ⇄", location.title) } else { location.title.clone() };
+        let btn: Element<Message> = if state.missing_bookmarks.contains(&location.path) {
+            let btn = iced::widget::button(text(title))
+                .style(|_, _| disabled_button(state))
+                .on_press(Message::OpenMissingBookmark(PathBuf::from(&location.path)))
+                .width(Length::Fill);
+            tooltip(btn, container(text("Not available")).style(context_menu_container_style).padding(5), tooltip::Position::Right).into()
+        } else {
+            iced::widget::button(text(title)).style(|_, _| dir_button(state)).on_press(Message::OpenBookmark(i)).width(Length::Fill).into()
+        };
+        let up = iced::widget::button(text("↑").size(12)).padding(2).on_press_maybe((i > 0).then(|| Message::MoveBookmark(i, i - 1)));
+        let down = iced::widget::button(text("↓").size(12)).padding(2).on_press_maybe((i + 1 < count).then(|| Message::MoveBookmark(i, i + 1)));
+        let entry = row![btn, column![up, down].spacing(2)].spacing(2).align_y(Alignment::Center);
+        let from_gtk = location.from_gtk;
+        locs.push(context_menu::ContextMenu::new(entry, move || {
+            let mut items = column![iced::widget::button(text("Remove bookmark")).on_press(Message::RemoveBookmark(i))].spacing(5);
+            // GTK bookmarks aren't written to csfm.toml, so there is nowhere to keep the view
+            if !from_gtk {
+                items = items.push(iced::widget::button(text("Save current view to bookmark")).on_press(Message::SaveBookmarkView(i)));
+            }
+            container(items).style(context_menu_container_style).padding(10).into()
+        }).into());
+    }
+
+    locs.push(iced::widget::text("Network").into());
+
+    for (i, location) in state.config.network_location.iter().enumerate() {
+        let btn = iced::widget::button(text(location.title.clone()));
+        match resolve_network_uri(&location.uri) {
+            NetworkTarget::Local(_) if state.connecting.as_ref() == Some(&location.uri) => {
+                let label = format!("{} — connecting…", location.title);
+                locs.push(iced::widget::button(text(label)).style(|_, _| disabled_button(state)).into());
+            }
+            NetworkTarget::Local(_) => {
+                locs.push(btn.style(|_, _| dir_button(state)).on_press(Message::OpenNetworkLocation(i)).into());
+            }
+            NetworkTarget::Unavailable(reason) => {
+                let btn = btn.style(|_, _| disabled_button(state));
+                locs.push(tooltip(btn, container(text(reason)).style(context_menu_container_style).padding(5), tooltip::Position::Right).into());
+            }
+        }
+    }
+
+    locs.push(iced::widget::button(text("Add network location…")).on_press(Message::AddNetworkLocation).into());
+
+    if !state.config.smart_folder.is_empty() {
+        locs.push(iced::widget::text("Smart folders").into());
+    }
+
+    for (i, folder) in state.config.smart_folder.iter().enumerate() {
+        locs.push(iced::widget::button(text(folder.title.clone())).style(|_, _| dir_button(state)).on_press(Message::OpenSmartFolder(i)).into());
+    }
+
+    locs
+}
+
+fn context_menu_container_style(theme: &Theme) -> iced::widget::container::Style {
+   iced::widget::container::Style { border: Border { color: theme.palette().primary, width: 5.0, radius: Radius::new(10) }, background: Option::from(Background::Color(theme.palette().background)), ..Default::default() } 
+}
+
+fn path_mount(path: &Path) -> Option<(PathBuf, String)> {
+    mounts::containing(path).map(|mount| (mount.mount_point.clone(), mounts::label(&mount)))
+}
+
+/// The current path as buttons. Everything above a removable or network mount collapses into
+/// one crumb named after the volume, and paths in home start at a home crumb. The collapsed
+/// ancestors are in that crumb's dropdown.
+fn breadcrumb(state: &CsFM) -> Element<'_, Message> {
+    let home = paths::home();
+    let (root, label) = match &state.path_mount {
+        Some((mount_point, label)) if !home.starts_with(mount_point) || !state.path.starts_with(&home) => {
+            (mount_point.clone(), format!("💾 {}", label))
+        }
+        _ if state.path.starts_with(&home) && home.parent().is_some() => (home, "🏠 Home".to_string()),
+        _ => (PathBuf::from("/"), "/".to_string()),
+    };
+
+    let mut crumbs = row![iced::widget::button(text(label)).style(iced::widget::button::text).on_press(Message::CD(root.clone()))]
+        .spacing(2)
+        .align_y(Alignment::Center);
+
+    if root.parent().is_some() {
+        let ancestors: Vec<Element<Message>> = root
+            .ancestors()
+            .skip(1)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .map(|p| {
+                iced::widget::button(text(sanitize_name(&p.to_string_lossy()).0))
+                    .style(iced::widget::button::text)
+                    .width(Length::Fill)
+                    .on_press(Message::CD(p.to_path_buf()))
+                    .into()
+            })
+            .collect();
+        let overlay = container(column(ancestors).spacing(2))
+            .style(context_menu_container_style)
+            .padding(5)
+            .width(250);
+        let chevron = iced::widget::button(text("⌄")).style(iced::widget::button::text).on_press(Message::ToggleCrumbMenu);
+        crumbs = crumbs.push(DropDown::new(chevron, overlay, state.crumb_menu_open).on_dismiss(Message::ToggleCrumbMenu));
+    }
+
+    let mut path = root.clone();
+    for component in state.path.strip_prefix(&root).unwrap_or(Path::new("")).iter() {
+        path.push(component);
+        crumbs = crumbs.push(text("›")).push(
+            iced::widget::button(text(sanitize_name(&component.to_string_lossy()).0))
+                .style(iced::widget::button::text)
+                .on_press(Message::CD(path.clone())),
+        );
+    }
+
+    crumbs.width(Length::Fill).into()
+}
+
+fn path_history_dropdown(state: &CsFM) -> Element<'_, Message> {
+    let chevron = iced::widget::button(text("⌄"))
+        .on_press_maybe((!state.path_history.is_empty()).then_some(Message::ToggleHistory));
+
+    let entries: Vec<Element<Message>> = state
+        .path_history
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let btn = iced::widget::button(text(sanitize_name(&path.to_string_lossy()).0))
+                .width(Length::Fill)
+                .on_press(Message::CD(path.clone()));
+            if state.history_cursor == Some(i) {
+                btn.style(|_, _| dir_button(state)).into()
+            } else {
+                btn.style(iced::widget::button::text).into()
+            }
+        })
+        .collect();
+
+    let overlay = container(column(entries).spacing(2))
+        .style(context_menu_container_style)
+        .padding(5)
+        .width(400);
+
+    DropDown::new(chevron, overlay, state.history_open)
+        .on_dismiss(Message::CloseHistory)
+        .into()
+}
+
+/// Labels and messages of the actions offered for `entry`.
+fn applicable_actions(state: &CsFM, entry: &FileEntry) -> Vec<(&'static str, Message)> {
+    actions()
+        .into_iter()
+        .filter_map(|action| match action.target {
+            Target::Entry(e) if (e.applies)(state, entry) => Some((action.label, (e.message)(entry))),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Labels and messages of the actions offered for the whole selection.
+fn selection_actions(state: &CsFM) -> Vec<(String, Message)> {
+    let paths = selection_in_order(state);
+    let entries: Vec<&FileEntry> = state.current_files.iter().filter(|f| state.selected.contains(&f.path)).collect();
+    let count = paths.len().to_string();
+
+    actions()
+        .into_iter()
+        .filter_map(|action| match action.target {
+            Target::Entry(e) if entries.iter().all(|f| (e.applies)(state, f)) => e.selection,
+            _ => None,
+        })
+        .map(|(label, message)| (label.replace("{}", &count), message(paths.clone())))
+        .collect()
+}
+
+/// Labels of the actions in the list background's menu, with their message when enabled.
+fn background_actions(state: &CsFM) -> Vec<(String, Option<Message>)> {
+    actions()
+        .into_iter()
+        .filter_map(|action| match action.target {
+            Target::Folder { enabled, message } => {
+                let message = message();
+                let label = match (&message, &state.clipboard) {
+                    (Message::Paste, Some((paths, _))) => match paths.as_slice() {
+                        [path] => format!("Paste {}", path.file_name().unwrap_or_default().to_string_lossy()),
+                        _ => format!("Paste {} items", paths.len()),
+                    },
+                    _ => action.label.to_string(),
+                };
+                Some((label, enabled(state).then_some(message)))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether `f` changed within the last few minutes while highlighting recent changes is on.
+fn is_recent(state: &CsFM, f: &FileEntry) -> bool {
+    let Some(modified) = f.modified.filter(|_| state.highlight_recent) else {
+        return false;
+    };
+    let minutes = state.config.recent_changes_minutes.unwrap_or(RECENT_CHANGES_MINUTES);
+    // A window reaching back before the epoch holds everything
+    let window_start = minutes
+        .checked_mul(60)
+        .and_then(|seconds| std::time::SystemTime::now().checked_sub(std::time::Duration::from_secs(seconds)));
+
+    window_start.is_none_or(|start| modified >= start) && state.recent_settled_at.is_none_or(|settled| modified > settled)
+}
+
+/// The current entries the filter lets through, in the order the list shows them: recent
+/// changes first when those are highlighted. The partition keeps the sort order within both
+/// groups.
+fn shown_files(state: &CsFM) -> Vec<&FileEntry> {
+    let filter: String = state.filter.nfc().collect::<String>().to_lowercase();
+    let (recent, rest): (Vec<&FileEntry>, Vec<&FileEntry>) = state
+        .current_files
+        .iter()
+        .filter(|f| filter.is_empty() || f.folded_name.contains(&filter))
+        .partition(|f| is_recent(state, f));
+
+    recent.into_iter().chain(rest).collect()
+}
+
+/// The selected paths, in listing order. Any the list doesn't show come last.
+fn selection_in_order(state: &CsFM) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = state.selected.iter().cloned().collect();
+    let positions: std::collections::HashMap<&Path, usize> = shown_files(state).iter().enumerate().map(|(i, f)| (f.path.as_path(), i)).collect();
+    paths.sort_by_key(|p| positions.get(p.as_path()).copied().unwrap_or(usize::MAX));
+
+    paths
+}
+
+fn entry_context_menu<'a>(state: &'a CsFM, entry: &'a FileEntry) -> Element<'a, Message> {
+    // Right-clicking one of several selected entries acts on all of them
+    let actions: Vec<(String, Message)> = if state.selected.len() > 1 && state.selected.contains(&entry.path) {
+        selection_actions(state)
+    } else {
+        applicable_actions(state, entry).into_iter().map(|(label, message)| (label.to_string(), message)).collect()
+    };
+    let items = actions
+        .into_iter()
+        .map(|(label, message)| iced::widget::button(text(label)).on_press(message).into());
+
+    container(column(items).spacing(5))
+        .style(context_menu_container_style)
+        .padding(10)
+        .into()
+}
+
+/// Why nothing can be created or pasted in what is shown, None in a folder listing.
+fn read_only_view_reason(state: &CsFM) -> Option<&'static str> {
+    match state.walk_view {
+        Some(WalkView::SmartFolder(_)) => Some("Smart folders only gather matching files, open a folder to add items"),
+        Some(WalkView::Search(_)) => Some("Search results only gather matching files, open a folder to add items"),
+        None => None,
+    }
+}
+
+fn new_folder_button(state: &CsFM) -> Element<'_, Message> {
+    let button = iced::widget::button("New folder").on_press_maybe(state.walk_view.is_none().then_some(Message::NewDir));
+    match read_only_view_reason(state) {
+        Some(reason) => tooltip(button, container(text(reason)).style(context_menu_container_style).padding(5), tooltip::Position::Bottom).into(),
+        None => button.into(),
+    }
+}
+
+fn paste_button(state: &CsFM) -> Element<'_, Message> {
+    let can_paste = state.clipboard.is_some() && state.walk_view.is_none();
+    let label = match (&state.clipboard, read_only_view_reason(state)) {
+        (_, Some(reason)) => reason.to_string(),
+        (Some((paths, transfer)), None) => format!(
+            "{} {} here",
+            if *transfer == Transfer::Move { "Move" } else { "Copy" },
+            match paths.as_slice() {
+                [path] => path.display().to_string(),
+                _ => format!("{} items", paths.len()),
+            }
+        ),
+        (None, None) => "Nothing to paste, use Copy or Cut on an item first".to_string(),
+    };
+
+    tooltip(
+        iced::widget::button("Paste").on_press_maybe(can_paste.then_some(Message::Paste)),
+        container(text(label)).style(context_menu_container_style).padding(5),
+        tooltip::Position::Bottom,
+    )
+    .into()
+}
+
+fn background_context_menu(state: &CsFM) -> Element<'_, Message> {
+    let items = background_actions(state)
+        .into_iter()
+        .map(|(label, message)| iced::widget::button(text(label)).on_press_maybe(message).into());
+
+    container(column(items).spacing(5))
+        .style(context_menu_container_style)
+        .padding(10)
+        .into()
+}
+
+/// Sums up the copies and moves writing into the shown directory, e.g. "Copying 12 items here".
+fn transfers_banner(state: &CsFM) -> Option<String> {
+    // Trashing shows in the folder the items leave, copies and moves where they arrive
+    let count = |kind: Transfer| -> usize {
+        state
+            .transfers
+            .iter()
+            .filter(|t| t.transfer == kind && t.stalled.is_empty())
+            .map(|t| match kind {
+                Transfer::Trash => t.sources.iter().filter(|s| s.parent() == Some(state.path.as_path())).count(),
+                _ if t.dest == state.path => t.sources.len(),
+                _ => 0,
+            })
+            .sum()
+    };
+    let items = |n: usize| if n == 1 { "1 item".to_string() } else { format!("{} items", n) };
+
+    let parts: Vec<String> = [(Transfer::Copy, "Copying {} here…"), (Transfer::Move, "Moving {} here…"), (Transfer::Trash, "Moving {} to the trash…")]
+        .into_iter()
+        .filter_map(|(kind, template)| {
+            let n = count(kind);
+            (n > 0).then(|| template.replace("{}", &items(n)))
+        })
+        .collect();
+
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+/// Tracks the pointer over a file list row and shows its metadata tooltip when due.
+fn hover_row<'a>(state: &'a CsFM, f: &'a FileEntry, row: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+    let row = mouse_area(row)
+        .on_enter(Message::HoverEntry(Some(f.path.clone())))
+        .on_exit(Message::HoverEntry(None));
+
+    if state.tooltip_entry.as_ref() != Some(&f.path) {
+        return row.into();
+    }
+
+    let name = sanitize_name(&f.path.file_name().unwrap_or(f.path.as_os_str()).to_string_lossy()).0;
+    let mut details = column![text(name)].spacing(2);
+    if !f.is_dir
+        && let Some(size) = f.size
+    {
+        details = details.push(text(format!("Size: {}", format_size(size))));
+    }
+    if let Some(modified) = f.modified {
+        let modified: chrono::DateTime<chrono::Local> = modified.into();
+        details = details.push(text(format!("Modified: {}", modified.format("%Y-%m-%d %H:%M"))));
+    }
+    details = details.push(text(format!("Type: {}", if f.is_dir { "folder" } else { mime_type(&f.path) })));
+
+    // Below the row, so the row itself stays readable
+    tooltip(row, container(details).style(context_menu_container_style).padding(5), tooltip::Position::Bottom)
+        .gap(4)
+        .into()
+}
+
+/// A size with the exact byte count, e.g. "1.4 MiB (1468006 bytes)".
+fn exact_size(size: Option<u64>) -> String {
+    match size {
+        Some(size) => format!("{} ({} bytes)", format_size(size), size),
+        None => "unknown".to_string(),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn display_name(state: &CsFM, path: &Path) -> String {
+    // Search and smart folder results come from many directories, so the bare name is ambiguous
+    let shown = match state.config.full_path_style {
+        _ if !state.config.show_full_paths && state.walk_view.is_none() => path.file_name().unwrap_or(path.as_os_str()),
+        PathStyle::Relative => path.strip_prefix(&state.path).unwrap_or(path).as_os_str(),
+        PathStyle::Absolute => path.as_os_str(),
+    };
+
+    let (name, had_bidi) = sanitize_name(&shown.to_string_lossy());
+    if had_bidi {
+        format!("⚠ {}", name)
+    } else {
+        name
+    }
+}
+
+/// Makes a name safe to render on one line: control characters become visible symbols and
+/// bidi controls, which can make `exe.txt` read as `txt.exe`, are dropped. The second value
+/// tells whether any bidi controls were found.
+fn sanitize_name(name: &str) -> (String, bool) {
+    let mut out = String::with_capacity(name.len());
+    let mut had_bidi = false;
+
+    for c in name.chars() {
+        match c {
+            // C0 controls map onto the Control Pictures block (newline becomes ␊)
+            '\0'..='\x1f' => out.push(char::from_u32(0x2400 + c as u32).unwrap_or('\u{fffd}')),
+            '\x7f' => out.push('␡'),
+            '\u{80}'..='\u{9f}' => out.push_str(&format!("\\x{:02x}", c as u32)),
+            '\u{200e}' | '\u{200f}' | '\u{061c}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => had_bidi = true,
+            _ => out.push(c),
+        }
+    }
+
+    (out, had_bidi)
+}
+
+fn menu_item(label: &str, shortcut: String, message: Message) -> Element<'_, Message> {
+    iced::widget::button(row![text(label).width(Length::Fill), text(shortcut)].spacing(20))
+        .style(iced::widget::button::text)
+        .width(Length::Fill)
+        .on_press(Message::MenuSelect(Box::new(message)))
+        .into()
+}
+
+fn top_menu<'a>(state: &'a CsFM, menu: TopMenu, label: &'a str, items: Vec<Element<'a, Message>>) -> Element<'a, Message> {
+    let btn = iced::widget::button(text(label)).on_press(Message::ToggleMenu(menu));
+    let overlay = container(column(items).spacing(2))
+        .style(context_menu_container_style)
+        .padding(5)
+        .width(220);
+
+    DropDown::new(btn, overlay, state.open_menu == Some(menu))
+        .on_dismiss(Message::CloseMenu)
+        .into()
+}
+
+fn version_line() -> String {
+    match option_env!("CSFM_GIT_COMMIT") {
+        Some(commit) => format!("csfm {} ({})", env!("CARGO_PKG_VERSION"), commit),
+        None => format!("csfm {}", env!("CARGO_PKG_VERSION")),
+    }
+}
+
+fn diagnostics(state: &CsFM) -> Vec<(&'static str, String)> {
+    vec![
+        ("Version", version_line()),
+        ("iced", env!("CSFM_ICED_VERSION").to_string()),
+        ("Config file", config_path().display().to_string()),
+        ("State file", path_history_file().display().to_string()),
+        ("Theme", theme(state).to_string()),
+        ("Listing cache", state.listing_cache.stats()),
+    ]
+}
+
+fn diagnostics_report(state: &CsFM) -> String {
+    let os = fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|data| {
+            data.lines()
+                .find_map(|l| l.strip_prefix("PRETTY_NAME="))
+                .map(|name| name.trim_matches('"').to_string())
+        })
+        .unwrap_or_else(|| std::env::consts::OS.to_string());
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_else(|_| "unknown".to_string());
+
+    let mut lines: Vec<String> = diagnostics(state)
+        .into_iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect();
+    lines.push(format!("OS: {} ({})", os, std::env::consts::ARCH));
+    lines.push(format!("Desktop: {}", desktop));
+
+    lines.join("\n")
+}
+
+fn shortcuts_dialog(state: &CsFM) -> Element<'_, Message> {
+    let actions = bound_actions(&state.config);
+    let mut content = column![text("Keyboard shortcuts").size(24)].spacing(5);
+
+    for category in BindingCategory::ALL {
+        let in_category: Vec<(&Action, &Shortcut)> = actions
+            .iter()
+            .filter(|a| a.category == category)
+            .filter_map(|a| Some((a, a.shortcut.as_ref()?)))
+            .collect();
+        if in_category.is_empty() {
+            continue;
+        }
+
+        content = content.push(text(category.title()).size(18));
+        for (action, shortcut) in in_category {
+            content = content.push(row![text(shortcut.chord()).width(120), text(action.label)].spacing(10));
+        }
+    }
+
+    container(scrollable(content).height(Length::Shrink))
+        .style(context_menu_container_style)
+        .padding(20)
+        .max_height(500)
+        .into()
+}
+
+fn operation_log_dialog(log: &OperationLogView) -> Element<'_, Message> {
+    const SHOWN: usize = 300;
+
+    let filter = log.filter.to_lowercase();
+    let matching = log.operations.iter().filter_map(|op| {
+        let time = chrono::DateTime::from_timestamp(op.time, 0)?.with_timezone(&chrono::Local);
+        let time = time.format("%Y-%m-%d %H:%M").to_string();
+        let source = op.source.to_string_lossy();
+        let dest = op.dest.as_ref().map(|d| d.to_string_lossy());
+        let matches_path = filter.is_empty()
+            || source.to_lowercase().contains(&filter)
+            || dest.as_ref().is_some_and(|d| d.to_lowercase().contains(&filter));
+        (matches_path && time.starts_with(log.date.trim())).then_some((op, time, source, dest))
+    });
+
+    let mut rows = column![].spacing(5);
+    let mut count = 0;
+    for (op, time, source, dest) in matching.take(SHOWN) {
+        let description = match dest {
+            Some(dest) => format!("{} {} → {}", op.action, source, dest),
+            None => format!("{} {}", op.action, source),
+        };
+        rows = rows.push(
+            row![
+                text(time).size(12).width(110),
+                text(description).size(12).width(Length::Fill),
+                iced::widget::button(text("Open containing folder").size(12)).on_press(Message::OpenContainingFolder(op.result_path().to_path_buf())),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+        );
+        count += 1;
+    }
+    if count == 0 {
+        rows = rows.push(text(if log.operations.is_empty() { "Nothing has been logged yet" } else { "No operations match" }).size(12));
+    }
+
+    let filters = row![
+        text_input("Path contains", &log.filter).on_input(Message::OperationLogFilter).padding(5),
+        text_input("Date, e.g. 2024-05", &log.date).on_input(Message::OperationLogDate).padding(5).width(180),
+    ].spacing(5);
+
+    container(column![
+        text("Operation log").size(24),
+        text(oplog::log_path().display().to_string()).size(12),
+        filters,
+        scrollable(rows).height(400),
+        iced::widget::button(text("Close")).on_press(Message::CloseModal),
+    ].spacing(10))
+        .style(context_menu_container_style)
+        .padding(20)
+        .width(900)
+        .into()
+}
+
+fn about_dialog(state: &CsFM) -> Element<'_, Message> {
+    let mut info = column![text("CsFM").size(24)].spacing(5);
+    for (key, value) in diagnostics(state) {
+        info = info.push(row![text(key).width(100), text(value)].spacing(10));
+    }
+
+    let buttons = row![
+        iced::widget::button(text("Copy diagnostics")).on_press(Message::CopyToClipboard(diagnostics_report(state))),
+        iced::widget::button(text("Close")).on_press(Message::CloseModal),
+    ].spacing(5);
+
+    container(column![info, buttons].spacing(15))
+        .style(context_menu_container_style)
+        .padding(20)
+        .into()
+}
+
+thread_local! {
+    /// Whether statx reported a birth time on each filesystem (by st_dev) tried so far
+    static BIRTH_TIME_SUPPORT: std::cell::RefCell<std::collections::HashMap<u64, bool>> = Default::default();
+}
+
+/// The birth time from statx. Filesystems that don't record it (tmpfs, older ext versions,
+/// most network filesystems) give None rather than the ctime, and aren't asked again.
+fn birth_time(path: &Path, metadata: &fs::Metadata) -> Option<std::time::SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = metadata.dev();
+    if BIRTH_TIME_SUPPORT.with(|support| support.borrow().get(&dev) == Some(&false)) {
+        return None;
+    }
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stx: libc::statx = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is NUL-terminated and stx is a valid statx to fill in
+    let result = unsafe { libc::statx(libc::AT_FDCWD, c_path.as_ptr(), libc::AT_SYMLINK_NOFOLLOW, libc::STATX_BTIME, &mut stx) };
+    if result != 0 {
+        return None;
+    }
+
+    // Some filesystems set the mask bit but leave the time at zero
+    let supported = stx.stx_mask & libc::STATX_BTIME != 0 && (stx.stx_btime.tv_sec, stx.stx_btime.tv_nsec) != (0, 0);
+    BIRTH_TIME_SUPPORT.with(|support| support.borrow_mut().insert(dev, supported));
+    if !supported {
+        return None;
+    }
+
+    let since_epoch = std::time::Duration::new(u64::try_from(stx.stx_btime.tv_sec).ok()?, stx.stx_btime.tv_nsec);
+    Some(std::time::UNIX_EPOCH + since_epoch)
+}
+
+fn large_file_dialog(prompt: &LargeFilePrompt) -> Element<'_, Message> {
+    let name = prompt.path.file_name().unwrap_or_default().to_string_lossy();
+    let extension = extension_key(&prompt.path);
+    let remember_label = if extension.is_empty() {
+        "Do the same for files without an extension this session".to_string()
+    } else {
+        format!("Do the same for .{} files this session", extension)
+    };
+
+    let buttons = row![
+        iced::widget::button(text("Open anyway")).on_press(Message::OpenLarge(prompt.path.clone(), LargeFileAction::OpenAnyway)),
+        iced::widget::button(text("Preview in csfm")).on_press(Message::OpenLarge(prompt.path.clone(), LargeFileAction::Preview)),
+        iced::widget::button(text("Open in terminal pager")).on_press(Message::OpenLarge(prompt.path.clone(), LargeFileAction::Pager)),
+        iced::widget::button(text("Cancel")).on_press(Message::CloseModal),
+    ].spacing(5);
+
+    container(column![
+        text("Large file").size(24),
+        text(format!("'{}' is {}. Its default application may take a long time to load it.", sanitize_name(&name).0, format_size(prompt.size))),
+        checkbox(remember_label, prompt.remember).on_toggle(Message::LargeFileRemember),
+        buttons,
+    ].spacing(15))
+        .style(context_menu_container_style)
+        .padding(20)
+        .width(600)
+        .into()
+}
+
+fn preview_dialog(preview: &Preview) -> Element<'_, Message> {
+    let name = preview.path.file_name().unwrap_or_default().to_string_lossy();
+    let shown = if preview.hex { PREVIEW_HEX_BYTES } else { PREVIEW_TEXT_BYTES };
+
+    let mut info = column![text(sanitize_name(&name).0).size(24)].spacing(5);
+    if preview.truncated {
+        info = info.push(text(format!("Showing the first {}", format_size(shown as u64))).size(12));
+    }
+    let contents = scrollable(text(&preview.contents).font(iced::Font::MONOSPACE).size(12))
+        .height(if preview.quick_look { Length::Fill } else { Length::Fixed(400.0) })
+        .width(Length::Fill);
+
+    if preview.quick_look {
+        let hint = text("Space or Esc to close, Left and Right for the previous or next file").size(12);
+        // Most of the window, leaving a margin to click outside of
+        return container(
+            container(column![info, contents, hint].spacing(15))
+                .style(context_menu_container_style)
+                .padding(20)
+                .width(Length::Fill)
+                .height(Length::Fill),
+        )
+        .padding(40)
+        .into();
+    }
+
+    container(column![info, contents, iced::widget::button(text("Close")).on_press(Message::CloseModal)].spacing(15))
+        .style(context_menu_container_style)
+        .padding(20)
+        .width(800)
+        .into()
+}
+
+fn properties_dialog(properties: &Properties) -> Element<'_, Message> {
+    let entry = &properties.entry;
+    let raw_name = entry.path.file_name().unwrap_or(entry.path.as_os_str());
+    let (name, _) = sanitize_name(&raw_name.to_string_lossy());
+
+    let mut rows = vec![
+        ("Name", name.clone()),
+        ("Location", sanitize_name(&entry.path.parent().unwrap_or(Path::new("/")).to_string_lossy()).0),
+        ("Type", if entry.is_dir { "folder".to_string() } else { mime_type(&entry.path).to_string() }),
+    ];
+    // Only worth showing when the name had to be altered for display
+    if raw_name.to_str() != Some(name.as_str()) {
+        rows.insert(1, ("Raw name", escaped_name(raw_name)));
+    }
+    if !entry.is_dir {
+        rows.push(("Size", exact_size(entry.size)));
+        rows.push(("Opens with", properties.opens_with.clone().unwrap_or_else(|| "the default application".to_string())));
+    }
+    if let Some(modified) = entry.modified {
+        let modified: chrono::DateTime<chrono::Local> = modified.into();
+        rows.push(("Modified", modified.format("%Y-%m-%d %H:%M:%S").to_string()));
+    }
+    if let Some(created) = properties.created {
+        let created: chrono::DateTime<chrono::Local> = created.into();
+        rows.push(("Created", created.format("%Y-%m-%d %H:%M:%S").to_string()));
+    }
+    let mut attributes = vec![];
+    if entry.flags.immutable {
+        attributes.push("immutable");
+    }
+    if entry.flags.append_only {
+        attributes.push("append-only");
+    }
+    if !attributes.is_empty() {
+        rows.push(("Attributes", attributes.join(", ")));
+    }
+    if let Some(mode) = properties.mode {
+        let bits = [mode >> 6, mode >> 3, mode].map(|p| format_perm(p as u16 & 0o7)).concat();
+        rows.push(("Permissions", format!("{} ({:o})", bits, mode & 0o7777)));
+    }
+
+    let mut info = column![text("Properties").size(24)].spacing(5);
+    for (key, value) in rows {
+        info = info.push(row![text(key).width(100), text(value)].spacing(10));
+    }
+    if let Some(acl) = &properties.acl {
+        info = info.push(acl_section(acl, properties.acl_expanded));
+    }
+    if let Some(mode) = properties.mode {
+        let label = if entry.is_dir { "Use as default for new folders" } else { "Use as default for new files" };
+        info = info.push(iced::widget::button(text(label).size(12)).on_press(Message::UseModeAsDefault(entry.is_dir, mode)));
+    }
+
+    container(column![info, iced::widget::button(text("Close")).on_press(Message::CloseModal)].spacing(15))
+        .style(context_menu_container_style)
+        .padding(20)
+        .width(500)
+        .into()
+}
+
+fn acl_section(acl: &[(String, AclEntry)], expanded: bool) -> Element<'_, Message> {
+    let mask = acl.iter().find(|(_, e)| e.tag == AclTag::Mask).map(|(_, e)| e.perm);
+    // Named entries, and a mask narrowing the owning group, aren't visible in the mode bits
+    let differs = acl.iter().any(|(_, e)| match e.tag {
+        AclTag::User(_) | AclTag::Group(_) => true,
+        AclTag::GroupObj => mask.is_some_and(|m| e.perm & m != e.perm),
+        _ => false,
+    });
+
+    let header = iced::widget::button(text(format!("{} Access control", if expanded { "▾" } else { "▸" })))
+        .style(iced::widget::button::text)
+        .padding(0)
+        .on_press(Message::ToggleAclSection);
+    let mut section = column![header].spacing(2);
+    if differs {
+        section = section.push(text("Effective access differs from the permission bits").size(12));
+    }
+    if !expanded {
+        return section.into();
+    }
+
+    for (who, entry) in acl {
+        let masked = matches!(entry.tag, AclTag::User(_) | AclTag::GroupObj | AclTag::Group(_));
+        let mut perm = format_perm(entry.perm);
+        if let Some(mask) = mask.filter(|m| masked && entry.perm & m != entry.perm) {
+            perm = format!("{} (effective {})", perm, format_perm(entry.perm & mask));
+        }
+        section = section.push(row![text(who.as_str()).width(150), text(perm)].spacing(10));
+    }
+
+    section.into()
+}
+
+fn folder_chooser_dialog<'a>(state: &'a CsFM, chooser: &'a FolderChooser) -> Element<'a, Message> {
+    let target_button = |title: String, path: PathBuf| {
+        iced::widget::button(text(title))
+            .style(|_, _| dir_button(state))
+            .width(Length::Fill)
+            .on_press(Message::ChooserCD(path))
+    };
+
+    // Quick targets: the last destination and the bookmarks
+    let mut targets = column![].spacing(5).width(150);
+    if let Some(last) = &state.last_transfer_dir {
+        targets = targets.push(text("Last used").size(12));
+        targets = targets.push(target_button(last.file_name().unwrap_or(last.as_os_str()).to_string_lossy().to_string(), last.clone()));
+    }
+    targets = targets.push(text("Places").size(12));
+    for location in state.config.sidebar.bookmarks.iter() {
+        targets = targets.push(target_button(location.title.clone(), PathBuf::from(&location.path)));
+    }
+
+    let mut entries = column![].spacing(5);
+    if let Some(parent) = chooser.dir.parent() {
+        entries = entries.push(target_button("..".to_string(), parent.to_path_buf()));
+    }
+    for dir in chooser.subdirs.iter() {
+        let name = sanitize_name(&dir.file_name().unwrap_or(dir.as_os_str()).to_string_lossy()).0;
+        entries = entries.push(target_button(name, dir.clone()));
+    }
+
+    let action = match chooser.transfer {
+        Transfer::Copy => "Copy here",
+        Transfer::Move | Transfer::Trash => "Move here",
+    };
+    let buttons = row![
+        iced::widget::button(text(action)).on_press(Message::ChooserConfirm),
+        iced::widget::button(text("Cancel")).on_press(Message::CloseModal),
+    ].spacing(5);
+
+    let browser = column![
+        text(sanitize_name(&chooser.dir.to_string_lossy()).0),
+        scrollable(entries).height(300),
+        buttons,
+    ].spacing(10).width(350);
+
+    container(row![scrollable(targets).height(360), browser].spacing(15))
+        .style(context_menu_container_style)
+        .padding(20)
+        .into()
+}
+
+/// Shows `dialog` centered over `base`, blocking interaction with everything behind it.
+fn modal<'a>(base: Element<'a, Message>, dialog: Element<'a, Message>) -> Element<'a, Message> {
+    stack![
+        base,
+        opaque(
+            mouse_area(center(opaque(dialog)).style(|theme: &Theme| container::Style {
+                background: Some(Background::Color(iced::Color { a: 0.6, ..theme.palette().background })),
+                ..Default::default()
+            }))
+            .on_press(Message::CloseModal)
+        ),
+    ]
+    .into()
+}
+
+/// Home plus the XDG user directories (Documents, Downloads, ...) that exist on this system.
+fn xdg_user_dirs() -> Vec<(String, PathBuf)> {
+    if paths::home_missing() {
+        return vec![];
+    }
+    let home = paths::home();
+
+    let mut dirs = vec![("Home".to_string(), home.clone())];
+    let user_dirs = fs::read_to_string(paths::config_home().join("user-dirs.dirs")).unwrap_or_default();
+    let configured: Vec<PathBuf> = user_dirs
+        .lines()
+        .filter(|l| l.starts_with("XDG_") && !l.starts_with("XDG_DESKTOP_DIR"))
+        .filter_map(|l| l.split_once('='))
+        .map(|(_, v)| v.trim_matches('"').replace("$HOME", &home.to_string_lossy()))
+        .map(PathBuf::from)
+        .collect();
+
+    let candidates = if configured.is_empty() {
+        ["Documents", "Downloads", "Music", "Pictures", "Videos"].iter().map(|d| home.join(d)).collect()
+    } else {
+        configured
+    };
+
+    for dir in candidates {
+        if dir.is_dir() && dir != home {
+            let title = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+            dirs.push((title, dir));
+        }
+    }
+
+    dirs
+}
+
+fn welcome_view(state: &CsFM) -> Element<'_, Message> {
+    let mut bookmarks = column![].spacing(5);
+    for (title, path) in xdg_user_dirs() {
+        let added = state.config.sidebar.bookmarks.iter().any(|l| l.path == path);
+        let label = if added { format!("✓ {}", title) } else { format!("Add {}", title) };
+        bookmarks = bookmarks.push(
+            iced::widget::button(text(label))
+                .style(|_, _| dir_button(state))
+                .on_press_maybe((!added).then_some(Message::WelcomeAddBookmark(title, path)))
+        );
+    }
+
+    let content = column![
+        text("Welcome to CsFM").size(28),
+        text("A few choices to get started. Everything can be changed later in the config file."),
+        row![text("Theme"), pick_list(Theme::ALL, Some(theme(state)), Message::WelcomeTheme)].spacing(10).align_y(Alignment::Center),
+        checkbox("Show hidden files", state.config.show_hidden_files).on_toggle(Message::WelcomeHiddenFiles),
+        text("Add folders to the sidebar"),
+        bookmarks,
+        row![
+            iced::widget::button(text("Finish")).on_press(Message::WelcomeFinish),
+            iced::widget::button(text("Use defaults")).on_press(Message::WelcomeUseDefaults),
+        ].spacing(5),
+    ]
+    .spacing(15)
+    .padding(20);
+
+    container(scrollable(content))
+        .style(container_style)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .padding(5)
+        .into()
+}
+
+/// A transfer waiting for space in its destination, with the way out of it.
+fn stalled_transfer_banner(running: &RunningTransfer) -> Element<'_, Message> {
+    let verb = if running.transfer == Transfer::Move { "Moving" } else { "Copying" };
+    let left = if running.stalled.len() == 1 { "1 item".to_string() } else { format!("{} items", running.stalled.len()) };
+
+    container(
+        row![
+            text(format!(
+                "{} into {} is paused, the destination is full. Free some space and resume, or cancel the {} left.",
+                verb,
+                running.dest.display(),
+                left
+            ))
+            .width(Length::Fill),
+            iced::widget::button(text("Resume")).on_press(Message::ResumeTransfer(running.id)),
+            iced::widget::button(text("Cancel")).on_press(Message::CancelTransfer(running.id)),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center)
+    )
+    .style(|theme: &Theme| container::Style {
+        border: Border { color: theme.palette().danger, width: 2.0, radius: Radius::new(10) },
+        ..Default::default()
+    })
+    .padding(10)
+    .into()
+}
+
+fn config_issues_banner(state: &CsFM) -> Element<'_, Message> {
+    const SHOWN: usize = 3;
+
+    let mut lines = column![
+        text(format!("Notes on {}:", config_path().display())),
+    ].spacing(2);
+    for issue in state.config_issues.iter().take(SHOWN) {
+        lines = lines.push(text(format!("• {}", issue)));
+    }
+    if state.config_issues.len() > SHOWN {
+        lines = lines.push(text(format!("…and {} more", state.config_issues.len() - SHOWN)));
+    }
+
+    container(
+        row![
+            lines.width(Length::Fill),
+            iced::widget::button(text("Dismiss")).on_press(Message::DismissConfigIssues),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center)
+    )
+    .style(|theme: &Theme| container::Style {
+        border: Border { color: theme.palette().danger, width: 2.0, radius: Radius::new(10) },
+        ..Default::default()
+    })
+    .padding(10)
+    .into()
+}
+
+fn up_button(state: &CsFM) -> Element<'_, Message> {
+    let parent = state.path.parent();
+    let label = match parent {
+        Some(parent) => format!("Go to {}", parent.display()),
+        None => "Already at the top level".to_string(),
+    };
+
+    tooltip(
+        iced::widget::button("Up").on_press_maybe(parent.map(|_| Message::Up)),
+        container(text(label)).style(context_menu_container_style).padding(5),
+        tooltip::Position::Bottom,
+    )
+    .into()
+}
+
+fn entry_row<'a>(state: &'a CsFM, f: &'a FileEntry) -> Element<'a, Message> {
+    if let Some((path, name)) = &state.renaming
+        && *path == f.path
+    {
+        return text_input("New name", name)
+            .id(rename_input_id())
+            .on_input(Message::RenameInput)
+            .on_submit(Message::Rename(path.clone(), name.clone()))
+            .padding(5)
+            .into();
+    }
+
+    let name = display_name(state, &f.path);
+    let name = if f.flags.locked() { format!("🔒 {}", name) } else { name };
+    let name = if is_recent(state, f) { format!("● {}", name) } else { name };
+    
+    if f.is_dir {
+        // Directory
+        let (name, on_press) = if f.leaves_tree {
+            (format!("{} ↗", name), Message::FollowExternalLink(f.path.clone()))
+        } else {
+            (name, Message::CD(f.path.clone()))
+        };
+        let btn = hover_row(state, f, iced::widget::button(entry_label(f, name))
+            .width(Length::Fill)
+            .style(|_, _| selected_style(state, f, revealed_style(state, f, dir_button(state))))
+            .on_press(Message::SelectEntry(f.path.clone(), Box::new(on_press))));
+        context_menu::ContextMenu::new(btn, || entry_context_menu(state, f)).into()
+    } else {
+        // File
+        let btn = hover_row(state, f, iced::widget::button(entry_label(f, name))
+            .width(Length::Fill)
+            .style(|_, _| selected_style(state, f, revealed_style(state, f, file_button(state))))
+            .on_press(Message::SelectEntry(f.path.clone(), Box::new(Message::Open(f.path.clone())))));
+        context_menu::ContextMenu::new(btn, || entry_context_menu(state, f)).into()
+    }
+}
+
+/// The name of a list entry with its size and modification time aligned to the right.
+fn entry_label(f: &FileEntry, name: String) -> Element<'_, Message> {
+    // Placeholders for what couldn't be read
+    let size = match f.size {
+        _ if f.is_dir => "—".to_string(),
+        Some(size) => format_size(size),
+        None => "?".to_string(),
+    };
+    let modified = f.modified.map_or_else(|| "?".to_string(), |modified| {
+        let modified: chrono::DateTime<chrono::Local> = modified.into();
+        modified.format("%Y-%m-%d %H:%M").to_string()
+    });
+
+    row![
+        text(name).width(Length::Fill),
+        text(size).width(90).align_x(iced::alignment::Horizontal::Right),
+        text(modified).width(130).align_x(iced::alignment::Horizontal::Right),
+    ]
+    .spacing(10)
+    .into()
+}
+
+/// Headers above the list, laid out like `entry_label`. Clicking one sorts by it.
+fn column_headers(state: &CsFM) -> Element<'_, Message> {
+    let header = |label: &'static str, mode: SortMode| {
+        let arrow = match state.config.sort_descending {
+            _ if state.config.sort_mode != mode => "",
+            true => " ↓",
+            false => " ↑",
+        };
+        iced::widget::button(text(format!("{}{}", label, arrow)).size(12))
+            .style(iced::widget::button::text)
+            .padding(0)
+            .on_press(Message::SortByColumn(mode))
+    };
+
+    row![
+        container(header("Name", SortMode::Name)).width(Length::Fill),
+        container(header("Size", SortMode::Size)).width(90).align_x(iced::alignment::Horizontal::Right),
+        container(header("Modified", SortMode::Modified)).width(130).align_x(iced::alignment::Horizontal::Right),
+    ]
+    .spacing(10)
+    // Lines the headers up with the text inside the entry buttons
+    .padding([0, 12])
+    .into()
+}
+
+/// Rows for `files` and, below each expanded directory, its children one level deeper.
+fn tree_rows<'a>(state: &'a CsFM, files: &'a [FileEntry], depth: usize, rows: &mut Vec<Element<'a, Message>>) {
+    let guides = || text("│  ".repeat(depth)).font(iced::Font::MONOSPACE);
+
+    for f in files {
+        let node = state.tree.get(&f.path);
+        let disclosure: Element<Message> = if f.is_dir && !f.leaves_tree && depth < TREE_MAX_DEPTH {
+            iced::widget::button(text(if node.is_some() { "▾" } else { "▸" }).font(iced::Font::MONOSPACE))
+                .style(iced::widget::button::text)
+                .padding(0)
+                .on_press(Message::ToggleTreeNode(f.path.clone()))
+                .into()
+        } else {
+            text(" ").font(iced::Font::MONOSPACE).into()
+        };
+        rows.push(row![guides(), disclosure, entry_row(state, f)].spacing(5).align_y(Alignment::Center).into());
+
+        let Some(node) = node else {
+            continue;
+        };
+        let child_guides = || text("│  ".repeat(depth + 1)).font(iced::Font::MONOSPACE);
+        match &node.children {
+            None => rows.push(row![child_guides(), text("Loading…").size(12)].into()),
+            Some(Err(e)) => rows.push(row![child_guides(), text(format!("Couldn't list: {}", e)).size(12)].into()),
+            Some(Ok(listing)) if listing.files.len() > TREE_EXPAND_LIMIT && !node.load_anyway => rows.push(
+                row![
+                    child_guides(),
+                    text(format!("{} items —", listing.files.len())).size(12),
+                    iced::widget::button(text("load anyway").size(12))
+                        .style(iced::widget::button::text)
+                        .padding(0)
+                        .on_press(Message::TreeLoadAnyway(f.path.clone())),
+                ]
+                .spacing(5)
+                .into(),
+            ),
+            Some(Ok(listing)) => tree_rows(state, &listing.files, depth + 1, rows),
+        }
+    }
+}
+
+fn view(state: &CsFM) -> Element<'_, Message> {
+    // ----- FILE LIST -----
+    let mut files: Vec<Element<Message>> = if state.loading && state.listed_path.as_ref() != Some(&state.path) {
+        // The old directory's entries would be misleading, a refresh keeps them
+        vec![text("Loading…").into()]
+    } else if state.tree_view && state.walk_view.is_none() && state.filter.is_empty() {
+        let mut rows = vec![];
+        tree_rows(state, &state.current_files, 0, &mut rows);
+        rows
+    } else {
+        let shown = shown_files(state);
+        if shown.is_empty() && !state.current_files.is_empty() {
+            vec![text(format!("No matches for \"{}\"", state.filter)).into()]
+        } else {
+            shown.into_iter().map(|f| entry_row(state, f)).collect()
+        }
+    };
+    files.insert(0, column_headers(state));
+
+    if let Some(WalkView::Search(query)) = &state.walk_view {
+        files.insert(0, text(format!("Search results for \"{}\" — Escape goes back to the folder", query)).into());
+    }
+
+    if let Some(notice) = state.walk_view_notice.as_ref().filter(|_| state.walk_view.is_some()) {
+        files.insert(0, text(format!("Incomplete results: {}", notice)).into());
+    }
+
+    if let Some(banner) = transfers_banner(state) {
+        files.insert(0, text(banner).into());
+    }
+
+    if let Some((_, notice)) = state.folder_notice.as_ref().filter(|(path, _)| *path == state.path && state.walk_view.is_none()) {
+        files.insert(0, text(notice).into());
+    }
+
+    if state.hidden_count > 0 {
+        let label = if state.hidden_count == 1 { "1 hidden item —".to_string() } else { format!("{} hidden items —", state.hidden_count) };
+        files.push(
+            row![
+                text(label).size(12),
+                iced::widget::button(text("show").size(12))
+                    .style(iced::widget::button::text)
+                    .padding(0)
+                    .on_press(Message::RevealHidden),
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center)
+            .into(),
+        );
+    }
+
+    // Right clicking below the rows opens the menu for the directory itself
+    let background = container(text("")).width(Length::Fill).height(60);
+    files.push(context_menu::ContextMenu::new(background, || background_context_menu(state)).into());
+
+    let file_list = container(
+        scrollable(
+            column(files)
+                .spacing(5)
+                .padding(5)
+        )
+        .id(file_list_id())
+        .on_scroll(|viewport| Message::FileListScrolled(ListView::of(viewport)))
+        .width(Length::Fill)
+        .height(Length::Fill)
+    )
+    .style(container_style)
+    .padding(5);
+
+    // ----- SIDEBAR -----
+    let mut main_view = row![].padding(5).spacing(5);
+
+    if state.sidebar_open {
+        let sidebar =
+            column![]
+                .extend(locations(state))  // <── FIXED HERE
+                .padding(5)
+                .spacing(5);
+
+        main_view = main_view.push(
+            container(sidebar)
+                .padding(5)
+                .style(container_style)
+                .width(150)
+                .height(Length::Fill),
+        );
+    }
+
+    // Push FILE LIST into main_view
+    if state.welcome {
+        main_view = main_view.push(welcome_view(state));
+    } else {
+        main_view = main_view.push(file_list);
+    }
+
+
+    // ----- TOP BAR -----
+    let bound = bound_actions(&state.config);
+    let chord = |label: &str| chord_for(&bound, label);
+    let top_bar = container(
+        row![
+            top_menu(state, TopMenu::File, "File", vec![
+                menu_item("Reload config", chord("Reload config"), Message::ReloadConfig),
+                menu_item("Export bookmarks…", String::new(), Message::ExportBookmarks),
+                menu_item("Import bookmarks…", String::new(), Message::ImportBookmarks(false)),
+                menu_item("Import and replace bookmarks…", String::new(), Message::ImportBookmarks(true)),
+                menu_item("Operation log…", String::new(), Message::ShowOperationLog),
+                menu_item("Open trash", String::new(), Message::CD(trash::files_dir())),
+                menu_item("Quit", chord("Quit"), Message::QuitApp(None)),
+            ]),
+
+            top_menu(state, TopMenu::View, "View", vec![
+                menu_item(
+                    if state.highlight_recent { "✓ Highlight recent changes" } else { "Highlight recent changes" },
+                    String::new(),
+                    Message::ToggleHighlightRecent,
+                ),
+                menu_item(
+                    if state.config.mix_folders { "Folders first" } else { "✓ Folders first" },
+                    String::new(),
+                    Message::ToggleMixFolders,
+                ),
+                menu_item("Refresh", chord("Refresh"), Message::Refresh(false)),
+                menu_item("Refresh and settle recent changes", chord("Refresh and settle recent changes"), Message::Refresh(true)),
+            ]),
+
+            top_menu(state, TopMenu::Help, "Help", vec![
+                menu_item("Keyboard shortcuts", chord("Keyboard shortcuts"), Message::ShowModal(Modal::Shortcuts)),
+                menu_item("About", String::new(), Message::ShowModal(Modal::About)),
+            ]),
+
+            iced::widget::button(if state.sidebar_open { "<" } else { ">" })
+                .on_press(Message::ToggleSidebar),
+
+            iced::widget::button("←").on_press_maybe((state.nav_index > 0).then_some(Message::Back)),
+
+            iced::widget::button("→").on_press_maybe((state.nav_index + 1 < state.nav_history.len()).then_some(Message::Forward)),
+
+            up_button(state),
+
+            iced::widget::button("Home").on_press(Message::CD(paths::home())),
+
+            new_folder_button(state),
+
+            paste_button(state),
+
+            iced::widget::button(if state.config.show_full_paths { "Names" } else { "Full paths" })
+                .on_press(Message::ToggleFullPaths),
+
+            iced::widget::button(if state.tree_view { "List" } else { "Tree" })
+                .on_press(Message::ToggleTreeView),
+
+            pick_list(SortMode::ALL, Some(state.config.sort_mode), Message::SetSort),
+
+            iced::widget::button(if state.config.sort_descending { "↓" } else { "↑" })
+                .on_press(Message::ToggleSortDirection),
+
+            text_input(
+                "Path",
+                state.path.to_string_lossy().as_ref()
+            )
+            .id(path_input_id())
+            .on_input(Message::PathChanged)
+            .on_submit(Message::SubmitPath)
+            .padding(5),
+
+            path_history_dropdown(state),
+        ]
+        .padding(5)
+        .spacing(5)
+    )
+    .style(container_style)
+    .padding(5);
+
+
+    // ----- FINAL LAYOUT -----
+    let filter = text_input("Filter (/)", &state.filter)
+        .id(filter_input_id())
+        .on_input(Message::FilterChanged)
+        .padding(5)
+        .width(200);
+    let search = text_input("Search below here", &state.search_input)
+        .on_input(Message::SearchInput)
+        .on_submit(Message::Search(state.search_input.clone()))
+        .padding(5)
+        .width(200);
+    let mut layout = column![top_bar, row![breadcrumb(state), filter, search].spacing(5).align_y(Alignment::Center)].padding(5);
+    if !state.config_issues.is_empty() {
+        layout = layout.push(config_issues_banner(state));
+    }
+    for running in state.transfers.iter().filter(|t| !t.stalled.is_empty()) {
+        layout = layout.push(stalled_transfer_banner(running));
+    }
+    let mut layout = layout.push(main_view);
+    if let Some((note, _)) = &state.toast {
+        layout = layout.push(container(text(note)).style(container_style).padding(5));
+    }
+    let layout = layout.into();
+
+    match state.modal {
+        Some(Modal::About) => modal(layout, about_dialog(state)),
+        Some(Modal::Shortcuts) => modal(layout, shortcuts_dialog(state)),
+        Some(Modal::Properties) => match &state.properties {
+            Some(properties) => modal(layout, properties_dialog(properties)),
+            None => layout,
+        },
+        Some(Modal::ChooseFolder) => match &state.folder_chooser {
+            Some(chooser) => modal(layout, folder_chooser_dialog(state, chooser)),
+            None => layout,
+        },
+        Some(Modal::LargeFile) => match &state.large_file {
+            Some(prompt) => modal(layout, large_file_dialog(prompt)),
+            None => layout,
+        },
+        Some(Modal::Preview) => match &state.preview {
+            Some(preview) => modal(layout, preview_dialog(preview)),
+            None => layout,
+        },
+        Some(Modal::OperationLog) => match &state.operation_log {
+            Some(log) => modal(layout, operation_log_dialog(log)),
+            None => layout,
+        },
+        None => layout,
+    }
+}
+
+
+/// The closest ancestor of `path` that is still a directory. When nothing but the root is
+/// left, e.g. after a network mount dropped, home is the more useful place to land.
+fn nearest_existing_ancestor(path: &Path) -> PathBuf {
+    path.ancestors()
+        .skip(1)
+        .find(|p| p.parent().is_some() && p.is_dir())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| {
+            let home = paths::home();
+            if home.is_dir() { home } else { PathBuf::from("/") }
+        })
+}
+
+/// Resolves a partially typed path to the single directory whose name starts with the typed leaf.
+fn unique_dir_completion(path: &Path) -> Option<PathBuf> {
+    let prefix = normalized_name(path.file_name()?);
+    let parent = path.parent()?;
+
+    let mut matches = fs::read_dir(parent)
+        .ok()?
+        .flatten()
+        .filter(|e| normalized_name(&e.file_name()).starts_with(&prefix))
+        .map(|e| e.path())
+        .filter(|p| p.is_dir());
+
+    let first = matches.next()?;
+    match matches.next() {
+        Some(_) => None,
+        None => Some(first),
+    }
+}
+
+/// Saves a changed sort setting and re-sorts what is shown.
+fn apply_sort(state: &mut CsFM) -> Task<Message> {
+    if let Err(e) = save_config(&state.config) {
+        error_zenity(format!("Failed to save config: {}", e));
+    }
+
+    // Smart folder results stay in path order
+    let order = state.config.sort_order();
+    if state.walk_view.is_none() {
+        sort_files(&mut state.current_files, order);
+    }
+    for node in state.tree.values_mut() {
+        if let Some(Ok(listing)) = node.children.as_mut() {
+            listing.sort(order);
+        }
+    }
+
+    Task::none()
+}
+
+
+
+fn push_path_history(state: &mut CsFM) {
+    let path = state.path.clone();
+    state.path_history.retain(|p| *p != path);
+    state.path_history.insert(0, path);
+    state.path_history.truncate(PATH_HISTORY_LEN);
+
+    if state.config.persist_path_history {
+        save_path_history(&state.path_history);
+    }
+}
+
+fn path_history_file() -> PathBuf {
+    paths::state_home().join("csdesktop/csfm_path_history")
+}
+
+// The history file holds raw path bytes, one per line, so non-UTF-8 names survive a restart
+fn load_path_history() -> Vec<PathBuf> {
+    let data = fs::read(path_history_file()).unwrap_or_default();
+
+    data.split(|&b| b == b'\n')
+        .filter(|l| !l.is_empty())
+        .map(|l| PathBuf::from(OsStr::from_bytes(l)))
+        .take(PATH_HISTORY_LEN)
+        .collect()
+}
+
+fn save_path_history(history: &[PathBuf]) {
+    let path = path_history_file();
+    let data: Vec<&[u8]> = history.iter().map(|p| p.as_os_str().as_bytes()).collect();
+
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Err(e) = write_atomic(&path, &data.join(&b'\n')) {
+        eprintln!("Failed to save path history: {}", e);
+    }
+}
+
+/// Marks the welcome panel as seen, whether it was finished or skipped. The path history
+/// can't tell, it is empty or disabled for some users.
+fn welcome_done_file() -> PathBuf {
+    paths::state_home().join("csdesktop/csfm_welcomed")
+}
+
+fn needs_welcome(fresh: bool, config: &Path, welcome_done: &Path) -> bool {
+    fresh || (!config.exists() && !welcome_done.exists())
+}
+
+fn mark_welcome_done() {
+    let path = welcome_done_file();
+
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Err(e) = write_atomic(&path, b"") {
+        eprintln!("Failed to save the welcome state: {}", e);
+    }
+}
+
+fn gtk_bookmarks_path() -> PathBuf {
+    paths::config_home().join("gtk-3.0/bookmarks")
+}
+
+/// Parses a `file:///path Label` line. Other URI schemes are left to GTK, and so are
+/// non-UTF-8 paths, which the config file can't store.
+fn parse_gtk_bookmark(line: &str) -> Option<Location> {
+    let (uri, label) = match line.split_once(' ') {
+        Some((uri, label)) => (uri, Some(label.trim())),
+        None => (line.trim(), None),
+    };
+    let path = PathBuf::from(OsString::from_vec(percent_decode(uri.strip_prefix("file://")?.as_bytes())));
+    let title = match label {
+        Some(label) if !label.is_empty() => label.to_string(),
+        _ => path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string(),
+    };
+
+    Some(Location { title, path: path.to_str()?.to_string(), from_gtk: true, hidden: None })
+}
+
+fn gtk_bookmark_line(location: &Location) -> String {
+    format!("file://{} {}", percent_encode(Path::new(&location.path).as_os_str().as_bytes()), location.title)
+}
+
+/// Adds GTK bookmarks missing from the config. On a path conflict the csfm title wins.
+fn merge_gtk_bookmarks(config: &mut Config) {
+    let data = fs::read_to_string(gtk_bookmarks_path()).unwrap_or_default();
+    merge_gtk_bookmark_lines(&mut config.sidebar, &data);
+}
+
+/// Adds the bookmarks of a GTK bookmarks file, placed as `sidebar.order` last had them.
+/// Bookmarks new since go after the rest.
+fn merge_gtk_bookmark_lines(sidebar: &mut SidebarConfig, data: &str) {
+    for location in data.lines().filter_map(parse_gtk_bookmark) {
+        if !sidebar.bookmarks.iter().any(|l| l.path == location.path) {
+            sidebar.bookmarks.push(location);
+        }
+    }
+
+    let position = |l: &Location| sidebar.order.iter().position(|p| *p == l.path).unwrap_or(usize::MAX);
+    sidebar.bookmarks.sort_by_key(position);
+}
+
+/// Applies csfm's bookmark edits to the GTK file. The file is re-read right before writing so
+/// changes by other applications survive, and lines csfm doesn't understand are kept as they are.
+fn write_gtk_bookmarks(added: &[Location], removed: &[String]) -> io::Result<()> {
+    let path = gtk_bookmarks_path();
+    let data = match fs::read_to_string(&path) {
+        Ok(d) => d,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let mut lines: Vec<String> = data
+        .lines()
+        .filter(|line| !parse_gtk_bookmark(line).is_some_and(|l| removed.contains(&l.path)))
+        .map(String::from)
+        .collect();
+    for location in added {
+        if !lines.iter().any(|line| parse_gtk_bookmark(line).is_some_and(|l| l.path == location.path)) {
+            lines.push(gtk_bookmark_line(location));
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    write_atomic(&path, contents.as_bytes())
+}
+
+fn config_path() -> PathBuf {
+    paths::config_home().join("csdesktop/csfm.toml")
+}
+
+fn load_config() -> (Config, Vec<ConfigIssue>) {
+    if !config_path().exists() {
+        return (Config::default(), vec![]);
+    }
+
+    match read_config(true) {
+        Ok((config, issues)) => {
+            if let Some(syntax_error) = issues.iter().find(|i| i.kind == IssueKind::Syntax) {
+                error_zenity(format!("{} isn't valid TOML, using the default settings.\n\n{}", config_path().display(), syntax_error));
+            }
+            (config, issues)
+        }
+        Err(e) => {
+            error_zenity(format!("Failed to read config, using the default settings: {}", e));
+            (Config::default(), vec![])
+        }
+    }
+}
+
+/// Reads and parses the config. `migrate` rewrites an old config in the current format,
+/// which only the first load does, reloads leave the file alone.
+fn read_config(migrate: bool) -> Result<(Config, Vec<ConfigIssue>), String> {
+    let path = config_path();
+
+
+    let data = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if let Some(recovered) = recover_config(&path, &data) {
+        return Ok(recovered);
+    }
+    if migrate {
+        write_migrated_config(&path, &data);
+    }
+    let (config, issues) = parse_config(&data);
+    for issue in issues.iter() {
+        eprintln!("{}: {}", path.display(), issue);
+    }
+    
+    Ok((config, issues))
+}
+
+fn config_backup_path(path: &Path) -> PathBuf {
+    path.with_extension("toml.bak")
+}
+
+/// Where the original of a migrated config is kept, e.g. `csfm.toml.pre-v1`. Apart from
+/// `csfm.toml.bak`, which the next save overwrites.
+fn config_migration_backup_path(path: &Path) -> PathBuf {
+    path.with_extension(format!("toml.pre-v{}", CONFIG_VERSION))
+}
+
+/// Keeps the current config as `csfm.toml.bak` before it is replaced.
+fn backup_config(path: &Path) -> io::Result<()> {
+    match fs::read_to_string(path) {
+        // A broken file must not replace a good backup, it may be what the backup is for
+        Ok(data) if !data.trim().is_empty() && data.parse::<toml::Table>().is_ok() => {
+            write_atomic(&config_backup_path(path), data.as_bytes())
+        }
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Falls back to the backup when the config is empty or not valid TOML and the backup is,
+/// which is what an interrupted write or a bad hand edit leaves behind.
+fn recover_config(path: &Path, data: &str) -> Option<(Config, Vec<ConfigIssue>)> {
+    let broken = data.trim().is_empty() || data.parse::<toml::Table>().is_err();
+    if !broken {
+        return None;
+    }
+
+    let backup_path = config_backup_path(path);
+    let backup = fs::read_to_string(&backup_path).ok()?;
+    if backup.trim().is_empty() || backup.parse::<toml::Table>().is_err() {
+        return None;
+    }
+
+    let (config, mut issues) = parse_config(&backup);
+    issues.insert(0, ConfigIssue {
+        kind: IssueKind::Notice,
+        key: String::new(),
+        found: format!("{} is empty or unreadable, using {} instead", path.display(), backup_path.display()),
+        expected: String::new(),
+        location: None,
+    });
+    Some((config, issues))
+}
+
+/// Saves the migrated form of an outdated config, keeping the original as `csfm.toml.pre-vN`.
+fn write_migrated_config(path: &Path, data: &str) {
+    let Ok(mut table) = data.parse::<toml::Table>() else {
+        return;
+    };
+    let notices = migrate_config(&mut table);
+    // Files from a newer version only produce a warning and are never rewritten
+    let migrated = table.get("config_version").and_then(|v| v.as_integer()) == Some(CONFIG_VERSION as i64);
+    if notices.is_empty() || !migrated {
+        return;
+    }
+
+    let Ok(migrated) = toml::to_string_pretty(&table) else {
+        return;
+    };
+    let backup = config_migration_backup_path(path);
+    if let Err(e) = write_atomic(&backup, data.as_bytes()).and_then(|_| write_atomic(path, migrated.as_bytes())) {
+        eprintln!("Failed to write migrated config to {}: {}", path.display(), e);
+    }
+}
+
+fn line_column(data: &str, offset: usize) -> (usize, usize) {
+    let before = &data[..offset.min(data.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+/// Location of `key`, or of its `index`-th `[[key]]` table, in the raw config text.
+fn key_location(data: &str, key: &str, index: Option<usize>) -> Option<(usize, usize)> {
+    let header = format!("[[{}]]", key);
+    let mut offset = 0;
+    let mut tables_seen = 0;
+
+    for line in data.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed == header {
+            if index == Some(tables_seen) || index.is_none() {
+                return Some(line_column(data, offset + line.find('[').unwrap_or(0)));
+            }
+            tables_seen += 1;
+        } else if index.is_none()
+            && let Some(rest) = trimmed.strip_prefix(key)
+            && rest.trim_start().starts_with('=')
+        {
+            return Some(line_column(data, offset + line.find(key).unwrap_or(0)));
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+fn describe_value(value: &toml::Value) -> String {
+    let mut found = value.to_string();
+    if found.chars().count() > 40 {
+        found = found.chars().take(40).collect::<String>() + "…";
+    }
+    found
+}
+
+fn take_value<T: DeserializeOwned>(table: &toml::Table, data: &str, key: &str, expected: &str, issues: &mut Vec<ConfigIssue>) -> Option<T> {
+    let value = table.get(key)?;
+
+    match value.clone().try_into::<T>() {
+        Ok(v) => Some(v),
+        Err(_) => {
+            issues.push(ConfigIssue {
+                kind: IssueKind::Setting,
+                key: key.to_string(),
+                found: describe_value(value),
+                expected: expected.to_string(),
+                location: key_location(data, key, None),
+            });
+            None
+        }
+    }
+}
+
+/// Like `take_value` for a count that must not exceed `max`.
+fn take_bounded(table: &toml::Table, data: &str, key: &str, expected: &str, max: u64, issues: &mut Vec<ConfigIssue>) -> Option<u64> {
+    let value = take_value::<u64>(table, data, key, expected, issues)?;
+    if value > max {
+        issues.push(ConfigIssue {
+            kind: IssueKind::Setting,
+            key: key.to_string(),
+            found: value.to_string(),
+            expected: expected.to_string(),
+            location: key_location(data, key, None),
+        });
+        return None;
+    }
+
+    Some(value)
+}
+
+/// Like `take_value` for arrays, but drops only the malformed elements.
+fn take_list<T: DeserializeOwned>(table: &toml::Table, data: &str, key: &str, expected: &str, issues: &mut Vec<ConfigIssue>) -> Option<Vec<T>> {
+    take_list_at(table, data, key, key, expected, issues)
+}
+
+/// `take_list` for a table nested below the top level, `path` being the dotted key path of the list.
+fn take_list_at<T: DeserializeOwned>(table: &toml::Table, data: &str, key: &str, path: &str, expected: &str, issues: &mut Vec<ConfigIssue>) -> Option<Vec<T>> {
+    let value = table.get(key)?;
+    let Some(items) = value.as_array() else {
+        issues.push(ConfigIssue {
+            kind: IssueKind::Setting,
+            key: path.to_string(),
+            found: describe_value(value),
+            expected: format!("a list of {}", expected),
+            location: key_location(data, path, None),
+        });
+        return None;
+    };
+
+    let mut list = vec![];
+    for (i, item) in items.iter().enumerate() {
+        match item.clone().try_into::<T>() {
+            Ok(v) => list.push(v),
+            Err(_) => issues.push(ConfigIssue {
+                kind: IssueKind::Setting,
+                key: format!("{}[{}]", path, i),
+                found: describe_value(item),
+                expected: expected.to_string(),
+                location: key_location(data, path, Some(i)).or_else(|| key_location(data, key, None)),
+            }),
+        }
+    }
+
+    Some(list)
+}
+
+/// Reads a `new_*_mode` setting. setuid and setgid are accepted on new files but noted,
+/// since they are almost never what a freshly created file should carry.
+fn take_mode(table: &toml::Table, data: &str, key: &str, issues: &mut Vec<ConfigIssue>) -> Option<String> {
+    let v = take_value::<String>(table, data, key, "an octal mode such as \"755\"", issues)?;
+    let Some(mode) = parse_mode(&v) else {
+        issues.push(ConfigIssue {
+            kind: IssueKind::Setting,
+            key: key.to_string(),
+            found: format!("\"{}\"", v),
+            expected: "at most 4 octal digits".to_string(),
+            location: key_location(data, key, None),
+        });
+        return None;
+    };
+    if key == "new_file_mode" && mode & 0o6000 != 0 {
+        issues.push(ConfigIssue {
+            kind: IssueKind::Notice,
+            key: String::new(),
+            found: format!("new_file_mode \"{}\" sets setuid or setgid on every new file", v),
+            expected: String::new(),
+            location: key_location(data, key, None),
+        });
+    }
+
+    Some(v)
+}
+
+/// Reads `[open_overrides]`, dropping only the malformed entries.
+fn take_open_overrides(table: &toml::Table, data: &str, issues: &mut Vec<ConfigIssue>) -> Option<std::collections::BTreeMap<String, OpenOverride>> {
+    let value = table.get("open_overrides")?;
+    let Some(entries) = value.as_table() else {
+        issues.push(ConfigIssue {
+            kind: IssueKind::Setting,
+            key: "open_overrides".to_string(),
+            found: describe_value(value),
+            expected: "a table".to_string(),
+            location: key_location(data, "open_overrides", None),
+        });
+        return None;
+    };
+
+    let mut overrides = std::collections::BTreeMap::new();
+    for (key, value) in entries {
+        let parsed = value.clone().try_into::<OpenOverride>().ok().filter(|o| !o.command().trim().is_empty());
+        let valid_key = !key.is_empty() && !key.starts_with('.') && key.matches('/').count() <= 1;
+        match parsed {
+            Some(o) if valid_key => {
+                overrides.insert(key.to_lowercase(), o);
+            }
+            _ => issues.push(ConfigIssue {
+                kind: IssueKind::Setting,
+                key: format!("open_overrides.{}", key),
+                found: describe_value(value),
+                expected: if valid_key {
+                    "a command, or a table with command and terminal".to_string()
+                } else {
+                    "an extension without the dot, or a MIME type".to_string()
+                },
+                location: key_location(data, key, None),
+            }),
+        }
+    }
+
+    Some(overrides)
+}
+
+fn take_keybindings(table: &toml::Table, data: &str, issues: &mut Vec<ConfigIssue>) -> Option<std::collections::BTreeMap<String, String>> {
+    let value = table.get("keybindings")?;
+    let Some(entries) = value.as_table() else {
+        issues.push(ConfigIssue {
+            kind: IssueKind::Setting,
+            key: "keybindings".to_string(),
+            found: describe_value(value),
+            expected: "a table".to_string(),
+            location: key_location(data, "keybindings", None),
+        });
+        return None;
+    };
+
+    let actions = actions();
+    let mut bindings = std::collections::BTreeMap::new();
+    let mut taken: Vec<(keyboard::Key, keyboard::Modifiers, &str)> = vec![];
+    for (name, value) in entries {
+        let issue = |found: String, expected: String| ConfigIssue {
+            kind: IssueKind::Setting,
+            key: format!("keybindings.{}", name),
+            found,
+            expected,
+            location: key_location(data, name, None),
+        };
+        let Some(chord) = value.as_str() else {
+            issues.push(issue(describe_value(value), "a chord such as \"Ctrl+Shift+N\"".to_string()));
+            continue;
+        };
+        if !actions.iter().any(|a| names_action(name, a.label)) {
+            issues.push(issue(format!("\"{}\"", name), "an action listed under Help ▸ Keyboard shortcuts".to_string()));
+            continue;
+        }
+        if !chord.trim().is_empty() {
+            let Some((key, modifiers)) = Shortcut::parse_chord(chord) else {
+                issues.push(issue(format!("\"{}\"", chord), "a chord such as \"Ctrl+Shift+N\"".to_string()));
+                continue;
+            };
+            if let Some((_, _, other)) = taken.iter().find(|(k, m, _)| *k == key && *m == modifiers) {
+                issues.push(issue(format!("\"{}\"", chord), format!("a chord not already bound to {}", other)));
+                continue;
+            }
+            taken.push((key, modifiers, name));
+        }
+        bindings.insert(name.clone(), chord.to_string());
+    }
+
+    Some(bindings)
+}
+
+/// Moves top level `sidebar_loc` entries into `[sidebar] bookmarks`.
+fn migrate_v0_to_v1(table: &mut toml::Table) {
+    let Some(bookmarks) = table.remove("sidebar_loc") else {
+        return;
+    };
+
+    let sidebar = table
+        .entry("sidebar")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    if let Some(sidebar) = sidebar.as_table_mut() {
+        sidebar.entry("bookmarks").or_insert(bookmarks);
+    }
+}
+
+/// Rewrites an older config structure into the current one. Returns a notice for every
+/// step taken; no notices means the table was already current.
+fn migrate_config(table: &mut toml::Table) -> Vec<String> {
+    const MIGRATIONS: [fn(&mut toml::Table); CONFIG_VERSION as usize] = [migrate_v0_to_v1];
+
+    let version = table
+        .get("config_version")
+        .and_then(|v| v.as_integer())
+        .unwrap_or(0)
+        .max(0) as u32;
+
+    if version > CONFIG_VERSION {
+        return vec![format!("Config version {} is newer than this CsFM understands ({}), loading what it can", version, CONFIG_VERSION)];
+    }
+
+    let mut notices = vec![];
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(table);
+        notices.push(format!("Updated config from version {} to {}", from, from + 1));
+    }
+    if !notices.is_empty() {
+        table.insert("config_version".to_string(), toml::Value::Integer(CONFIG_VERSION as i64));
+    }
+
+    notices
+}
+
+/// Parses the config text, replacing every invalid setting with its default instead of
+/// rejecting the whole file. Returns the problems found along the way.
+fn parse_config(data: &str) -> (Config, Vec<ConfigIssue>) {
+    let mut config = Config::default();
+    let mut issues = vec![];
+
+    let mut table: toml::Table = match data.parse() {
+        Ok(t) => t,
+        Err(e) => {
+            let e: toml::de::Error = e;
+            issues.push(ConfigIssue {
+                kind: IssueKind::Syntax,
+                key: String::new(),
+                found: e.message().to_string(),
+                expected: String::new(),
+                location: e.span().map(|span| line_column(data, span.start)),
+            });
+            return (config, issues);
+        }
+    };
+
+    for notice in migrate_config(&mut table) {
+        issues.push(ConfigIssue { kind: IssueKind::Notice, key: String::new(), found: notice, expected: String::new(), location: None });
+    }
+    config.config_version = CONFIG_VERSION;
+
+    if let Some(v) = take_value::<String>(&table, data, "theme", "a theme name", &mut issues) {
+        let known = v.trim().is_empty() || theme_by_name(&v).is_some();
+        if known {
+            config.theme = v;
+        } else {
+            eprintln!("Warning: unknown theme \"{}\" in the config, using the default", v);
+            issues.push(ConfigIssue {
+                kind: IssueKind::Setting,
+                key: "theme".to_string(),
+                found: format!("\"{}\"", v),
+                expected: "a known theme name".to_string(),
+                location: key_location(data, "theme", None),
+            });
+        }
+    }
+    if let Some(v) = take_value(&table, data, "show_hidden_files", "true or false", &mut issues) { config.show_hidden_files = v; }
+    match table.get("sidebar") {
+        Some(toml::Value::Table(sidebar)) => {
+            if let Some(v) = take_list_at(sidebar, data, "bookmarks", "sidebar.bookmarks", "tables with title and path", &mut issues) { config.sidebar.bookmarks = v; }
+            if let Some(v) = take_list_at(sidebar, data, "order", "sidebar.order", "paths", &mut issues) { config.sidebar.order = v; }
+        }
+        Some(value) => issues.push(ConfigIssue {
+            kind: IssueKind::Setting,
+            key: "sidebar".to_string(),
+            found: describe_value(value),
+            expected: "a table".to_string(),
+            location: key_location(data, "sidebar", None),
+        }),
+        None => {}
+    }
+    if let Some(v) = take_list(&table, data, "network_location", "tables with title and uri", &mut issues) { config.network_location = v; }
+    if let Some(v) = take_value(&table, data, "persist_path_history", "true or false", &mut issues) { config.persist_path_history = v; }
+    if let Some(v) = take_value(&table, data, "show_full_paths", "true or false", &mut issues) { config.show_full_paths = v; }
+    if let Some(v) = take_value(&table, data, "full_path_style", "relative or absolute", &mut issues) { config.full_path_style = v; }
+    if let Some(v) = take_value(&table, data, "sort_mode", "name, size, modified or type", &mut issues) { config.sort_mode = v; }
+    if let Some(v) = take_value(&table, data, "sort_descending", "true or false", &mut issues) { config.sort_descending = v; }
+    if let Some(v) = take_value(&table, data, "mix_folders", "true or false", &mut issues) { config.mix_folders = v; }
+    if let Some(v) = take_list(&table, data, "smart_folder", "tables with title, root and rules", &mut issues) { config.smart_folder = v; }
+    if let Some(v) = take_value(&table, data, "confirm_symlink_leave", "true or false", &mut issues) { config.confirm_symlink_leave = v; }
+    if let Some(v) = take_value(&table, data, "editor", "a command string", &mut issues) { config.editor = Some(v); }
+    if let Some(v) = take_value(&table, data, "safe_mode", "true or false", &mut issues) { config.safe_mode = v; }
+    if let Some(v) = take_value(&table, data, "diff_tool", "a command string", &mut issues) { config.diff_tool = Some(v); }
+    if let Some(v) = take_value(&table, data, "sync_gtk_bookmarks", "true or false", &mut issues) { config.sync_gtk_bookmarks = v; }
+    if let Some(v) = take_value(&table, data, "full_path_title", "true or false", &mut issues) { config.full_path_title = v; }
+    if let Some(v) = take_value(&table, data, "disable_prefetch", "true or false", &mut issues) { config.disable_prefetch = v; }
+    if let Some(v) = take_value(&table, data, "walk_max_depth", "a positive number", &mut issues) { config.walk_max_depth = Some(v); }
+    if let Some(v) = take_value(&table, data, "walk_follow_symlinks", "true or false", &mut issues) { config.walk_follow_symlinks = v; }
+    if let Some(v) = take_value(&table, data, "disable_tooltips", "true or false", &mut issues) { config.disable_tooltips = v; }
+    config.new_dir_mode = take_mode(&table, data, "new_dir_mode", &mut issues);
+    config.new_file_mode = take_mode(&table, data, "new_file_mode", &mut issues);
+    if let Some(v) = take_value(&table, data, "inline_create", "true or false", &mut issues) { config.inline_create = v; }
+    if let Some(v) = take_bounded(&table, data, "large_file_threshold", "a size in MiB up to 1 PiB", LARGE_FILE_THRESHOLD_MAX_MIB, &mut issues) { config.large_file_threshold = Some(v); }
+    if let Some(v) = take_bounded(&table, data, "recent_changes_minutes", "a number of minutes up to a year", RECENT_CHANGES_MAX_MINUTES, &mut issues) { config.recent_changes_minutes = Some(v); }
+    if let Some(v) = take_value(&table, data, "terminal", "a command", &mut issues) { config.terminal = Some(v); }
+    if let Some(v) = take_open_overrides(&table, data, &mut issues) { config.open_overrides = v; }
+    if let Some(v) = take_keybindings(&table, data, &mut issues) { config.keybindings = v; }
+
+    const KNOWN_KEYS: &[&str] = &[
+        "config_version", "theme", "show_hidden_files", "sidebar", "network_location", "persist_path_history",
+        "show_full_paths", "full_path_style", "sort_mode", "sort_descending", "mix_folders", "smart_folder", "confirm_symlink_leave", "editor", "safe_mode", "diff_tool",
+        "sync_gtk_bookmarks", "full_path_title", "disable_prefetch",
+        "walk_max_depth", "walk_follow_symlinks", "disable_tooltips", "new_dir_mode", "new_file_mode", "inline_create",
+        "large_file_threshold", "recent_changes_minutes", "terminal", "open_overrides", "keybindings",
+    ];
+    for (key, value) in table.iter() {
+        if !KNOWN_KEYS.contains(&key.as_str()) {
+            issues.push(ConfigIssue {
+                kind: IssueKind::Setting,
+                key: key.clone(),
+                found: describe_value(value),
+                expected: "a known setting".to_string(),
+                location: key_location(data, key, None),
+            });
+        }
+    }
+
+    (config, issues)
+}
+
+/// The config as written to csfm.toml. GTK bookmarks stay in their own file, only their
+/// place among the others is kept.
+fn config_file_contents(config: &Config) -> Result<String, String> {
+    let mut config = config.clone();
+    config.config_version = CONFIG_VERSION;
+    config.sidebar.order = if config.sidebar.bookmarks.iter().any(|l| l.from_gtk) {
+        config.sidebar.bookmarks.iter().map(|l| l.path.clone()).collect()
+    } else {
+        vec![]
+    };
+    config.sidebar.bookmarks.retain(|l| !l.from_gtk);
+    toml::to_string_pretty(&config).map_err(|e| e.to_string())
+}
+
+fn save_config(config: &Config) -> Result<(), String> {
+    let path = config_path();
+    let data = config_file_contents(config)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    backup_config(&path).map_err(|e| e.to_string())?;
+    write_atomic(&path, data.as_bytes()).map_err(|e| e.to_string())
+}
+
+
+impl Default for CsFM {
+    fn default() -> Self {
+        let path = cli_paths()
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or(PathBuf::from("/")));
+        let fresh = std::env::args_os().skip(1).any(|a| a == "--fresh");
+        let welcome = needs_welcome(fresh, &config_path(), &welcome_done_file());
+        let (mut cfg, mut config_issues) = load_config();
+        if paths::home_missing() {
+            config_issues.insert(0, ConfigIssue {
+                kind: IssueKind::Notice,
+                key: String::new(),
+                found: format!("HOME isn't set, so settings are kept in {} until the next reboot", paths::home().display()),
+                expected: String::new(),
+                location: None,
+            });
+        }
+        if cfg.sync_gtk_bookmarks {
+            merge_gtk_bookmarks(&mut cfg);
+        }
+        let path_history = if cfg.persist_path_history { load_path_history() } else { vec![] };
+
+        CsFM { welcome, config_issues, path_history, ..CsFM::new(cfg, path) }
+    }
+}
+
+impl CsFM {
+    /// A window on `path` with nothing loaded yet.
+    fn new(config: Config, path: PathBuf) -> Self {
+        CsFM {
+            config,
+            // The first listing is loaded like any other, see main
+            listed_path: None,
+            nav_history: vec![path.clone()],
+            path,
+            current_files: vec![],
+            hidden_count: 0,
+            hidden_override: None,
+            sidebar_open: true,
+            path_history: vec![],
+            nav_index: 0,
+            history_open: false,
+            history_cursor: None,
+            walk_view: None,
+            search_input: String::new(),
+            search_generation: 0,
+            walk_view_notice: None,
+            open_menu: None,
+            modal: None,
+            welcome: false,
+            config_issues: vec![],
+            listing_cache: ListingCache::default(),
+            hovered_entry: None,
+            tooltip_entry: None,
+            prefetches_running: 0,
+            missing_bookmarks: vec![],
+            folder_chooser: None,
+            properties: None,
+            last_transfer_dir: None,
+            transfers: vec![],
+            next_transfer_id: 0,
+            large_file: None,
+            large_file_choices: Default::default(),
+            preview: None,
+            clipboard: None,
+            placeholder: None,
+            selected: HashSet::new(),
+            highlight_recent: false,
+            path_mount: None,
+            crumb_menu_open: false,
+            filter: String::new(),
+            recent_settled_at: None,
+            selection_anchor: None,
+            file_list_view: None,
+            last_click: None,
+            modifiers: keyboard::Modifiers::empty(),
+            operation_log: None,
+            loading: true,
+            folder_notice: None,
+            renaming: None,
+            revealed_entry: None,
+            tree_view: false,
+            tree: Default::default(),
+            toast: None,
+            connecting: None
+        }
+    }
+}
+
+fn handle_event(event: iced::Event, status: event::Status, id: Id) -> Option<Message> {
+    match event {
+        iced::Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) => {
+            Some(Message::KeyPressed(key, modifiers, status, id))
+        }
+        iced::Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => Some(Message::ModifiersChanged(modifiers)),
+        iced::Event::Mouse(iced::mouse::Event::ButtonPressed(iced::mouse::Button::Back)) => Some(Message::Back),
+        iced::Event::Mouse(iced::mouse::Event::ButtonPressed(iced::mouse::Button::Forward)) => Some(Message::Forward),
+        // Drives get plugged in and folders removed while csfm is in the background
+        iced::Event::Window(iced::window::Event::Focused) => Some(Message::WindowFocused),
+        _ => None,
+    }
+}
+
+fn subscription(state: &CsFM) -> Subscription<Message> {
+    let recent = if state.highlight_recent {
+        every(RECENT_CHANGES_REFRESH).map(|_| Message::RecentChangesTick)
+    } else {
+        Subscription::none()
+    };
+
+    let toast = if state.toast.is_some() {
+        every(std::time::Duration::from_secs(1)).map(|_| Message::ToastTick)
+    } else {
+        Subscription::none()
+    };
+
+    Subscription::batch([event::listen_with(handle_event), recent, toast])
+}
+
+/// Folders to open, from `csfm [DIR|FILE|URI]...` relative to where csfm was started.
+/// A file opens the folder it is in.
+fn cli_paths() -> Vec<PathBuf> {
+    let cwd = std::env::current_dir().unwrap_or(PathBuf::from("/"));
+
+    std::env::args_os()
+        .skip(1)
+        .filter(|a| !a.as_bytes().starts_with(b"--"))
+        .filter_map(|a| desktop::arg_to_path(&a))
+        .map(|p| {
+            let p = cwd.join(p);
+            match p.parent() {
+                Some(parent) if p.exists() && !p.is_dir() => parent.to_path_buf(),
+                _ => p,
+            }
+        })
+        .collect()
+}
+
+fn confirm_on_terminal(question: &str) -> bool {
+    use std::io::Write;
+
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Handles the desktop integration flags. Returns false when one was given and csfm
+/// shouldn't start.
+fn run_desktop_flags(args: &[OsString]) -> bool {
+    let result = if args.iter().any(|a| a == "--install-desktop-file") {
+        let set_default = args.iter().any(|a| a == "--set-default")
+            && confirm_on_terminal("Make csfm the default application for opening folders?");
+        desktop::install(set_default)
+    } else if args.iter().any(|a| a == "--uninstall-desktop-file") {
+        desktop::uninstall()
+    } else {
+        return true;
+    };
+
+    match result {
+        Ok(done) => done.iter().for_each(|line| println!("{}", line)),
+        Err(e) => eprintln!("Error: {}", e),
+    }
+    false
 }
 
 pub fn main() -> iced::Result {
-    iced::application("CsFM", update, view).theme(theme).run()
-}
\ No newline at end of file
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    if args.iter().any(|a| a == "--version") {
+        println!("{}", version_line());
+        return Ok(());
+    }
+    if !run_desktop_flags(&args) {
+        return Ok(());
+    }
+
+    for arg in args.iter().filter(|a| !a.as_bytes().starts_with(b"--")) {
+        if desktop::arg_to_path(arg).is_none() {
+            eprintln!("Ignoring {}: only local paths and valid file:// URIs can be opened", arg.to_string_lossy());
+        }
+    }
+
+    iced::application(title, update, view)
+        .subscription(subscription)
+        .theme(theme)
+        .run_with(|| {
+            let state = CsFM::default();
+            // Launchers pass every selected folder at once; each gets its own window
+            for path in cli_paths().iter().skip(1) {
+                if state.config.safe_mode {
+                    eprintln!("Not opening {}: new windows are disabled in safe mode", path.display());
+                } else if let Err(e) = open_new_window(path) {
+                    eprintln!("Failed to open a window for {}: {}", path.display(), e);
+                }
+            }
+            (state, Task::batch([Task::done(Message::CDToPath), Task::done(Message::CheckBookmarks)]))
+        })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csfm_core::test_util::ScratchDir;
+
+    #[test]
+    fn broken_config_falls_back_to_the_backup() {
+        let dir = ScratchDir::new("recover");
+        let path = dir.join("csfm.toml");
+        write_atomic(&path, b"show_hidden_files = true\n").unwrap();
+        backup_config(&path).unwrap();
+        fs::write(&path, "").unwrap();
+
+        let (config, issues) = recover_config(&path, "").expect("the backup is used");
+
+        assert!(config.show_hidden_files);
+        assert!(issues[0].found.contains("using"));
+        assert!(recover_config(&path, "show_hidden_files = false\n").is_none());
+    }
+
+    #[test]
+    fn broken_config_doesnt_replace_the_backup() {
+        let dir = ScratchDir::new("broken-backup");
+        let path = dir.join("csfm.toml");
+        fs::write(&path, "show_hidden_files = true\n").unwrap();
+        backup_config(&path).unwrap();
+        fs::write(&path, "show_hidden_files = [").unwrap();
+        backup_config(&path).unwrap();
+
+        assert_eq!(fs::read_to_string(config_backup_path(&path)).unwrap(), "show_hidden_files = true\n");
+    }
+
+    #[test]
+    fn welcome_shows_until_finished_or_configured() {
+        let dir = ScratchDir::new("welcome");
+        let config = dir.join("csfm.toml");
+        let done = dir.join("csfm_welcomed");
+        fs::write(dir.join("csfm_path_history"), "/tmp").unwrap();
+        assert!(needs_welcome(false, &config, &done));
+
+        fs::write(&done, "").unwrap();
+        assert!(!needs_welcome(false, &config, &done));
+        assert!(needs_welcome(true, &config, &done));
+
+        fs::remove_file(&done).unwrap();
+        fs::write(&config, "").unwrap();
+        assert!(!needs_welcome(false, &config, &done));
+    }
+
+    #[test]
+    fn migration_keeps_the_original_apart_from_the_save_backup() {
+        let dir = ScratchDir::new("migration-backup");
+        let path = dir.join("csfm.toml");
+        fs::write(config_backup_path(&path), "show_hidden_files = true\n").unwrap();
+        let old = "sidebar_loc = [\"/tmp\"]\n";
+        fs::write(&path, old).unwrap();
+
+        write_migrated_config(&path, old);
+
+        assert_eq!(fs::read_to_string(config_migration_backup_path(&path)).unwrap(), old);
+        assert_eq!(fs::read_to_string(config_backup_path(&path)).unwrap(), "show_hidden_files = true\n");
+        assert!(fs::read_to_string(&path).unwrap().contains("config_version"));
+    }
+
+    #[test]
+    fn recent_changes_window_is_bounded() {
+        let (config, issues) = parse_config("config_version = 1\nrecent_changes_minutes = 9223372036854775807\n");
+        assert_eq!(config.recent_changes_minutes, None);
+        assert_eq!(issues[0].key, "recent_changes_minutes");
+
+        let (config, issues) = parse_config("config_version = 1\nrecent_changes_minutes = 60\n");
+        assert_eq!(config.recent_changes_minutes, Some(60));
+        assert!(issues.is_empty());
+    }
+
+    #[test]
+    fn large_file_threshold_is_bounded() {
+        let (config, issues) = parse_config("config_version = 1\nlarge_file_threshold = 9223372036854775807\n");
+        assert_eq!(config.large_file_threshold, None);
+        assert_eq!(issues[0].key, "large_file_threshold");
+    }
+
+    /// The xattr form of an ACL from its (tag, permissions, id) entries.
+    fn acl_blob(entries: &[(u16, u16, u32)]) -> Vec<u8> {
+        let mut blob = ACL_XATTR_VERSION.to_le_bytes().to_vec();
+        for &(tag, perm, id) in entries {
+            blob.extend(tag.to_le_bytes());
+            blob.extend(perm.to_le_bytes());
+            blob.extend(id.to_le_bytes());
+        }
+        blob
+    }
+
+    #[test]
+    fn parses_acl_xattrs() {
+        // user::rw- user:1000:r-- group::r-x mask::r-- other::---
+        let blob = acl_blob(&[(0x01, 6, u32::MAX), (0x02, 4, 1000), (0x04, 5, u32::MAX), (0x10, 4, u32::MAX), (0x20, 0, u32::MAX)]);
+
+        let acl = parse_acl_xattr(&blob).unwrap();
+
+        assert_eq!(acl, vec![
+            AclEntry { tag: AclTag::UserObj, perm: 6 },
+            AclEntry { tag: AclTag::User(1000), perm: 4 },
+            AclEntry { tag: AclTag::GroupObj, perm: 5 },
+            AclEntry { tag: AclTag::Mask, perm: 4 },
+            AclEntry { tag: AclTag::Other, perm: 0 },
+        ]);
+    }
+
+    #[test]
+    fn rejects_malformed_acl_xattrs() {
+        let good = acl_blob(&[(0x01, 6, u32::MAX), (0x20, 0, u32::MAX)]);
+
+        let mut other_version = good.clone();
+        other_version[0] = 1;
+        assert_eq!(parse_acl_xattr(&other_version), None);
+        assert_eq!(parse_acl_xattr(&good[..good.len() - 3]), None);
+        assert_eq!(parse_acl_xattr(&acl_blob(&[(0x40, 7, 0)])), None);
+        assert_eq!(parse_acl_xattr(&[]), None);
+        // Only the rwx bits count
+        assert_eq!(parse_acl_xattr(&acl_blob(&[(0x20, 0o17, 0)])), Some(vec![AclEntry { tag: AclTag::Other, perm: 7 }]));
+    }
+
+    #[test]
+    fn unknown_accounts_show_their_number() {
+        assert_eq!(account_name(Account::User, 0), "root");
+        assert_eq!(account_name(Account::User, 4_000_000_000), "4000000000");
+        assert_eq!(account_name(Account::Group, 4_000_000_000), "4000000000");
+    }
+
+    fn test_entry(path: &str, is_dir: bool) -> FileEntry {
+        let path = PathBuf::from(path);
+        FileEntry {
+            folded_name: folded_name(&path),
+            sort_keys: SortKeys::of(&path),
+            path,
+            is_dir,
+            leaves_tree: false,
+            is_symlink: false,
+            size: Some(0),
+            modified: None,
+            flags: InodeFlags::default(),
+        }
+    }
+
+    fn labels(actions: &[(impl AsRef<str>, Message)]) -> Vec<&str> {
+        actions.iter().map(|(label, _)| label.as_ref()).collect()
+    }
+
+    #[test]
+    fn menu_for_a_file() {
+        let state = CsFM::new(Config::default(), PathBuf::from("/home/u"));
+        let menu = applicable_actions(&state, &test_entry("/home/u/notes.txt", false));
+        let labels = labels(&menu);
+
+        for expected in ["Open", "Copy as data URI", "Rename", "Copy", "Cut", "Copy to…", "Move to…", "Move to trash", "Delete permanently", "Properties"] {
+            assert!(labels.contains(&expected), "{} is missing from {:?}", expected, labels);
+        }
+        for unexpected in ["Open in new window", "Add to bookmarks", "Go to link target", "Restore", "Open containing folder", "Open in editor"] {
+            assert!(!labels.contains(&unexpected), "{} is offered in {:?}", unexpected, labels);
+        }
+    }
+
+    #[test]
+    fn menu_for_a_folder_and_a_link() {
+        let state = CsFM::new(Config::default(), PathBuf::from("/home/u"));
+        let labels_for = |entry: &FileEntry| applicable_actions(&state, entry).into_iter().map(|(label, _)| label).collect::<Vec<_>>();
+
+        let folder = labels_for(&test_entry("/home/u/src", true));
+        assert!(folder.contains(&"Open in new window") && folder.contains(&"Add to bookmarks"));
+        assert!(!folder.contains(&"Copy as data URI"));
+
+        let link = labels_for(&FileEntry { is_symlink: true, ..test_entry("/home/u/latest", true) });
+        assert!(link.contains(&"Go to link target") && link.contains(&"Go to final link target"));
+    }
+
+    #[test]
+    fn menu_in_the_trash_and_in_search_results() {
+        let state = CsFM::new(Config::default(), trash::files_dir());
+        let trashed = applicable_actions(&state, &test_entry(&trash::files_dir().join("old.txt").to_string_lossy(), false));
+        assert!(labels(&trashed).contains(&"Restore"));
+        assert!(!labels(&trashed).contains(&"Move to trash"));
+
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/home/u"));
+        state.walk_view = Some(WalkView::Search("notes".to_string()));
+        let found = applicable_actions(&state, &test_entry("/home/u/a/notes.txt", false));
+        assert!(labels(&found).contains(&"Open containing folder"));
+        assert!(!labels(&found).contains(&"Rename"));
+    }
+
+    #[test]
+    fn menu_for_a_selection() {
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/home/u"));
+        state.current_files = vec![test_entry("/home/u/a.txt", false), test_entry("/home/u/b", true)];
+        state.selected = HashSet::from([PathBuf::from("/home/u/a.txt"), PathBuf::from("/home/u/b")]);
+
+        let menu = selection_actions(&state);
+
+        assert_eq!(labels(&menu), vec![
+            "Copy 2 items",
+            "Cut 2 items",
+            "Copy 2 items to…",
+            "Move 2 items to…",
+            "Move 2 items to trash",
+            "Delete 2 items permanently",
+        ]);
+        let Some((_, Message::ChooseTransferTarget(Transfer::Move, sources))) = menu.iter().find(|(label, _)| label == "Move 2 items to…") else {
+            panic!("Move to… doesn't choose a target");
+        };
+        assert_eq!(sources, &vec![PathBuf::from("/home/u/a.txt"), PathBuf::from("/home/u/b")]);
+
+        // Trashing is only offered when it applies to every selected entry
+        let trashed = trash::files_dir().join("old.txt");
+        state.current_files.push(test_entry(&trashed.to_string_lossy(), false));
+        state.selected.insert(trashed);
+        assert!(!labels(&selection_actions(&state)).contains(&"Move 3 items to trash"));
+    }
+
+    #[test]
+    fn background_menu_follows_the_clipboard() {
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/home/u"));
+        let paste = |state: &CsFM| background_actions(state).into_iter().find(|(label, _)| label.starts_with("Paste")).unwrap();
+
+        let (label, message) = paste(&state);
+        assert_eq!(label, "Paste");
+        assert!(message.is_none());
+
+        state.clipboard = Some((vec![PathBuf::from("/tmp/report.pdf")], Transfer::Copy));
+        let (label, message) = paste(&state);
+        assert_eq!(label, "Paste report.pdf");
+        assert!(matches!(message, Some(Message::Paste)));
+
+        state.walk_view = Some(WalkView::Search("x".to_string()));
+        assert!(background_actions(&state).iter().all(|(_, message)| message.is_none()));
+    }
+
+    #[test]
+    fn shortcuts_are_unique() {
+        let actions = actions();
+        let shortcuts: Vec<&Shortcut> = actions.iter().filter_map(|a| a.shortcut.as_ref()).collect();
+
+        for (i, a) in shortcuts.iter().enumerate() {
+            assert!(!shortcuts[i + 1..].iter().any(|b| a.key == b.key && a.modifiers == b.modifiers), "{} is bound twice", a.chord());
+        }
+    }
+
+    #[test]
+    fn history_arrows_only_act_on_an_open_dropdown() {
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/home/u"));
+        state.path_history = vec![PathBuf::from("/tmp")];
+        let actions = actions();
+        let down = keyboard::Key::Named(keyboard::key::Named::ArrowDown);
+        let label = |state: &CsFM, modifiers| pressed_action(&actions, state, down.as_ref(), modifiers, false).map(|a| a.label);
+
+        assert_eq!(label(&state, keyboard::Modifiers::empty()), None);
+        assert_eq!(label(&state, keyboard::Modifiers::ALT), Some("Show path history"));
+        state.history_open = true;
+        assert_eq!(label(&state, keyboard::Modifiers::empty()), Some("Next history entry"));
+    }
+
+    #[test]
+    fn entry_shortcuts_act_on_the_selection() {
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/home/u"));
+        state.current_files = vec![test_entry("/home/u/a.txt", false), test_entry("/home/u/b.txt", false)];
+        let actions = actions();
+        let trash = actions.iter().find(|a| a.label == "Move to trash").unwrap();
+        let rename = actions.iter().find(|a| a.label == "Rename").unwrap();
+        let id = Id::unique();
+
+        assert!(shortcut_message(&state, trash, id).is_none());
+
+        state.selected = HashSet::from([PathBuf::from("/home/u/a.txt")]);
+        assert!(matches!(shortcut_message(&state, trash, id), Some(Message::Trash(p)) if p == Path::new("/home/u/a.txt")));
+
+        state.selected.insert(PathBuf::from("/home/u/b.txt"));
+        assert!(matches!(shortcut_message(&state, trash, id), Some(Message::TrashSelection)));
+        assert!(shortcut_message(&state, rename, id).is_none());
+    }
+
+    fn sorted_names(names: &[&str]) -> Vec<String> {
+        let mut files: Vec<FileEntry> = names.iter().map(|name| test_entry(name, false)).collect();
+        sort_by_name(&mut files);
+        files.iter().map(|f| f.path.to_string_lossy().into_owned()).collect()
+    }
+
+    fn read_link(p: &Path) -> Option<PathBuf> {
+        fs::read_link(p).ok()
+    }
+
+    #[test]
+    fn resolves_relative_and_absolute_links() {
+        let dir = ScratchDir::new("links");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/file"), "").unwrap();
+        std::os::unix::fs::symlink("../file", dir.join("a/b/up")).unwrap();
+        std::os::unix::fs::symlink("./b/./up", dir.join("a/chain")).unwrap();
+        std::os::unix::fs::symlink(dir.join("a/chain"), dir.join("absolute")).unwrap();
+
+        assert_eq!(resolve_link(&dir.join("a/b/up"), false, read_link), Ok(dir.join("a/file")));
+        assert_eq!(resolve_link(&dir.join("a/chain"), false, read_link), Ok(dir.join("a/b/up")));
+        assert_eq!(resolve_link(&dir.join("absolute"), false, read_link), Ok(dir.join("a/chain")));
+        assert_eq!(resolve_link(&dir.join("absolute"), true, read_link), Ok(dir.join("a/file")));
+        assert_eq!(resolve_link(&dir.join("a/file"), true, read_link), Err(LinkError::NotALink));
+    }
+
+    #[test]
+    fn resolves_dangling_links_to_the_missing_target() {
+        let dir = ScratchDir::new("dangling");
+        std::os::unix::fs::symlink("gone/../missing", dir.join("dangling")).unwrap();
+        std::os::unix::fs::symlink("dangling", dir.join("to-dangling")).unwrap();
+
+        assert_eq!(resolve_link(&dir.join("dangling"), true, read_link), Ok(dir.join("missing")));
+        assert_eq!(resolve_link(&dir.join("to-dangling"), true, read_link), Ok(dir.join("missing")));
+    }
+
+    #[test]
+    fn stops_on_link_loops() {
+        let dir = ScratchDir::new("loops");
+        std::os::unix::fs::symlink("self", dir.join("self")).unwrap();
+        std::os::unix::fs::symlink("pong", dir.join("ping")).unwrap();
+        std::os::unix::fs::symlink("ping", dir.join("pong")).unwrap();
+
+        assert_eq!(resolve_link(&dir.join("self"), true, read_link), Err(LinkError::TooManyHops));
+        assert_eq!(resolve_link(&dir.join("ping"), true, read_link), Err(LinkError::TooManyHops));
+        // One hop is fine even inside a loop
+        assert_eq!(resolve_link(&dir.join("ping"), false, read_link), Ok(dir.join("pong")));
+    }
+
+    #[test]
+    fn follows_long_chains_up_to_the_limit() {
+        // link0 -> link1 -> ... -> linkN, where linkN isn't a link
+        let chain = |length: usize| {
+            move |p: &Path| {
+                let n: usize = p.file_name()?.to_str()?.strip_prefix("link")?.parse().ok()?;
+                (n < length).then(|| PathBuf::from(format!("link{}", n + 1)))
+            }
+        };
+
+        assert_eq!(resolve_link(Path::new("/d/link0"), true, chain(MAX_LINK_HOPS)), Ok(PathBuf::from(format!("/d/link{}", MAX_LINK_HOPS))));
+        assert_eq!(resolve_link(Path::new("/d/link0"), true, chain(MAX_LINK_HOPS + 1)), Err(LinkError::TooManyHops));
+    }
+
+    fn paths(entries: &[&str]) -> Vec<FileEntry> {
+        entries.iter().map(|name| test_entry(&format!("/d/{}", name), false)).collect()
+    }
+
+    fn reconciled(old: &[&str], new: &[&str], selected: &[&str], anchor: Option<&str>) -> (Vec<String>, Option<String>) {
+        let mut selected: HashSet<PathBuf> = selected.iter().map(|name| PathBuf::from(format!("/d/{}", name))).collect();
+        let mut anchor = anchor.map(|name| PathBuf::from(format!("/d/{}", name)));
+        reconcile_selection(&paths(old), &paths(new), &mut selected, &mut anchor);
+
+        let name = |p: &PathBuf| p.file_name().unwrap().to_string_lossy().into_owned();
+        let mut selected: Vec<String> = selected.iter().map(name).collect();
+        selected.sort();
+        (selected, anchor.as_ref().map(name))
+    }
+
+    #[test]
+    fn reload_keeps_the_surviving_selection() {
+        let (selected, anchor) = reconciled(&["a", "b", "c"], &["a", "c", "d"], &["a", "b", "c"], Some("c"));
+        assert_eq!(selected, ["a", "c"]);
+        assert_eq!(anchor.as_deref(), Some("c"));
+
+        // Entries that moved in the order stay selected
+        let (selected, _) = reconciled(&["a"], &["b", "a"], &["a"], None);
+        assert_eq!(selected, ["a"]);
+    }
+
+    #[test]
+    fn reload_moves_a_vanished_anchor_to_its_neighbour() {
+        // The next entry first, then the previous one
+        assert_eq!(reconciled(&["a", "b", "c"], &["a", "c"], &[], Some("b")).1.as_deref(), Some("c"));
+        assert_eq!(reconciled(&["a", "b", "c"], &["a"], &[], Some("b")).1.as_deref(), Some("a"));
+        assert_eq!(reconciled(&["a", "b", "c", "d", "e"], &["a", "e"], &[], Some("c")).1.as_deref(), Some("e"));
+        assert_eq!(reconciled(&["a", "b", "c", "d", "e"], &["a", "x"], &[], Some("d")).1.as_deref(), Some("a"));
+        // Nothing left, or the anchor wasn't listed before
+        assert_eq!(reconciled(&["a", "b"], &["x"], &["a"], Some("a")), (vec![], None));
+        assert_eq!(reconciled(&["a"], &["a"], &[], Some("z")).1, None);
+    }
+
+    #[test]
+    fn reload_scrolls_only_when_the_anchor_left_the_view() {
+        // Rows 25 to 49 of 100 are in view
+        let view = ListView { start: 0.25, visible: 0.25 };
+
+        assert_eq!(scroll_into_view(25, 100, view), None);
+        assert_eq!(scroll_into_view(49, 100, view), None);
+        assert_eq!(scroll_into_view(24, 100, view), Some(24.0 / 99.0));
+        assert_eq!(scroll_into_view(50, 100, view), Some(50.0 / 99.0));
+        assert_eq!(scroll_into_view(0, 1, ListView { start: 0.0, visible: 1.0 }), None);
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_match("*.pdf", "report.pdf"));
+        assert!(glob_match("img_??.*", "img_07.jpg"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("*.pdf", "report.pdf.bak"));
+        assert!(!glob_match("img_??.*", "img_7.jpg"));
+        assert!(!glob_match("", "a"));
+    }
+
+    #[test]
+    fn smart_rules_match_entries() {
+        let dir = ScratchDir::new("smart-rules");
+        fs::write(dir.join("Report.PDF"), vec![0; 2048]).unwrap();
+        fs::write(dir.join("notes.txt"), "short").unwrap();
+        let (pdf, txt) = (dir.join("Report.PDF"), dir.join("notes.txt"));
+        let matches = |rule: &SmartRule, path: &Path| rule.matches(path, &fs::metadata(path).unwrap());
+
+        assert!(matches(&SmartRule::default(), &txt));
+        let by_extension = SmartRule { extensions: vec![".pdf".into(), "doc".into()], ..SmartRule::default() };
+        assert!(matches(&by_extension, &pdf));
+        assert!(!matches(&by_extension, &txt));
+
+        let by_name = SmartRule { name_glob: Some("report*".into()), ..SmartRule::default() };
+        assert!(matches(&by_name, &pdf));
+        assert!(!matches(&by_name, &txt));
+
+        let by_size = SmartRule { min_size: Some(1024), max_size: Some(4096), ..SmartRule::default() };
+        assert!(matches(&by_size, &pdf));
+        assert!(!matches(&by_size, &txt));
+
+        let month_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(31 * 24 * 60 * 60);
+        fs::File::options().write(true).open(&pdf).unwrap().set_modified(month_ago).unwrap();
+        let recent = SmartRule { modified_within_days: Some(30), ..SmartRule::default() };
+        assert!(matches(&recent, &txt));
+        assert!(!matches(&recent, &pdf));
+    }
+
+    #[test]
+    fn smart_folders_round_trip_through_the_config() {
+        let data = "config_version = 1\n\n[[smart_folder]]\ntitle = \"Recent PDFs\"\nroot = \"~/Documents\"\nextensions = [\"pdf\"]\nmodified_within_days = 30\n";
+        let (config, issues) = parse_config(data);
+        assert!(issues.is_empty(), "{:?}", issues.iter().map(|i| &i.found).collect::<Vec<_>>());
+
+        let folder = &config.smart_folder[0];
+        assert_eq!((folder.title.as_str(), folder.root.as_str()), ("Recent PDFs", "~/Documents"));
+        assert_eq!(folder.rule, SmartRule { extensions: vec!["pdf".into()], modified_within_days: Some(30), ..SmartRule::default() });
+
+        // Unset conditions stay out of the saved file
+        let saved = toml::to_string(&config).unwrap();
+        assert!(saved.contains("modified_within_days = 30") && !saved.contains("min_size"));
+        assert_eq!(parse_config(&saved).0.smart_folder[0].rule, folder.rule);
+    }
+
+    #[test]
+    fn smart_folders_gather_matching_files() {
+        let dir = ScratchDir::new("smart-folder");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::create_dir(dir.join("c.pdf")).unwrap();
+        for file in ["x.pdf", "a/y.PDF", "a/b/z.pdf", "a/b/notes.txt", ".hidden.pdf"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let rule = SmartRule { extensions: vec!["pdf".into()], ..SmartRule::default() };
+        let options = WalkOptions { max_depth: 8, follow_symlinks: false, show_hidden_files: false };
+
+        let (files, summary) = run_smart_folder(dir.to_path_buf(), rule, options);
+        let found: Vec<PathBuf> = files.iter().map(|f| f.path.strip_prefix(&dir).unwrap().to_path_buf()).collect();
+        // Folders never match, and hidden files only when shown
+        assert_eq!(found, [PathBuf::from("a/b/z.pdf"), PathBuf::from("a/y.PDF"), PathBuf::from("x.pdf")]);
+        assert!(!summary.stopped);
+    }
+
+    #[test]
+    fn quitting_without_a_window_id_doesnt_panic() {
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/"));
+        let _ = update(&mut state, Message::QuitApp(None));
+        let _ = update(&mut state, Message::QuitApp(Some(Id::unique())));
+
+        let actions = actions();
+        let quit = actions.iter().find(|a| a.label == "Quit").unwrap();
+        assert!(quit.shortcut.as_ref().is_some_and(|s| s.matches(keyboard::Key::Character("q"), keyboard::Modifiers::CTRL)));
+        let id = Id::unique();
+        assert!(matches!(shortcut_message(&state, quit, id), Some(Message::QuitApp(Some(quit_id))) if quit_id == id));
+    }
+
+    #[test]
+    fn stale_listings_are_dropped() {
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/home/u"));
+        state.loading = true;
+        let listing = || Ok(Listing { files: vec![test_entry("/home/u/.profile", false)], hidden: 0, order: None });
+
+        // Read before hidden files were toggled
+        let _ = update(&mut state, Message::FilesLoaded(PathBuf::from("/home/u"), true, listing()));
+        let _ = update(&mut state, Message::FilesLoaded(PathBuf::from("/tmp"), false, listing()));
+        assert!(state.loading);
+        assert!(state.current_files.is_empty());
+
+        let _ = update(&mut state, Message::FilesLoaded(PathBuf::from("/home/u"), false, listing()));
+        assert!(!state.loading);
+        assert_eq!(state.current_files.len(), 1);
+    }
+
+    #[test]
+    fn invalid_settings_fall_back_one_by_one() {
+        let data = "config_version = 1\nshow_hidden_files = \"yes\"\nsort_mode = \"colour\"\nmix_folders = true\ntheme = \"Nope\"\nsidebar = 3\nunknown_key = 1\n";
+        let (config, issues) = parse_config(data);
+
+        // The valid setting survives its invalid neighbours
+        assert!(config.mix_folders);
+        assert!(!config.show_hidden_files);
+        assert_eq!(config.sort_mode, SortMode::default());
+        assert!(config.theme.is_empty());
+
+        let keys: Vec<&str> = issues.iter().map(|i| i.key.as_str()).collect();
+        assert_eq!(keys, ["theme", "show_hidden_files", "sidebar", "sort_mode", "unknown_key"]);
+        let hidden = &issues[1];
+        assert_eq!((hidden.found.as_str(), hidden.expected.as_str()), ("\"yes\"", "true or false"));
+        assert_eq!(hidden.location, Some((2, 1)));
+        assert_eq!(issues[4].location, Some((7, 1)));
+    }
+
+    #[test]
+    fn broken_toml_is_reported_with_its_position() {
+        let (config, issues) = parse_config("config_version = 1\nshow_hidden_files = tru\n");
+
+        assert!(!config.show_hidden_files);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::Syntax);
+        assert_eq!(issues[0].location.map(|(line, _)| line), Some(2));
+        assert!(issues[0].to_string().starts_with("line 2, column "));
+    }
+
+    #[test]
+    fn notices_with_a_position_are_not_syntax_errors() {
+        let (config, issues) = parse_config("config_version = 1\nnew_file_mode = \"4755\"\n");
+
+        // The mode is used, the note only points at its line
+        assert_eq!(config.new_file_mode.as_deref(), Some("4755"));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::Notice);
+        assert_eq!(issues[0].location.map(|(line, _)| line), Some(2));
+        assert!(!issues.iter().any(|i| i.kind == IssueKind::Syntax));
+    }
+
+    #[test]
+    fn migrates_sidebar_loc_into_the_sidebar_table() {
+        let old = "theme = \"Dark\"\n\n[[sidebar_loc]]\ntitle = \"Music\"\npath = \"/home/u/Music\"\n";
+        let mut table: toml::Table = old.parse().unwrap();
+
+        let notices = migrate_config(&mut table);
+        assert_eq!(notices, ["Updated config from version 0 to 1"]);
+        assert!(table.get("sidebar_loc").is_none());
+        assert_eq!(table["config_version"].as_integer(), Some(1));
+        assert_eq!(table["sidebar"]["bookmarks"][0]["path"].as_str(), Some("/home/u/Music"));
+
+        // Already current, nothing to do
+        assert!(migrate_config(&mut table).is_empty());
+
+        let (config, issues) = parse_config(old);
+        assert_eq!(config.sidebar.bookmarks[0].title, "Music");
+        assert_eq!(issues.len(), 1, "only the migration notice");
+    }
+
+    #[test]
+    fn migration_keeps_bookmarks_already_in_the_sidebar() {
+        let mut table: toml::Table = "[[sidebar_loc]]\ntitle = \"Old\"\npath = \"/old\"\n\n[sidebar]\nbookmarks = [{ title = \"New\", path = \"/new\" }]\n"
+            .parse()
+            .unwrap();
+        migrate_config(&mut table);
+        assert_eq!(table["sidebar"]["bookmarks"][0]["path"].as_str(), Some("/new"));
+    }
+
+    #[test]
+    fn newer_configs_load_what_they_can() {
+        let (config, issues) = parse_config("config_version = 99\nshow_hidden_files = true\n");
+        assert!(config.show_hidden_files);
+        assert!(issues[0].found.contains("newer"));
+    }
+
+    fn location(title: &str, path: &str) -> Location {
+        Location { title: title.to_string(), path: path.to_string(), from_gtk: false, hidden: None }
+    }
+
+    #[test]
+    fn bookmark_exports_round_trip() {
+        let bookmarks = vec![
+            location("Music", "/home/u/Music"),
+            Location { hidden: Some(true), ..location("Dotfiles \"and\" more", "/home/u/.config") },
+            location("Unmounted", "/mnt/nfs/share"),
+        ];
+
+        let imported = import_bookmarks(&export_bookmarks(&bookmarks).unwrap()).unwrap();
+        assert_eq!(imported.len(), 3);
+        for (a, b) in bookmarks.iter().zip(&imported) {
+            assert_eq!((&a.title, &a.path, a.hidden), (&b.title, &b.path, b.hidden));
+        }
+        assert!(import_bookmarks("bookmarks = 3").is_err());
+        assert!(import_bookmarks("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn importing_bookmarks_merges_by_path() {
+        let mut bookmarks = vec![location("Music", "/m"), location("Docs", "/d")];
+        let imported = vec![location("Other music", "/m"), location("Nowhere", "/missing/here"), location("", "/untitled"), location("Docs", "/d")];
+
+        let (added, removed) = merge_bookmarks(&mut bookmarks, imported.clone(), false);
+        assert_eq!(added.iter().map(|l| l.path.as_str()).collect::<Vec<_>>(), ["/missing/here"]);
+        assert!(removed.is_empty());
+        assert_eq!(bookmarks.iter().map(|l| l.title.as_str()).collect::<Vec<_>>(), ["Music", "Docs", "Nowhere"]);
+
+        let (_, removed) = merge_bookmarks(&mut bookmarks, imported, true);
+        // Every path came back with the import, so none counts as removed
+        assert!(removed.is_empty());
+        assert_eq!(bookmarks.iter().map(|l| l.title.as_str()).collect::<Vec<_>>(), ["Other music", "Nowhere", "Docs"]);
+
+        let mut bookmarks = vec![location("Old", "/old")];
+        assert_eq!(merge_bookmarks(&mut bookmarks, vec![location("New", "/new")], true).1, ["/old"]);
+    }
+
+    #[test]
+    fn visually_identical_names_sort_and_filter_alike() {
+        let (nfc, nfd) = ("caf\u{e9}.txt", "cafe\u{301}.txt");
+        assert_eq!(folded_name(Path::new(nfd)), folded_name(Path::new(nfc)));
+
+        // Equal after normalization, the bytes keep the order the same on every refresh
+        let once = sorted_names(&[nfd, nfc, "cafe.txt", "caf\u{e9} 🎉.txt"]);
+        let again = sorted_names(&[nfc, "caf\u{e9} 🎉.txt", "cafe.txt", nfd]);
+        assert_eq!(once, again);
+        assert_eq!(once, ["cafe.txt", "caf\u{e9} 🎉.txt", nfd, nfc]);
+
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/d"));
+        state.current_files = [nfc, nfd, "a\u{308}rger.txt", "party 🎉.txt"].iter().map(|n| test_entry(&format!("/d/{}", n), false)).collect();
+        let shown = |state: &CsFM| shown_files(state).len();
+        state.filter = "CAFÉ".to_string();
+        assert_eq!(shown(&state), 2);
+        state.filter = "\u{e4}rger".to_string();
+        assert_eq!(shown(&state), 1);
+        state.filter = "🎉".to_string();
+        assert_eq!(shown(&state), 1);
+    }
+
+    #[test]
+    fn non_utf8_names_keep_their_bytes() {
+        let dir = ScratchDir::new("non-utf8");
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        fs::write(dir.join(name), "latin-1").unwrap();
+
+        let listing = get_files(dir.to_path_buf(), false).unwrap();
+        assert_eq!(listing.files.len(), 1);
+        assert_eq!(listing.files[0].path.file_name(), Some(name));
+
+        // Neither the config nor GTK bookmarks can store them
+        assert!(parse_gtk_bookmark("file:///tmp/caf%E9").is_none());
+        assert_eq!(escaped_name(name), "caf\\xe9.txt");
+    }
+
+    #[test]
+    fn sanitizes_nasty_names() {
+        let cases = [
+            ("plain.txt", "plain.txt", false),
+            ("two\nlines", "two␊lines", false),
+            ("tab\there", "tab␉here", false),
+            ("\x1b[31mred", "␛[31mred", false),
+            ("del\x7f", "del␡", false),
+            ("c1\u{85}", "c1\\x85", false),
+            ("evil\u{202e}txt.exe", "eviltxt.exe", true),
+            ("\u{2066}isolated\u{2069}", "isolated", true),
+            ("mark\u{200f}", "mark", true),
+            ("שלום.txt", "שלום.txt", false),
+            ("🎉", "🎉", false),
+        ];
+        for (name, shown, bidi) in cases {
+            assert_eq!(sanitize_name(name), (shown.to_string(), bidi), "{:?}", name);
+        }
+
+        let state = CsFM::new(Config::default(), PathBuf::from("/d"));
+        assert_eq!(display_name(&state, Path::new("/d/evil\u{202e}txt.exe")), "⚠ eviltxt.exe");
+        assert_eq!(escaped_name(OsStr::new("a\nb\\")), "a\\x0ab\\\\");
+    }
+
+    #[test]
+    fn full_paths_follow_their_style() {
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/d"));
+        let nested = Path::new("/d/photos/a.jpg");
+        assert_eq!(display_name(&state, nested), "a.jpg");
+
+        // Search results are relative to where the search started, unless asked otherwise
+        state.walk_view = Some(WalkView::Search("a".to_string()));
+        assert_eq!(display_name(&state, nested), "photos/a.jpg");
+        state.config.full_path_style = PathStyle::Absolute;
+        assert_eq!(display_name(&state, nested), "/d/photos/a.jpg");
+
+        // The setting applies to the tree view and plain listings too
+        state.walk_view = None;
+        state.config.show_full_paths = true;
+        assert_eq!(display_name(&state, nested), "/d/photos/a.jpg");
+        state.config.full_path_style = PathStyle::Relative;
+        assert_eq!(display_name(&state, nested), "photos/a.jpg");
+        assert_eq!(display_name(&state, Path::new("/elsewhere/b")), "/elsewhere/b");
+
+        let (config, issues) = parse_config("config_version = 1\nfull_path_style = \"absolute\"\n");
+        assert_eq!(config.full_path_style, PathStyle::Absolute);
+        assert!(issues.is_empty());
+    }
+
+    fn walk_options(max_depth: usize, follow_symlinks: bool) -> WalkOptions {
+        WalkOptions { max_depth, follow_symlinks, show_hidden_files: true }
+    }
+
+    #[test]
+    fn walks_mark_where_the_depth_limit_cut_them() {
+        let dir = ScratchDir::new("walk-depth");
+        fs::create_dir_all(dir.join("1/2/3/4")).unwrap();
+
+        let mut seen = vec![];
+        let summary = walk(&dir, &walk_options(1, false), |p, _| {
+            seen.push(p.strip_prefix(&dir).unwrap().to_path_buf());
+            true
+        });
+        assert_eq!(seen, [PathBuf::from("1"), PathBuf::from("1/2")]);
+        assert_eq!(summary.depth_limited, [dir.join("1/2")]);
+        assert!(walk_notice(&summary).unwrap().contains("depth limit reached in 1 directories"));
+
+        // Stopping early is reported too
+        let summary = walk(&dir, &walk_options(32, false), |_, _| false);
+        assert!(summary.stopped);
+    }
+
+    #[test]
+    fn creating_an_item_tells_its_permissions() {
+        let dir = ScratchDir::new("create-toast");
+        let config = Config { inline_create: true, new_dir_mode: Some("750".to_string()), ..Default::default() };
+        let mut state = CsFM::new(config, dir.to_path_buf());
+
+        let _ = update(&mut state, Message::NewDir);
+        assert!(dir.join("New Folder").is_dir());
+        assert_eq!(state.toast.as_ref().map(|(note, _)| note.as_str()), Some("New folder created with permissions rwxr-x--- (750)"));
+        assert_eq!(mode_summary(0o102775), "rwxrwxr-x (2775)");
+
+        // Still shown until the time is up
+        let _ = update(&mut state, Message::ToastTick);
+        assert!(state.toast.is_some());
+        state.toast.as_mut().unwrap().1 -= TOAST_DURATION;
+        let _ = update(&mut state, Message::ToastTick);
+        assert!(state.toast.is_none());
+    }
+
+    #[test]
+    fn network_locations_open_local_folders_only() {
+        let local = |uri: &str| match resolve_network_uri(uri) {
+            NetworkTarget::Local(path) => Some(path),
+            NetworkTarget::Unavailable(_) => None,
+        };
+        assert_eq!(local("file:///home/me/My%20Files"), Some(PathBuf::from("/home/me/My Files")));
+        assert_eq!(local("FILE://localhost/srv/a%2Bb"), Some(PathBuf::from("/srv/a+b")));
+        assert_eq!(local("/mnt/nas"), Some(PathBuf::from("/mnt/nas")));
+        assert_eq!(local("file://host/path"), None);
+        assert_eq!(local("sftp://host/path"), None);
+
+        let dir = ScratchDir::new("network-location");
+        let mut config = Config::default();
+        config.network_location.push(NetworkLocation { title: "NAS".to_string(), uri: format!("file://{}", dir.display()) });
+        let mut state = CsFM::new(config, PathBuf::from("/"));
+
+        let _ = update(&mut state, Message::OpenNetworkLocation(0));
+        assert_eq!(state.connecting.as_ref(), Some(&state.config.network_location[0].uri));
+        let uri = state.config.network_location[0].uri.clone();
+        let _ = update(&mut state, Message::NetworkLocationOpened(uri.clone(), Err("Couldn't open NAS: gone".to_string())));
+        assert_eq!(state.connecting, None);
+        assert_eq!(state.toast.as_ref().map(|(note, _)| note.as_str()), Some("Couldn't open NAS: gone"));
+
+        // A late answer to an attempt nobody waits for any more changes nothing
+        state.toast = None;
+        let _ = update(&mut state, Message::NetworkLocationOpened(uri, Ok(dir.to_path_buf())));
+        assert!(state.toast.is_none());
+    }
+
+    #[test]
+    fn transfers_wait_for_space_until_resumed_or_cancelled() {
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/b"));
+        let pairs = vec![(PathBuf::from("/a/1"), PathBuf::from("/b/1")), (PathBuf::from("/a/2"), PathBuf::from("/b/2"))];
+        state.transfers.push(RunningTransfer {
+            id: 7,
+            transfer: Transfer::Move,
+            sources: pairs.iter().map(|(source, _)| source.clone()).collect(),
+            dest: PathBuf::from("/b"),
+            stalled: vec![],
+        });
+        state.clipboard = Some((vec![PathBuf::from("/a/1")], Transfer::Move));
+        assert_eq!(transfers_banner(&state).as_deref(), Some("Moving 2 items here…"));
+
+        let _ = update(&mut state, Message::TransferDone(7, Outcome { errors: vec![], stalled: pairs.clone() }));
+        assert_eq!(state.transfers.len(), 1);
+        assert_eq!(state.transfers[0].stalled, pairs);
+        assert_eq!(transfers_banner(&state), None);
+        // Nothing moved yet, so the cut items stay cut
+        assert!(state.clipboard.is_some());
+
+        let _ = update(&mut state, Message::ResumeTransfer(7));
+        assert!(state.transfers[0].stalled.is_empty());
+        // Only a paused transfer can be cancelled
+        let _ = update(&mut state, Message::CancelTransfer(7));
+        assert_eq!(state.transfers.len(), 1);
+
+        let _ = update(&mut state, Message::TransferDone(7, Outcome { errors: vec![], stalled: pairs[1..].to_vec() }));
+        let _ = update(&mut state, Message::CancelTransfer(7));
+        assert!(state.transfers.is_empty());
+        assert_eq!(title(&state), "b — CsFM");
+    }
+
+    #[test]
+    fn title_names_the_folder_and_running_operations() {
+        let mut state = CsFM::new(Config::default(), PathBuf::from("/home/u/Documents"));
+        assert_eq!(title(&state), "Documents — CsFM");
+
+        let running = |id| RunningTransfer { id, transfer: Transfer::Copy, sources: vec![PathBuf::from("/a")], dest: PathBuf::from("/b"), stalled: vec![] };
+        state.transfers.push(running(0));
+        assert_eq!(title(&state), "Documents • 1 operation — CsFM");
+        state.transfers.push(running(1));
+        assert_eq!(title(&state), "Documents • 2 operations — CsFM");
+        state.transfers.clear();
+
+        state.path = PathBuf::from(OsStr::from_bytes(b"/tmp/caf\xe9\nx"));
+        assert_eq!(title(&state), "caf\u{fffd}␊x — CsFM");
+        state.path = PathBuf::from(format!("/{}", "é".repeat(200)));
+        let long = title(&state);
+        assert!(long.starts_with('…') && long.chars().count() == 80 + " — CsFM".chars().count());
+    }
+
+    #[test]
+    fn safe_mode_hides_what_starts_programs() {
+        let mut state = CsFM::new(Config { editor: Some("vim".to_string()), ..Config::default() }, PathBuf::from("/home/u"));
+        let (folder, file) = (test_entry("/home/u/dir", true), test_entry("/home/u/a.txt", false));
+        assert!(labels(&applicable_actions(&state, &folder)).contains(&"Open in new window"));
+        assert!(labels(&applicable_actions(&state, &file)).contains(&"Open in editor"));
+
+        state.config.safe_mode = true;
+        assert!(!labels(&applicable_actions(&state, &folder)).contains(&"Open in new window"));
+        let file_menu = applicable_actions(&state, &file);
+        for hidden in ["Open in editor", "Compare with clipboard"] {
+            assert!(!labels(&file_menu).contains(&hidden), "{} is still offered", hidden);
+        }
+    }
+
+    #[test]
+    fn chords_read_back_as_written() {
+        for action in actions() {
+            let Some(shortcut) = action.shortcut else {
+                continue;
+            };
+            assert_eq!(Shortcut::parse_chord(&shortcut.chord()), Some((shortcut.key.clone(), shortcut.modifiers)), "{}", shortcut.chord());
+        }
+
+        let ctrl = keyboard::Modifiers::CTRL;
+        assert_eq!(Shortcut::parse_chord("shift + ctrl + n"), Some((keyboard::Key::Character("n".into()), ctrl | keyboard::Modifiers::SHIFT)));
+        assert_eq!(Shortcut::parse_chord("Ctrl++"), Some((keyboard::Key::Character("+".into()), ctrl)));
+        assert_eq!(Shortcut::parse_chord("pagedown"), Some((keyboard::Key::Named(keyboard::key::Named::PageDown), keyboard::Modifiers::empty())));
+        for bad in ["", "Ctrl+", "Hyper+A", "Ctrl+Nope", "AB"] {
+            assert_eq!(Shortcut::parse_chord(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn keybindings_replace_the_defaults() {
+        let data = "config_version = 1\n[keybindings]\n\"Reload config\" = \"Ctrl+Shift+R\"\n\"New folder\" = \"\"\n\"New file…\" = \"Ctrl+R\"\n\"Refresh\" = \"R\"\n";
+        let (config, issues) = parse_config(data);
+        assert!(issues.is_empty(), "{:?}", issues.iter().map(ToString::to_string).collect::<Vec<_>>());
+
+        let actions = bound_actions(&config);
+        let shortcut = |label: &str| actions.iter().find(|a| a.label == label).unwrap().shortcut.clone();
+        assert_eq!(chord_for(&actions, "Reload config"), "Ctrl+Shift+R");
+        assert_eq!(shortcut("New folder…"), None);
+        assert_eq!(chord_for(&actions, "New file…"), "Ctrl+R");
+        // A bare letter doesn't fire while typing
+        assert!(shortcut("Refresh").unwrap().unfocused_only);
+        assert!(!shortcut("Reload config").unwrap().unfocused_only);
+        // Unchanged
+        assert_eq!(chord_for(&actions, "Quit"), "Ctrl+Q");
+
+        let state = CsFM::new(config, PathBuf::from("/"));
+        let pressed = |key: &str, modifiers| {
+            let actions = bound_actions(&state.config);
+            actions.iter().find(|a| a.shortcut.as_ref().is_some_and(|s| s.matches(keyboard::Key::Character(key), modifiers))).map(|a| a.label)
+        };
+        assert_eq!(pressed("r", keyboard::Modifiers::CTRL), Some("New file…"));
+        assert_eq!(pressed("n", keyboard::Modifiers::CTRL | keyboard::Modifiers::SHIFT), None);
+    }
+
+    #[test]
+    fn keybindings_taking_a_default_chord_unbind_its_action() {
+        let config = Config {
+            keybindings: [("Keyboard shortcuts".to_string(), "F5".to_string())].into(),
+            ..Config::default()
+        };
+        let actions = bound_actions(&config);
+
+        assert_eq!(chord_for(&actions, "Keyboard shortcuts"), "F5");
+        assert_eq!(chord_for(&actions, "Refresh"), "");
+    }
+
+    #[test]
+    fn invalid_keybindings_are_reported() {
+        let data = "config_version = 1\n[keybindings]\n\"Fly away\" = \"Ctrl+F\"\n\"Quit\" = \"Ctrl+Nope\"\n\"Refresh\" = 5\n\"Back\" = \"Ctrl+B\"\n\"Forward\" = \"ctrl+b\"\n";
+        let (config, issues) = parse_config(data);
+
+        let keys: Vec<&str> = issues.iter().map(|i| i.key.as_str()).collect();
+        assert_eq!(keys, ["keybindings.Fly away", "keybindings.Forward", "keybindings.Quit", "keybindings.Refresh"]);
+        assert!(issues[1].expected.contains("Back"));
+        assert_eq!(config.keybindings.keys().collect::<Vec<_>>(), ["Back"]);
+    }
+
+    #[test]
+    fn moved_gtk_bookmarks_keep_their_place() {
+        let gtk = "file:///home/u/Music Music\nfile:///home/u/Pictures\n";
+        let mut config = Config::default();
+        config.sidebar.bookmarks = vec![location("Docs", "/home/u/Docs")];
+        merge_gtk_bookmark_lines(&mut config.sidebar, gtk);
+        let titles = |config: &Config| config.sidebar.bookmarks.iter().map(|l| l.title.clone()).collect::<Vec<_>>();
+        assert_eq!(titles(&config), ["Docs", "Music", "Pictures"]);
+
+        // Pictures moved to the top, then the config is saved and read again
+        let pictures = config.sidebar.bookmarks.remove(2);
+        config.sidebar.bookmarks.insert(0, pictures);
+        let saved = config_file_contents(&config).unwrap();
+        assert!(!saved.contains("title = \"Music\""), "GTK bookmarks stay in their own file");
+        let (mut reloaded, issues) = parse_config(&saved);
+        assert!(issues.is_empty());
+        merge_gtk_bookmark_lines(&mut reloaded.sidebar, gtk);
+        assert_eq!(titles(&reloaded), ["Pictures", "Docs", "Music"]);
+
+        // Bookmarks added to the GTK file since go last
+        merge_gtk_bookmark_lines(&mut reloaded.sidebar, "file:///srv New\n");
+        assert_eq!(titles(&reloaded), ["Pictures", "Docs", "Music", "New"]);
+    }
+
+    #[test]
+    fn sidebar_order_is_only_saved_with_gtk_bookmarks() {
+        let mut config = Config::default();
+        config.sidebar.bookmarks = vec![location("Docs", "/home/u/Docs")];
+        assert!(!config_file_contents(&config).unwrap().contains("order"));
+    }
+}